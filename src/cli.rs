@@ -0,0 +1,458 @@
+// ヘッドレスモード（GUI を起動せずにコマンドラインからプレビュー/適用する）
+
+use crate::content_hash::hash_blocking;
+use crate::duplicates::find_duplicates_blocking;
+use crate::lookup::LookupTable;
+use crate::name_date::describe_date_formats;
+use crate::paths::sensitive_dir_reason;
+use crate::preview::{compile_rules, describe_excludes, describe_size_filter, load_files, update_preview};
+use crate::rename::{exceeds_batch_limit, execute_renames, plan_renames, ApplyMode};
+use crate::state::{AppState, LinkKind};
+use clap::Parser;
+use std::path::Path;
+use std::sync::Arc;
+
+/// コマンドライン引数。`--dir` を指定したときだけヘッドレスモードで動作する。
+#[derive(Parser, Debug)]
+#[command(name = "filename-change", version, about = "ファイル名一括変更")]
+pub struct CliArgs {
+    /// 対象フォルダ（指定するとGUIを起動せずに実行）
+    #[arg(long)]
+    pub dir: Option<String>,
+    /// GUI で開くフォルダ（ファイルならそのファイルのあるフォルダ。エクスプローラーの右クリックメニュー用）
+    #[arg(value_name = "PATH", conflicts_with = "dir")]
+    pub open: Option<String>,
+    /// 検索文字列
+    #[arg(long, default_value = "")]
+    pub search: String,
+    /// 置換文字列
+    #[arg(long, default_value = "")]
+    pub replace: String,
+    /// 接頭辞（拡張子の前の部分の先頭に付ける）
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+    /// 接尾辞（拡張子の前の部分の末尾に付ける）
+    #[arg(long, default_value = "")]
+    pub suffix: String,
+    /// 拡張子の前の部分に挿入する文字列
+    #[arg(long, default_value = "")]
+    pub insert_text: String,
+    /// 挿入位置（先頭からの文字数。負の値は末尾から数える）
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    pub insert_position: isize,
+    /// 拡張子の前の部分をこの区切りで 2 つに分けて前後を入れ替える（例 `", "`）
+    #[arg(long)]
+    pub swap: Option<String>,
+    /// 入れ替え後につなぐ文字列（省略時は区切りと同じ）
+    #[arg(long, default_value = "")]
+    pub swap_joiner: String,
+    /// 3 つ以上に分かれる名前も最初の区切りで分けて入れ替える
+    #[arg(long)]
+    pub swap_split_first: bool,
+    /// 名前に含まれるこの書式の日付を --date-to の書式に書き換える（例 `%Y%m%d`、日付のない名前は変更しない）
+    #[arg(long)]
+    pub date_from: Option<String>,
+    /// 日付の書き換え後の書式
+    #[arg(long, default_value = "%Y-%m-%d", requires = "date_from")]
+    pub date_to: String,
+    /// ファイルごとに評価して新しい名前の元にする式（例 `stem + "_" + pad(index, 3) + "." + ext`）
+    #[arg(long, allow_hyphen_values = true)]
+    pub script: Option<String>,
+    /// 置換表（1 行に「置換前,置換後」の CSV、またはタブ区切りの TSV）
+    #[arg(long)]
+    pub lookup_table: Option<String>,
+    /// 名前の最後の数字を並び順の連番に振り直す
+    #[arg(long)]
+    pub renumber: bool,
+    /// 振り直す番号の桁数（ゼロ埋め）
+    #[arg(long, default_value_t = 2)]
+    pub renumber_digits: usize,
+    /// 拡張子の前の部分全体を番号にする（--renumber と併用）
+    #[arg(long)]
+    pub renumber_whole: bool,
+    /// 名前の元の番号（末尾、なければ先頭の数字）を除く（番号は {num} で使える）
+    #[arg(long)]
+    pub strip_existing_number: bool,
+    /// 連番 {n} の開始番号
+    #[arg(long, default_value_t = 1)]
+    pub sequence_start: usize,
+    /// 連番 {n} を一覧の最後のファイルから振る
+    #[arg(long)]
+    pub reverse_numbering: bool,
+    /// 拡張子を小文字にそろえる
+    #[arg(long)]
+    pub lowercase_ext: bool,
+    /// 拡張子の別名を統一する（jpeg → jpg, htm → html など）
+    #[arg(long)]
+    pub normalize_ext: bool,
+    /// 区切り文字をそろえる（例 `space:underscore`。space / underscore / hyphen、空白は全角スペースも含む）
+    #[arg(long, value_name = "FROM:TO")]
+    pub separators: Option<String>,
+    /// 区切り文字の正規化で、連続した区切りをまとめない
+    #[arg(long)]
+    pub separator_keep_runs: bool,
+    /// 区切り文字の正規化で、先頭・末尾の区切りを残す
+    #[arg(long)]
+    pub separator_keep_edges: bool,
+    /// .tar.gz などを 1 つの拡張子として扱う
+    #[arg(long)]
+    pub compound_ext: bool,
+    /// " (1)", " copy", " のコピー" などの重複風の接尾辞を除去する
+    #[arg(long)]
+    pub strip_copy_suffixes: bool,
+    /// 検索を正規表現として扱う
+    #[arg(long)]
+    pub regex: bool,
+    /// 大文字小文字を区別する
+    #[arg(long)]
+    pub case_sensitive: bool,
+    /// 対象のファイルシステムが大小無視か（true/false、省略時は Windows/macOS で true）。重複・衝突の判定に使う
+    #[arg(long)]
+    pub case_insensitive_fs: Option<bool>,
+    /// 正規表現の \w \d や大小無視を ASCII だけで判定する（Unicode を使わない）
+    #[arg(long)]
+    pub regex_ascii: bool,
+    /// 正規表現の `.` を改行にも一致させる
+    #[arg(long)]
+    pub regex_dot_all: bool,
+    /// 検索が名前全体と一致する場合だけ置換する
+    #[arg(long)]
+    pub whole_match: bool,
+    /// サブフォルダを含める
+    #[arg(long)]
+    pub recursive: bool,
+    /// --recursive で読み込む階層の上限（1 なら直下のみ、2 ならその 1 つ下のフォルダまで。0 なら無制限）
+    #[arg(long, requires = "recursive")]
+    pub max_depth: Option<usize>,
+    /// シンボリックリンク（ファイル・フォルダ）をたどって走査する
+    #[arg(long)]
+    pub follow_symlinks: bool,
+    /// 音楽ファイルのタグを読み込み、{artist} {album} {title} {track} を使えるようにする
+    #[arg(long)]
+    pub audio_tags: bool,
+    /// {exif} で EXIF がない場合に更新日時を使わず空にする
+    #[arg(long)]
+    pub exif_no_mtime_fallback: bool,
+    /// サブフォルダ内のファイルも対象フォルダ直下へ移動する（--recursive と併用）
+    #[arg(long)]
+    pub flatten: bool,
+    /// フラット化で空になったサブフォルダを削除する
+    #[arg(long)]
+    pub remove_empty_dirs: bool,
+    /// 新しい名前の / をフォルダの区切りとして、サブフォルダ（なければ作成）へ振り分ける
+    #[arg(long)]
+    pub sort_into_subfolders: bool,
+    /// 検索/置換を対象フォルダからの相対パス全体に適用し、書き換えたパスへ移動する
+    #[arg(long)]
+    pub full_path: bool,
+    /// 同じ条件でまとめて変更する追加の対象フォルダ（複数指定可）
+    #[arg(long)]
+    pub extra_dir: Vec<String>,
+    /// 適用時に同時に処理するファイルの数（1 なら 1 件ずつ。既定は CPU のコア数、最大 8）
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// 新しい名前の末尾の . と空白を取り除かない（Windows のみ。既定では取り除く）
+    #[arg(long)]
+    pub keep_trailing_dots: bool,
+    /// 許可する文字以外を置き換える（例 `A-Za-z0-9._-`）
+    #[arg(long)]
+    pub allowed_chars: Option<String>,
+    /// --allowed-chars で許可されない文字の置き換え先
+    #[arg(long, default_value = "_")]
+    pub restrict_replacement: String,
+    /// --allowed-chars の置き換えが連続してもまとめない
+    #[arg(long)]
+    pub no_collapse: bool,
+    /// 除外パターン（カンマ区切り）
+    #[arg(long, default_value = "")]
+    pub exclude: String,
+    /// 隠しファイルも対象にする
+    #[arg(long)]
+    pub include_hidden: bool,
+    /// この大きさ未満のファイルを除外する（例 1KB、単位は 1024 基準）
+    #[arg(long)]
+    pub min_size: Option<String>,
+    /// この大きさを超えるファイルを除外する（例 10MB）
+    #[arg(long)]
+    pub max_size: Option<String>,
+    /// 重複時に連番を付与する
+    #[arg(long)]
+    pub auto_number: bool,
+    /// 連番の書式（例 `_{n:2}`）
+    #[arg(long, default_value = " ({n})")]
+    pub auto_number_format: String,
+    /// リネームせず、新しい名前で指定フォルダへコピーする
+    #[arg(long)]
+    pub copy_to: Option<String>,
+    /// 元のファイルを残し、新しい名前のリンクを作る（hard / symbolic）
+    #[arg(long, value_parser = ["hard", "symbolic"])]
+    pub link: Option<String>,
+    /// 読み取り専用のファイルの扱い（attempt: そのまま試す / skip: 飛ばす / unlock: 解除して変更 / unlock_restore: 解除して変更し元に戻す）
+    #[arg(long, value_parser = ["attempt", "skip", "unlock", "unlock_restore"])]
+    pub read_only: Option<String>,
+    /// この件数以上を一度に変更する場合は --force がなければ中止する（0 なら件数では中止しない、既定 1000）
+    #[arg(long)]
+    pub max_files: Option<usize>,
+    /// プレビューの何番目から適用するか（1 から。--range-end と組み合わせて範囲を限定する）
+    #[arg(long)]
+    pub range_start: Option<usize>,
+    /// プレビューの何番目まで適用するか
+    #[arg(long)]
+    pub range_end: Option<usize>,
+    /// 内容が同一のファイル（重複）を検出して表示する
+    #[arg(long)]
+    pub find_duplicates: bool,
+    /// 検索のみ: --search に一致するファイルのパスを表示する（名前は変えない、--apply とは併用不可）
+    #[arg(long, conflicts_with = "apply")]
+    pub find: bool,
+    /// --find であいまい検索にし、検索語と違う文字がこの数までの名前も表示する
+    #[arg(long, requires = "find")]
+    pub fuzzy: Option<usize>,
+    /// 適用前に元のファイルを対象フォルダの .filename-change-backup/<日時>/ へコピーする
+    #[arg(long)]
+    pub backup: bool,
+    /// 変更を実際に適用する（省略時はプレビューのみ）
+    #[arg(long)]
+    pub apply: bool,
+    /// ルート・ホーム・システムフォルダや大量の変更でも適用する
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// ヘッドレスで読み込み・プレビュー・（必要なら）適用を行い、終了コードを返す。
+pub fn run(args: CliArgs) -> i32 {
+    let mut data = AppState::new();
+    data.selected_dir = args.dir.unwrap_or_default();
+    data.search_pattern = args.search;
+    data.replace_pattern = args.replace;
+    data.prefix = args.prefix;
+    data.suffix = args.suffix;
+    data.insert_text = args.insert_text;
+    data.insert_position = args.insert_position;
+    if let Some(delimiter) = args.swap {
+        data.swap_parts = true;
+        data.swap_delimiter = delimiter;
+    }
+    data.swap_joiner = args.swap_joiner;
+    data.swap_split_first = args.swap_split_first;
+    if let Some(format) = args.date_from {
+        data.reformat_date = true;
+        data.date_input_format = format;
+    }
+    data.date_output_format = args.date_to;
+    if let Some(script) = args.script {
+        data.use_script = true;
+        data.script_expr = script;
+    }
+    data.strip_copy_suffixes = args.strip_copy_suffixes;
+    data.renumber = args.renumber;
+    data.renumber_digits = args.renumber_digits;
+    data.renumber_whole = args.renumber_whole;
+    data.strip_existing_number = args.strip_existing_number;
+    data.sequence_start = args.sequence_start;
+    data.reverse_numbering = args.reverse_numbering;
+    data.lowercase_extension = args.lowercase_ext;
+    data.normalize_extension_aliases = args.normalize_ext;
+    data.compound_extensions = args.compound_ext;
+    data.search_only = args.find;
+    data.backup_before_apply = args.backup;
+    if let Some(distance) = args.fuzzy {
+        data.fuzzy_search = true;
+        data.fuzzy_distance = distance;
+    }
+    data.use_regex = args.regex;
+    data.case_sensitive = args.case_sensitive;
+    if let Some(case_insensitive_fs) = args.case_insensitive_fs {
+        data.case_insensitive_fs = case_insensitive_fs;
+    }
+    data.regex_unicode = !args.regex_ascii;
+    data.regex_dot_all = args.regex_dot_all;
+    data.whole_match = args.whole_match;
+    data.include_subdirectories = args.recursive;
+    data.max_depth = args.max_depth.filter(|depth| *depth > 0);
+    data.follow_symlinks = args.follow_symlinks;
+    data.use_audio_tags = args.audio_tags;
+    data.exif_fallback_mtime = !args.exif_no_mtime_fallback;
+    data.flatten = args.flatten;
+    data.remove_empty_dirs = args.remove_empty_dirs;
+    data.sort_into_subfolders = args.sort_into_subfolders;
+    data.match_full_path = args.full_path;
+    data.extra_dirs = args.extra_dir.into_iter().collect();
+    if let Some(threads) = args.threads {
+        data.rename_threads = threads.max(1);
+    }
+    data.strip_trailing_dots = !args.keep_trailing_dots;
+    if let Some(allowed) = args.allowed_chars {
+        data.restrict_chars = true;
+        data.allowed_chars = allowed;
+    }
+    data.restrict_replacement = args.restrict_replacement;
+    data.collapse_replacements = !args.no_collapse;
+    data.exclude_pattern = args.exclude;
+    data.exclude_hidden = !args.include_hidden;
+    data.min_size = args.min_size.unwrap_or_default();
+    data.max_size = args.max_size.unwrap_or_default();
+    data.auto_number_on_conflict = args.auto_number;
+    data.auto_number_template = args.auto_number_format;
+    if args.range_start.is_some() || args.range_end.is_some() {
+        data.limit_range = true;
+        data.range_start = args.range_start.unwrap_or(1);
+        data.range_end = args.range_end.unwrap_or(0);
+    }
+    if let Some(target) = args.copy_to {
+        data.copy_to_target = true;
+        data.target_dir = target;
+    }
+    data.link_kind = match args.link.as_deref() {
+        Some("hard") => LinkKind::Hard,
+        Some("symbolic") => LinkKind::Symbolic,
+        _ => LinkKind::None,
+    };
+    if let Some(max_files) = args.max_files {
+        data.large_batch_threshold = max_files;
+    }
+    if let Some(handling) = args.read_only.as_deref().and_then(|s| s.parse().ok()) {
+        data.read_only_handling = handling;
+    }
+    if let Some(spec) = args.separators {
+        let parsed = spec.split_once(':').and_then(|(from, to)| Some((from.parse().ok()?, to.parse().ok()?)));
+        let Some((from, to)) = parsed else {
+            eprintln!("--separators の指定が不正です（例 space:underscore）: {}", spec);
+            return 1;
+        };
+        data.normalize_separators = true;
+        data.separator_from = from;
+        data.separator_to = to;
+        data.separator_collapse = !args.separator_keep_runs;
+        data.separator_trim = !args.separator_keep_edges;
+    }
+
+    if let Some(path) = args.lookup_table {
+        match LookupTable::load(Path::new(&path)) {
+            Ok(table) => {
+                eprintln!("{}", table.summary());
+                data.lookup_table = Some(Arc::new(table));
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    }
+    if let Err(e) = compile_rules(&data) {
+        eprintln!("検索パターンのエラー: {}", e);
+        return 1;
+    }
+    if data.reformat_date {
+        let date_error = describe_date_formats(&data.date_input_format, &data.date_output_format);
+        if !date_error.is_empty() {
+            eprintln!("日付の書式のエラー: {}", date_error);
+            return 1;
+        }
+    }
+    // 無効な除外パターンは読み飛ばして続行するが、内容は必ず知らせる
+    let (_, exclude_errors) = describe_excludes(&data.exclude_pattern);
+    if !exclude_errors.is_empty() {
+        eprintln!("除外パターンのエラー:\n{}", exclude_errors);
+    }
+    // サイズの指定の誤りは意図しないファイルまで変えかねないため、続行しない
+    let size_error = describe_size_filter(&data.min_size, &data.max_size);
+    if !size_error.is_empty() {
+        eprintln!("{}", size_error);
+        return 1;
+    }
+    load_files(&mut data);
+    // 除外の内訳は除外の調整用のため、プレビューの出力と混ざらないよう標準エラーに出す
+    if !data.exclude_breakdown.is_empty() {
+        eprintln!("{}", data.exclude_breakdown);
+    }
+    if data.files.is_empty() {
+        eprintln!("{}", data.status_message);
+        return 1;
+    }
+    if args.find_duplicates {
+        let paths: Vec<String> = data.files.iter().map(|f| f.original_path.clone()).collect();
+        let mut groups: Vec<(usize, String)> = find_duplicates_blocking(&paths).into_iter().map(|(p, g)| (g, p)).collect();
+        groups.sort();
+        for (group, path) in &groups {
+            println!("重複 #{}: {}", group, path);
+        }
+        println!("内容が同一のファイル {} 件", groups.len());
+    }
+    update_preview(&mut data);
+    if !data.hash_pending.is_empty() {
+        // 大きいファイルの {hash} はここで計算してから名前を決め直す
+        let paths: Vec<String> = data.hash_pending.iter().cloned().collect();
+        hash_blocking(&paths);
+        update_preview(&mut data);
+    }
+    if data.search_only {
+        for f in data.preview_files.iter() {
+            println!("{}", f.original_path);
+        }
+        println!("{}", data.status_message);
+        return 0;
+    }
+    for f in data.preview_files.iter() {
+        if f.in_range {
+            println!("{} -> {}", f.original_path, f.new_name);
+        } else {
+            println!("{} -> {} (範囲外)", f.original_path, f.new_name);
+        }
+    }
+    println!("{}", data.status_message);
+
+    if !args.apply {
+        return 0;
+    }
+    let mode = match ApplyMode::from_state(&data) {
+        Ok(mode) => mode,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return 1;
+        }
+    };
+    let changed_files = match plan_renames(&data, &mode) {
+        Ok(files) => files,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return 1;
+        }
+    };
+    // GUI の警告ダイアログの代わりに、--force がなければ中止する
+    if !args.force {
+        if let Some(reason) = sensitive_dir_reason(Path::new(&data.selected_dir)) {
+            eprintln!("対象フォルダは{}: {}（適用するには --force を指定）", reason, data.selected_dir);
+            return 1;
+        }
+        if exceeds_batch_limit(changed_files.len(), data.large_batch_threshold) {
+            eprintln!(
+                "{} 件のファイルを一度に変更します（上限 {} 件。適用するには --force を指定するか --max-files で上限を変更）",
+                changed_files.len(),
+                data.large_batch_threshold
+            );
+            return 1;
+        }
+    }
+    let outcome = execute_renames(
+        &changed_files,
+        &mode,
+        data.rename_threads,
+        data.read_only_handling,
+        data.backup_before_apply,
+        |_| {},
+    );
+    println!("{}", outcome.message);
+    let folders = outcome.folder_breakdown();
+    if !folders.is_empty() {
+        println!("フォルダごと:\n{}", folders);
+    }
+    for f in &outcome.failures {
+        eprintln!("失敗: {} -> {}: {}", f.original_path, f.new_name, f.reason);
+    }
+    if !outcome.failures.is_empty() {
+        1
+    } else {
+        0
+    }
+}
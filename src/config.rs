@@ -0,0 +1,155 @@
+// 設定・ログの保存先
+
+use druid::{Point, Rect, Screen, Size};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tracing::warn;
+
+/// アプリの設定ディレクトリ（Windows: %APPDATA%\filename-change、その他: $XDG_CONFIG_HOME または ~/.config 配下）。
+/// 存在しなければ作成する。
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+    }?;
+    let dir = base.join("filename-change");
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+/// パスを OS の既定の方法で開く（フォルダはエクスプローラ（Finder など）で、ファイルは既定のアプリで）。
+pub fn open_in_file_manager(path: &std::path::Path) -> std::io::Result<()> {
+    let program = if cfg!(windows) {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(program).arg(path).spawn().map(|_| ())
+}
+
+/// ファイルをエクスプローラ（Finder）で、選択した状態で表示する。
+/// 選択表示の方法が決まっていない Linux などでは、ファイルのあるフォルダを開く。
+pub fn reveal_in_file_manager(path: &std::path::Path) -> std::io::Result<()> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // explorer は引数の引用符を独自に解釈するため、/select, の後にパスを引用符付きでそのまま渡す
+        return std::process::Command::new("explorer")
+            .raw_arg(format!("/select,\"{}\"", path.display()))
+            .spawn()
+            .map(|_| ());
+    }
+    #[cfg(target_os = "macos")]
+    {
+        return std::process::Command::new("open").arg("-R").arg(path).spawn().map(|_| ());
+    }
+    #[allow(unreachable_code)]
+    match path.parent() {
+        Some(dir) => open_in_file_manager(dir),
+        None => open_in_file_manager(path),
+    }
+}
+
+/// 設定ファイル（config_dir 直下の settings.ini、`key=value` 形式の行）
+#[derive(Debug, Default)]
+pub struct Settings {
+    values: BTreeMap<String, String>,
+}
+
+impl Settings {
+    /// 設定ファイルを読み込む。無い・読めない場合は空の設定を返す。
+    pub fn load() -> Self {
+        let values = settings_path()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .map(|text| {
+                text.lines()
+                    .filter(|line| !line.trim_start().starts_with('#'))
+                    .filter_map(|line| line.split_once('='))
+                    .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { values }
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.values.get(key).and_then(|v| v.parse().ok())
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = settings_path().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "設定フォルダがありません"))?;
+        let text: String = self.values.iter().map(|(k, v)| format!("{}={}\n", k, v)).collect();
+        std::fs::write(path, text)
+    }
+}
+
+/// 設定を 1 項目だけ書き換えて保存する（失敗はログに出すだけ）。
+pub fn save_setting(key: &str, value: impl ToString) {
+    let mut settings = Settings::load();
+    settings.set(key, value);
+    if let Err(e) = settings.save() {
+        warn!(key, err = %e, "settings_save_failed");
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("settings.ini"))
+}
+
+/// ウィンドウの位置と大きさ（論理座標）
+#[derive(Debug, Clone, Copy)]
+pub struct WindowGeometry {
+    pub position: Point,
+    pub size: Size,
+}
+
+/// 復元するウィンドウの最小サイズ
+const MIN_WINDOW_SIZE: Size = Size::new(400.0, 300.0);
+
+impl WindowGeometry {
+    /// 保存されたウィンドウ位置・サイズを読み込む。未保存なら None。
+    pub fn load(settings: &Settings) -> Option<Self> {
+        Some(Self {
+            position: Point::new(settings.get("window.x")?, settings.get("window.y")?),
+            size: Size::new(settings.get("window.width")?, settings.get("window.height")?),
+        })
+    }
+
+    /// 現在の位置・サイズを設定ファイルに書き込む。
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut settings = Settings::load();
+        settings.set("window.x", self.position.x.round());
+        settings.set("window.y", self.position.y.round());
+        settings.set("window.width", self.size.width.round());
+        settings.set("window.height", self.size.height.round());
+        settings.save()
+    }
+
+    /// 復元に使うサイズ（小さすぎる値は最小サイズに広げる）
+    pub fn restored_size(&self) -> Size {
+        Size::new(
+            self.size.width.max(MIN_WINDOW_SIZE.width),
+            self.size.height.max(MIN_WINDOW_SIZE.height),
+        )
+    }
+
+    /// タイトルバー付近が現在接続中のいずれかのモニターに十分に重なっているか。
+    /// モニターを外した後などに画面外へ開かないよう、重ならなければ位置は復元しない。
+    pub fn is_on_screen(&self) -> bool {
+        let title_bar = Rect::from_origin_size(self.position, (self.restored_size().width, 30.0));
+        Screen::get_monitors().iter().any(|m| {
+            let visible = m.virtual_work_rect().intersect(title_bar);
+            visible.width() >= 100.0 && visible.height() >= 10.0
+        })
+    }
+}
@@ -0,0 +1,185 @@
+// 内容のハッシュによる名前付け（`{hash}` / `{hash:桁数}`）
+//
+// ファイル内容の BLAKE3 ハッシュ（16 進表記）の先頭を名前に使う。重い処理のため、トークンを使う場合だけ計算し、
+// 結果はパスごとにサイズ・更新日時と合わせてキャッシュする。小さいファイルはその場で計算し、
+// 大きいファイルはバックグラウンドで計算して、済んだら HASHES_READY でプレビューを更新し直す。
+
+use crate::events::HASHES_READY;
+use crate::paths::to_extended_path;
+use druid::{ExtEventSink, Target};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use tracing::debug;
+
+/// `{hash}` と桁数を省略したときの桁数
+const DEFAULT_DIGITS: usize = 8;
+/// このサイズ（バイト）以下のファイルはプレビューの更新中にその場で計算する
+const INLINE_HASH_LIMIT: u64 = 4 * 1024 * 1024;
+/// 読み込みの単位（この単位ごとに中止を確認する）
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// 計算済みのハッシュ（サイズ・更新日時が変わっていれば使わない）
+struct CachedHash {
+    len: u64,
+    modified: Option<SystemTime>,
+    hash: Result<String, String>, // 16 進表記 / 読めなかった理由
+}
+
+/// 元パス → 計算済みのハッシュ
+static HASH_CACHE: OnceLock<Mutex<HashMap<String, CachedHash>>> = OnceLock::new();
+/// バックグラウンドの計算の世代。計算するファイルが変わったら増やし、古い計算スレッドは打ち切る。
+static HASH_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// バックグラウンドで計算中のファイル
+static HASH_QUEUE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// ファイルのハッシュ
+pub enum ContentHash {
+    Ready(String),      // 16 進表記
+    Unreadable(String), // 読めなかった理由
+    Pending,            // 大きいファイルで、まだ計算していない
+}
+
+/// `{hash}` / `{hash:桁数}` を含むか。
+pub fn has_hash_token(text: &str) -> bool {
+    text.contains("{hash}") || text.contains("{hash:")
+}
+
+/// `{hash:桁数}` をハッシュの先頭の桁数分（1〜64、省略時 8）に置き換える。`hash` が None なら空にする。
+pub fn expand_hash_tokens(name: &str, hash: Option<&str>) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find("{hash") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + "{hash".len()..];
+        let digits = match after.find('}') {
+            Some(0) => Some(DEFAULT_DIGITS),
+            Some(end) if after.starts_with(':') => after[1..end].parse::<usize>().ok(),
+            _ => None,
+        };
+        let Some(digits) = digits else {
+            // `{hashx}` や桁数が数字でないものはそのまま残す
+            out.push_str("{hash");
+            rest = after;
+            continue;
+        };
+        if let Some(hash) = hash {
+            out.push_str(&hash[..digits.clamp(1, hash.len())]);
+        }
+        rest = &after[after.find('}').map(|e| e + 1).unwrap_or(after.len())..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// キャッシュ済みのハッシュを返す。なければ小さいファイルはその場で計算し、大きいファイルは Pending。
+pub fn content_hash(path: &str) -> ContentHash {
+    let meta = match std::fs::metadata(to_extended_path(Path::new(path))) {
+        Ok(meta) => meta,
+        Err(e) => return ContentHash::Unreadable(e.to_string()),
+    };
+    let (len, modified) = (meta.len(), meta.modified().ok());
+    let cached = cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(path)
+        .filter(|c| c.len == len && c.modified == modified)
+        .map(|c| c.hash.clone());
+    let hash = match cached {
+        Some(hash) => hash,
+        None if len > INLINE_HASH_LIMIT => return ContentHash::Pending,
+        None => match compute_and_store(path, || false) {
+            Some(hash) => hash,
+            None => return ContentHash::Pending,
+        },
+    };
+    match hash {
+        Ok(hex) => ContentHash::Ready(hex),
+        Err(e) => ContentHash::Unreadable(e),
+    }
+}
+
+/// `paths` のハッシュをバックグラウンドで計算し、済んだら HASHES_READY を送る。
+/// 同じファイルを計算中なら何もしない。計算するファイルが変わった（空を含む）場合は計算中のものを打ち切る。
+pub fn hash_in_background(paths: &[String], sink: ExtEventSink) {
+    let mut queue = HASH_QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+    if queue.as_slice() == paths {
+        return;
+    }
+    let generation = HASH_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    *queue = paths.to_vec();
+    if paths.is_empty() {
+        return;
+    }
+    let paths = paths.to_vec();
+    std::thread::spawn(move || {
+        let cancelled = || HASH_GENERATION.load(Ordering::SeqCst) != generation;
+        for path in &paths {
+            if compute_and_store(path, cancelled).is_none() {
+                debug!(generation, "content_hash_cancelled");
+                return;
+            }
+        }
+        let mut queue = HASH_QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+        if cancelled() {
+            return;
+        }
+        queue.clear();
+        drop(queue);
+        let _ = sink.submit_command(HASHES_READY, (), Target::Global);
+    });
+}
+
+/// `paths` のハッシュをその場で計算する（ヘッドレスモード用）。
+pub fn hash_blocking(paths: &[String]) {
+    for path in paths {
+        compute_and_store(path, || false);
+    }
+}
+
+fn cache() -> &'static Mutex<HashMap<String, CachedHash>> {
+    HASH_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// ハッシュを計算してキャッシュに入れる。中止された場合は None。
+fn compute_and_store(path: &str, cancelled: impl Fn() -> bool) -> Option<Result<String, String>> {
+    let full_path = to_extended_path(Path::new(path));
+    // 読み始める前のサイズ・更新日時で記録し、読んでいる間に変わったファイルは次回計算し直す
+    let (len, modified) = match std::fs::metadata(&full_path) {
+        Ok(meta) => (meta.len(), meta.modified().ok()),
+        Err(e) => return Some(Err(e.to_string())),
+    };
+    let hash = hash_file(&full_path, cancelled)?;
+    cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(path.to_string(), CachedHash { len, modified, hash: hash.clone() });
+    Some(hash)
+}
+
+/// ファイル内容の BLAKE3 ハッシュ（16 進表記）。途中で中止された場合は None。
+fn hash_file(path: &Path, cancelled: impl Fn() -> bool) -> Option<Result<String, String>> {
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Some(Err(e.to_string())),
+    };
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+    loop {
+        if cancelled() {
+            return None;
+        }
+        let n = match file.read(&mut buffer) {
+            Ok(n) => n,
+            Err(e) => return Some(Err(e.to_string())),
+        };
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Some(Ok(hasher.finalize().to_hex().to_string()))
+}
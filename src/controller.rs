@@ -1,8 +1,30 @@
-use crate::events::{PREVIEW_REQUEST, RENAMING_DONE, RENAMING_PROGRESS};
+use crate::events::{
+    APPLY_ROW, DUPLICATE_DONE, DUPLICATE_PROGRESS, EXCLUDE_FILE, FILES_CHANGED, FILTER_PREVIEW_REQUEST, HASHES_READY, MOVE_PREVIEW_ROW, OPEN_FILE, PREVIEW_REQUEST,
+    RECORD_INPUT_CHANGE, REMOVE_EXCLUDE_ITEM, REMOVE_EXTRA_DIR, REMOVE_RULE, RENAMING_DONE, RENAMING_PROGRESS, RENUMBER_PREVIEW_REQUEST, REVEAL_FILE, SCAN_CHUNK,
+    SCAN_DONE, SELECT_PREVIEW_ROW, TEST_PATTERN_REQUEST, TOGGLE_EXCLUDE_ITEM, TOGGLE_EXTENSION, TOGGLE_TARGET_DETAIL, UNDO_BATCH,
+};
 use rfd::{MessageButtons, MessageDialog, MessageLevel};
-use crate::preview::update_preview;
+use crate::preview::{
+    apply_preview_filter, apply_to_test_input, exclude_file, file_entry, remove_exclude_item, renumber_preview, request_preview,
+    select_preview_rows, set_extension_chips, start_pending_hashes, start_scan, toggle_exclude_item, toggle_extension, toggle_target_detail,
+    update_preview,
+};
+use crate::duplicates::apply_duplicate_groups;
+use crate::input_history::{record_input_change, redo_input, undo_input, InputSnapshot};
+use crate::config::{open_in_file_manager, reveal_in_file_manager, save_setting, Settings, WindowGeometry};
+use crate::history::list_batches;
+use crate::paths::to_extended_path;
+use crate::rename::{apply_changes, apply_row, undo_batch};
 use crate::state::AppState;
-use druid::{Env, Event, EventCtx, UpdateCtx, Widget};
+use crate::watch::stop_watching;
+use druid::{Data, Env, Event, EventCtx, ExtEventSink, HotKey, KbKey, SysMods, UpdateCtx, Widget};
+use std::path::Path;
+use tracing::{debug, warn};
+
+/// 完了ダイアログに列挙する失敗の最大件数
+const MAX_FAILURES_IN_DIALOG: usize = 10;
+/// 完了ダイアログに列挙するフォルダの最大数
+const MAX_FOLDERS_IN_DIALOG: usize = 10;
 
 pub struct AppController;
 
@@ -15,9 +37,225 @@ impl<W: Widget<AppState>> druid::widget::Controller<AppState, W> for AppControll
         data: &mut AppState,
         env: &Env,
     ) {
+        match event {
+            Event::WindowConnected => {
+                // 前回の位置が現在のモニター上にある場合だけ移動する（画面外に開かないように）
+                if let Some(geometry) = WindowGeometry::load(&Settings::load()) {
+                    if geometry.is_on_screen() {
+                        ctx.window().set_position(geometry.position);
+                    }
+                }
+                // 起動時にフォルダが渡されていれば読み込む
+                if !data.selected_dir.is_empty() && data.files.is_empty() && !data.scan_in_progress {
+                    start_scan(data, ctx.get_external_handle(), false);
+                }
+            }
+            Event::WindowCloseRequested => {
+                let geometry = WindowGeometry {
+                    position: ctx.window().get_position(),
+                    size: ctx.window().get_size(),
+                };
+                if let Err(e) = geometry.save() {
+                    warn!("failed to save window geometry: {}", e);
+                }
+            }
+            _ => {}
+        }
+        if let Event::KeyDown(key) = event {
+            // ショートカット: Ctrl+Enter / F5 でプレビュー、Ctrl+Shift+Enter / F9 で適用
+            if !data.conversion_in_progress {
+                if HotKey::new(SysMods::CmdShift, KbKey::Enter).matches(key)
+                    || HotKey::new(None, KbKey::F9).matches(key)
+                {
+                    apply_changes(ctx, data);
+                    ctx.set_handled();
+                    return;
+                }
+                // F5 はフォルダを読み直してから、Ctrl+Enter は条件が変わっていなければ読み込み済みの一覧でプレビュー
+                if HotKey::new(None, KbKey::F5).matches(key) {
+                    reload_files(data, ctx.get_external_handle());
+                    ctx.set_handled();
+                    return;
+                }
+                if HotKey::new(SysMods::Cmd, KbKey::Enter).matches(key) {
+                    request_preview(data, ctx.get_external_handle());
+                    ctx.set_handled();
+                    return;
+                }
+                // Ctrl+Z / Ctrl+Y（Ctrl+Shift+Z）で入力欄・チェックボックスの変更を取り消す/やり直す
+                if HotKey::new(SysMods::Cmd, "z").matches(key) {
+                    undo_input(data);
+                    ctx.set_handled();
+                    return;
+                }
+                if HotKey::new(SysMods::Cmd, "y").matches(key) || HotKey::new(SysMods::CmdShift, "Z").matches(key) {
+                    redo_input(data);
+                    ctx.set_handled();
+                    return;
+                }
+            }
+        }
         if let Event::Command(cmd) = event {
             if cmd.is(PREVIEW_REQUEST) {
-                update_preview(data);
+                // 走査条件が変わった場合や適用後だけファイル一覧を読み直す
+                request_preview(data, ctx.get_external_handle());
+                ctx.set_handled();
+                return;
+            }
+            if let Some((scan_id, scanned)) = cmd.get(SCAN_CHUNK) {
+                // 古い走査（フォルダ変更前など）の結果は捨てる
+                if *scan_id == data.scan_id {
+                    for f in scanned.iter().filter(|f| !data.session_excluded.contains(&f.path)) {
+                        let entry = file_entry(f.clone(), data);
+                        data.files.push_back(entry);
+                    }
+                    data.status_message = format!("読み込み中... {} 件", data.files.len());
+                }
+                ctx.set_handled();
+                return;
+            }
+            if let Some((scan_id, summary)) = cmd.get(SCAN_DONE) {
+                if *scan_id == data.scan_id {
+                    data.scan_in_progress = false;
+                    set_extension_chips(data, &summary.extensions);
+                    data.invalid_names = summary.invalid_names.iter().cloned().collect();
+                    data.exclude_breakdown = summary.exclude_breakdown();
+                    data.set_status(summary.status(data.files.len()));
+                    debug!("loaded_files: {}", data.files.len());
+                    if data.preview_after_scan {
+                        data.preview_after_scan = false;
+                        update_preview(data);
+                        start_pending_hashes(data, ctx.get_external_handle());
+                    }
+                }
+                ctx.set_handled();
+                return;
+            }
+            if cmd.is(HASHES_READY) {
+                // 読み込み中・適用中なら、その後のプレビューの更新でキャッシュ済みのハッシュが使われる
+                if !data.scan_in_progress && !data.conversion_in_progress {
+                    update_preview(data);
+                    start_pending_hashes(data, ctx.get_external_handle());
+                }
+                ctx.set_handled();
+                return;
+            }
+            if cmd.is(FILES_CHANGED) {
+                // 適用中の変更は適用後にまとめて読み直すため、ここでは読み直さない
+                if !data.conversion_in_progress {
+                    reload_files(data, ctx.get_external_handle());
+                }
+                ctx.set_handled();
+                return;
+            }
+            if let Some(before) = cmd.get(RECORD_INPUT_CHANGE) {
+                record_input_change(data, before.clone());
+                ctx.set_handled();
+                return;
+            }
+            if let Some(&id) = cmd.get(REMOVE_RULE) {
+                data.extra_rules.retain(|r| r.id != id);
+                ctx.set_handled();
+                return;
+            }
+            if let Some(ext) = cmd.get(TOGGLE_EXTENSION) {
+                toggle_extension(data, ext, ctx.get_external_handle());
+                ctx.set_handled();
+                return;
+            }
+            if let Some(raw) = cmd.get(TOGGLE_EXCLUDE_ITEM) {
+                toggle_exclude_item(data, raw);
+                request_preview(data, ctx.get_external_handle());
+                ctx.set_handled();
+                return;
+            }
+            if let Some(raw) = cmd.get(REMOVE_EXCLUDE_ITEM) {
+                remove_exclude_item(data, raw);
+                request_preview(data, ctx.get_external_handle());
+                ctx.set_handled();
+                return;
+            }
+            if let Some(dir) = cmd.get(REMOVE_EXTRA_DIR) {
+                data.extra_dirs.retain(|d| d != dir);
+                ctx.set_handled();
+                return;
+            }
+            if let Some(path) = cmd.get(APPLY_ROW) {
+                apply_row(ctx, data, path);
+                ctx.set_handled();
+                return;
+            }
+            if let Some((path, delta)) = cmd.get(MOVE_PREVIEW_ROW) {
+                if let Some(idx) = data.preview_files.iter().position(|f| &f.original_path == path) {
+                    let target = idx as isize + delta;
+                    if target >= 0 && (target as usize) < data.preview_files.len() {
+                        data.preview_files.swap(idx, target as usize);
+                        renumber_preview(data);
+                    }
+                }
+                ctx.set_handled();
+                return;
+            }
+            if let Some(&(done, total)) = cmd.get(DUPLICATE_PROGRESS) {
+                if data.duplicate_check_in_progress {
+                    data.status_message = format!("重複ファイル検出中... {} / {} 件", done, total);
+                }
+                ctx.set_handled();
+                return;
+            }
+            if let Some((check_id, scan_id, groups)) = cmd.get(DUPLICATE_DONE) {
+                // 中止・再開始した古い検出の結果は捨てる
+                if *check_id == data.duplicate_check_id && data.duplicate_check_in_progress {
+                    data.duplicate_check_in_progress = false;
+                    if let Some(groups) = groups {
+                        apply_duplicate_groups(data, *scan_id, groups);
+                    }
+                }
+                ctx.set_handled();
+                return;
+            }
+            if let Some(batch_id) = cmd.get(UNDO_BATCH) {
+                undo_batch(ctx, data, batch_id);
+                ctx.set_handled();
+                return;
+            }
+            if let Some(path) = cmd.get(REVEAL_FILE) {
+                open_row_file(data, path, true);
+                ctx.set_handled();
+                return;
+            }
+            if let Some(path) = cmd.get(OPEN_FILE) {
+                open_row_file(data, path, false);
+                ctx.set_handled();
+                return;
+            }
+            if let Some(path) = cmd.get(TOGGLE_TARGET_DETAIL) {
+                toggle_target_detail(data, path);
+                ctx.set_handled();
+                return;
+            }
+            if let Some(path) = cmd.get(EXCLUDE_FILE) {
+                exclude_file(data, path);
+                ctx.set_handled();
+                return;
+            }
+            if let Some((path, extend)) = cmd.get(SELECT_PREVIEW_ROW) {
+                select_preview_rows(data, path, *extend);
+                ctx.set_handled();
+                return;
+            }
+            if cmd.is(RENUMBER_PREVIEW_REQUEST) {
+                renumber_preview(data);
+                ctx.set_handled();
+                return;
+            }
+            if cmd.is(FILTER_PREVIEW_REQUEST) {
+                apply_preview_filter(data);
+                ctx.set_handled();
+                return;
+            }
+            if cmd.is(TEST_PATTERN_REQUEST) {
+                data.test_output = apply_to_test_input(data);
                 ctx.set_handled();
                 return;
             }
@@ -27,19 +265,56 @@ impl<W: Widget<AppState>> druid::widget::Controller<AppState, W> for AppControll
                 ctx.set_handled();
                 return;
             }
-            if let Some(msg) = cmd.get(RENAMING_DONE) {
-                data.status_message = msg.clone();
+            if let Some(outcome) = cmd.get(RENAMING_DONE) {
+                let (msg, failures) = (&outcome.message, &outcome.failures);
+                data.set_status(msg.clone());
                 data.conversion_in_progress = false;
-                // リネーム適用後にファイル一覧/プレビューを最新化
+                let single_row = std::mem::take(&mut data.single_row_apply);
+                data.rename_failures = failures.iter().cloned().collect();
+                data.rename_results = outcome.results.iter().cloned().collect();
+                data.rename_folder_breakdown = outcome.folder_breakdown();
+                // 適用したファイルは元のパスが変わるため、貼り付けた名前の対応を外す
+                for result in outcome.results.iter().filter(|r| r.error.is_empty()) {
+                    data.pasted_names.remove(&result.original_path);
+                }
+                if data.show_history {
+                    data.history = list_batches().into_iter().collect();
+                }
+                // リネーム適用後にファイル一覧を読み直してプレビューを最新化
+                data.rescan_needed = true;
                 ctx.submit_command(PREVIEW_REQUEST.with(()));
-                // 完了ダイアログを表示
-                let message = msg.clone();
+                // 1 行だけの適用が成功した場合は、見直しを続けられるようステータスだけで知らせる
+                if single_row && failures.is_empty() {
+                    ctx.request_update();
+                    ctx.set_handled();
+                    return;
+                }
+                // 完了ダイアログを表示（失敗があれば先頭の数件と理由も示す）
+                let mut message = msg.clone();
+                for f in failures.iter().take(MAX_FAILURES_IN_DIALOG) {
+                    message.push_str(&format!("\n・{}: {}", f.original_path, f.reason));
+                }
+                if failures.len() > MAX_FAILURES_IN_DIALOG {
+                    message.push_str(&format!("\n…ほか {} 件（一覧は画面に表示）", failures.len() - MAX_FAILURES_IN_DIALOG));
+                }
+                // サブフォルダにまたがる場合は、どのフォルダで失敗したかを見つけやすいようフォルダごとの件数を添える
+                let folders: Vec<&str> = data.rename_folder_breakdown.lines().collect();
+                if !folders.is_empty() {
+                    message.push_str("\n\nフォルダごと:");
+                    for line in folders.iter().take(MAX_FOLDERS_IN_DIALOG) {
+                        message.push_str(&format!("\n・{}", line));
+                    }
+                    if folders.len() > MAX_FOLDERS_IN_DIALOG {
+                        message.push_str(&format!("\n…ほか {} フォルダ（一覧は画面に表示）", folders.len() - MAX_FOLDERS_IN_DIALOG));
+                    }
+                }
+                let level = if failures.is_empty() { MessageLevel::Info } else { MessageLevel::Warning };
                 std::thread::spawn(move || {
                     MessageDialog::new()
                         .set_title("変更の適用が完了しました")
                         .set_description(&message)
                         .set_buttons(MessageButtons::Ok)
-                        .set_level(MessageLevel::Info)
+                        .set_level(level)
                         .show();
                 });
                 ctx.request_update();
@@ -60,12 +335,138 @@ impl<W: Widget<AppState>> druid::widget::Controller<AppState, W> for AppControll
     ) {
         let checkbox_changed =
             old_data.case_sensitive != data.case_sensitive ||
-            old_data.include_subdirectories != data.include_subdirectories;
+            old_data.use_regex != data.use_regex ||
+            old_data.regex_unicode != data.regex_unicode ||
+            old_data.regex_dot_all != data.regex_dot_all ||
+            old_data.whole_match != data.whole_match ||
+            old_data.replace_count != data.replace_count ||
+            old_data.include_subdirectories != data.include_subdirectories ||
+            old_data.max_depth != data.max_depth ||
+            old_data.follow_symlinks != data.follow_symlinks ||
+            old_data.use_audio_tags != data.use_audio_tags ||
+            old_data.exif_fallback_mtime != data.exif_fallback_mtime ||
+            old_data.flatten != data.flatten ||
+            old_data.use_script != data.use_script ||
+            old_data.swap_parts != data.swap_parts ||
+            old_data.swap_split_first != data.swap_split_first ||
+            old_data.reformat_date != data.reformat_date ||
+            old_data.sort_into_subfolders != data.sort_into_subfolders ||
+            old_data.match_full_path != data.match_full_path ||
+            !old_data.extra_dirs.same(&data.extra_dirs) ||
+            old_data.strip_trailing_dots != data.strip_trailing_dots ||
+            old_data.exclude_hidden != data.exclude_hidden ||
+            old_data.case_insensitive_fs != data.case_insensitive_fs ||
+            old_data.fullwidth_to_halfwidth != data.fullwidth_to_halfwidth ||
+            old_data.normalize_separators != data.normalize_separators ||
+            old_data.separator_from != data.separator_from ||
+            old_data.separator_to != data.separator_to ||
+            old_data.separator_collapse != data.separator_collapse ||
+            old_data.separator_trim != data.separator_trim ||
+            old_data.renumber != data.renumber ||
+            old_data.renumber_whole != data.renumber_whole ||
+            old_data.strip_existing_number != data.strip_existing_number ||
+            old_data.lowercase_extension != data.lowercase_extension ||
+            old_data.normalize_extension_aliases != data.normalize_extension_aliases ||
+            old_data.compound_extensions != data.compound_extensions ||
+            old_data.search_only != data.search_only ||
+            old_data.fuzzy_search != data.fuzzy_search ||
+            old_data.fuzzy_distance != data.fuzzy_distance ||
+            old_data.strip_diacritics != data.strip_diacritics ||
+            old_data.strip_copy_suffixes != data.strip_copy_suffixes ||
+            old_data.transliterate_ascii != data.transliterate_ascii ||
+            old_data.unicode_normalization != data.unicode_normalization ||
+            old_data.restrict_chars != data.restrict_chars ||
+            old_data.collapse_replacements != data.collapse_replacements ||
+            old_data.truncate_with_ellipsis != data.truncate_with_ellipsis ||
+            !old_data.lookup_table.same(&data.lookup_table);
+        // 適用範囲はプレビューの並び順を保ったまま反映する（番号は限定が有効なときだけ）
+        // 逆順の番号付けも並び順を保ったまま番号だけを振り直す
+        let renumber_needed = old_data.limit_range != data.limit_range ||
+            old_data.reverse_numbering != data.reverse_numbering ||
+            (data.limit_range && (old_data.range_start != data.range_start || old_data.range_end != data.range_end));
+        if renumber_needed {
+            ctx.submit_command(RENUMBER_PREVIEW_REQUEST.with(()));
+        }
         if checkbox_changed {
             ctx.submit_command(PREVIEW_REQUEST.with(()));
         }
+        if old_data.dark_mode != data.dark_mode {
+            save_setting("dark_mode", data.dark_mode);
+        }
+        if old_data.backup_before_apply != data.backup_before_apply {
+            save_setting("backup_before_apply", data.backup_before_apply);
+        }
+        if old_data.read_only_handling != data.read_only_handling {
+            save_setting("read_only_handling", data.read_only_handling.key());
+        }
+        if old_data.large_batch_threshold != data.large_batch_threshold {
+            save_setting("large_batch_threshold", data.large_batch_threshold);
+        }
+        if old_data.rename_threads != data.rename_threads && data.rename_threads > 0 {
+            save_setting("rename_threads", data.rename_threads);
+        }
+        if old_data.auto_reload != data.auto_reload {
+            save_setting("auto_reload", data.auto_reload);
+            // ON にしたら読み直して監視を始め、OFF にしたら監視を止める
+            if data.auto_reload {
+                ctx.submit_command(FILES_CHANGED);
+            } else {
+                stop_watching();
+            }
+        }
+        if old_data.preview_filter != data.preview_filter || old_data.problems_first != data.problems_first {
+            ctx.submit_command(FILTER_PREVIEW_REQUEST.with(()));
+        }
+        // 正規表現テスト欄は入力のたびに即時反映する
+        let test_changed = checkbox_changed ||
+            old_data.test_input != data.test_input ||
+            old_data.search_pattern != data.search_pattern ||
+            old_data.replace_pattern != data.replace_pattern ||
+            !old_data.extra_rules.same(&data.extra_rules) ||
+            old_data.prefix != data.prefix ||
+            old_data.suffix != data.suffix ||
+            old_data.insert_text != data.insert_text ||
+            old_data.insert_position != data.insert_position ||
+            old_data.swap_delimiter != data.swap_delimiter ||
+            old_data.swap_joiner != data.swap_joiner ||
+            old_data.date_input_format != data.date_input_format ||
+            old_data.date_output_format != data.date_output_format ||
+            old_data.script_expr != data.script_expr ||
+            old_data.ascii_placeholder != data.ascii_placeholder ||
+            old_data.allowed_chars != data.allowed_chars ||
+            old_data.restrict_replacement != data.restrict_replacement ||
+            old_data.max_name_length != data.max_name_length ||
+            old_data.renumber_digits != data.renumber_digits ||
+            old_data.sequence_start != data.sequence_start ||
+            old_data.replace_nth != data.replace_nth;
+        if test_changed {
+            ctx.submit_command(TEST_PATTERN_REQUEST.with(()));
+        }
+        let before = InputSnapshot::capture(old_data);
+        if !before.same(&InputSnapshot::capture(data)) {
+            ctx.submit_command(RECORD_INPUT_CHANGE.with(before));
+        }
         child.update(ctx, old_data, data, env);
     }
 }
 
+/// 対象フォルダを条件が同じでも読み直し、プレビューを更新する（F5・再読み込みボタン・自動再読み込み）。
+fn reload_files(data: &mut AppState, sink: ExtEventSink) {
+    data.rescan_needed = true;
+    request_preview(data, sink);
+}
 
+/// 右クリックメニューの「エクスプローラーで表示」（`reveal`）/「既定のアプリで開く」。
+/// 読み込み後に移動・削除されたファイルは開かずにステータスで知らせる。
+fn open_row_file(data: &mut AppState, path: &str, reveal: bool) {
+    let file = Path::new(path);
+    if !to_extended_path(file).exists() {
+        data.set_status(format!("ファイルが見つかりません（移動・削除された可能性があります）: {}", path));
+        return;
+    }
+    let result = if reveal { reveal_in_file_manager(file) } else { open_in_file_manager(file) };
+    if let Err(e) = result {
+        warn!("open_file_failed: {} {}", path, e);
+        data.set_status(format!("ファイルを開けません: {}", e));
+    }
+}
@@ -0,0 +1,156 @@
+// 内容が同一のファイル（バイト単位の重複）の検出
+//
+// 読み込み済みのファイルのうち、サイズが同じものだけを BLAKE3 でハッシュして比較する。
+// 重い処理のため利用者が開始したときだけバックグラウンドで実行し、DUPLICATE_PROGRESS / DUPLICATE_DONE で通知する。
+
+use crate::events::{DUPLICATE_DONE, DUPLICATE_PROGRESS};
+use crate::paths::to_extended_path;
+use crate::preview::{apply_preview_filter, set_selected};
+use crate::state::AppState;
+use druid::{ExtEventSink, Target};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::debug;
+
+/// 元パス → 内容が同一のファイルのグループ番号（1 から、重複のないファイルは含めない）
+pub type DuplicateGroups = HashMap<String, usize>;
+
+/// 検出の世代。中止・再開始で増やし、古い検出スレッドはこれを見て打ち切る。
+static DUPLICATE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// 読み込みの単位（この単位ごとに中止を確認する）
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// 読み込み済みのファイルの重複検出をバックグラウンドで始める。
+pub fn start_duplicate_check(data: &mut AppState, sink: ExtEventSink) {
+    if data.scan_in_progress {
+        data.set_status("ファイル一覧の読み込み中です。完了後に検出してください。");
+        return;
+    }
+    let generation = DUPLICATE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let scan_id = data.scan_id;
+    let paths: Vec<String> = data.files.iter().map(|f| f.original_path.clone()).collect();
+    data.duplicate_check_id = generation;
+    data.duplicate_check_in_progress = true;
+    data.status_message = "重複ファイル検出中...".to_string();
+    std::thread::spawn(move || {
+        let groups = find_duplicate_groups(&paths, generation, |done, total| {
+            let _ = sink.submit_command(DUPLICATE_PROGRESS, (done, total), Target::Global);
+        });
+        let _ = sink.submit_command(DUPLICATE_DONE, (generation, scan_id, groups), Target::Global);
+    });
+}
+
+/// 実行中の重複検出を中止する（検出スレッドは次の読み込み単位で打ち切られ、その結果は捨てる）。
+pub fn cancel_duplicate_check(data: &mut AppState) {
+    DUPLICATE_GENERATION.fetch_add(1, Ordering::SeqCst);
+    data.duplicate_check_in_progress = false;
+    data.set_status("重複ファイル検出を中止しました");
+}
+
+/// 検出結果を一覧に反映する。検出を始めた後にファイル一覧を読み直していたら結果は捨てる。
+pub fn apply_duplicate_groups(data: &mut AppState, scan_id: u64, groups: &DuplicateGroups) {
+    if scan_id != data.scan_id {
+        data.set_status("検出中にファイル一覧が変わったため、重複ファイル検出の結果を破棄しました");
+        return;
+    }
+    for f in data.files.iter_mut() {
+        f.duplicate_group = groups.get(&f.original_path).copied().unwrap_or(0);
+    }
+    for f in data.preview_files.iter_mut() {
+        f.duplicate_group = groups.get(&f.original_path).copied().unwrap_or(0);
+    }
+    apply_preview_filter(data);
+    let group_count = groups.values().max().copied().unwrap_or(0);
+    if group_count == 0 {
+        data.set_status("重複ファイル検出: 内容が同一のファイルはありません");
+    } else {
+        data.set_status(format!("重複ファイル検出: 内容が同一 {} グループ（{} 件）", group_count, groups.len()));
+    }
+}
+
+/// 重複の各グループで、一覧の先頭（名前順）の 1 件だけを残し、残りのプレビューのチェックを外す。
+pub fn deselect_duplicates(data: &mut AppState) {
+    let mut kept: HashSet<usize> = HashSet::new();
+    let targets: HashSet<String> = data
+        .files
+        .iter()
+        .filter(|f| f.duplicate_group > 0 && !kept.insert(f.duplicate_group))
+        .map(|f| f.original_path.clone())
+        .collect();
+    let count = targets.len();
+    set_selected(data, &targets, false);
+    data.set_status(format!("重複ファイルの 2 件目以降 {} 件のチェックを外しました", count));
+}
+
+/// 重複検出をその場で実行する（ヘッドレスモード用、中止はできない）。
+pub fn find_duplicates_blocking(paths: &[String]) -> DuplicateGroups {
+    let generation = DUPLICATE_GENERATION.load(Ordering::SeqCst);
+    find_duplicate_groups(paths, generation, |_, _| {}).unwrap_or_default()
+}
+
+/// 内容が同一のファイルをまとめ、元パス → グループ番号（1 から）を返す。重複のないファイルは含めない。
+/// 中止された場合は None。`on_progress` にはハッシュした件数と、ハッシュが必要な件数を渡す。
+fn find_duplicate_groups(
+    paths: &[String],
+    generation: u64,
+    on_progress: impl Fn(usize, usize),
+) -> Option<DuplicateGroups> {
+    // サイズが他と異なるファイルは重複しえないため読まない
+    let mut by_size: HashMap<u64, Vec<&String>> = HashMap::new();
+    for path in paths {
+        if let Ok(meta) = std::fs::metadata(to_extended_path(Path::new(path))) {
+            by_size.entry(meta.len()).or_default().push(path);
+        }
+    }
+    let candidates: Vec<&String> = by_size.into_values().filter(|v| v.len() > 1).flatten().collect();
+    let total = candidates.len();
+    let mut by_hash: HashMap<blake3::Hash, Vec<&String>> = HashMap::new();
+    for (i, path) in candidates.into_iter().enumerate() {
+        if DUPLICATE_GENERATION.load(Ordering::SeqCst) != generation {
+            debug!(generation, "duplicate_check_cancelled");
+            return None;
+        }
+        // 読めないファイルは比較から外す
+        if let Some(hash) = hash_file(Path::new(path), generation) {
+            by_hash.entry(hash).or_default().push(path);
+        }
+        on_progress(i + 1, total);
+    }
+    if DUPLICATE_GENERATION.load(Ordering::SeqCst) != generation {
+        return None;
+    }
+    let mut groups: Vec<Vec<&String>> = by_hash.into_values().filter(|v| v.len() > 1).collect();
+    // 番号が実行ごとに変わらないよう、先頭のパスの順に並べる
+    for group in groups.iter_mut() {
+        group.sort();
+    }
+    groups.sort();
+    let mut result = HashMap::new();
+    for (i, group) in groups.into_iter().enumerate() {
+        for path in group {
+            result.insert(path.clone(), i + 1);
+        }
+    }
+    Some(result)
+}
+
+/// ファイル内容の BLAKE3 ハッシュ。読めない場合や途中で中止された場合は None。
+fn hash_file(path: &Path, generation: u64) -> Option<blake3::Hash> {
+    let mut file = std::fs::File::open(to_extended_path(path)).ok()?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+    loop {
+        if DUPLICATE_GENERATION.load(Ordering::SeqCst) != generation {
+            return None;
+        }
+        let n = file.read(&mut buffer).ok()?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Some(hasher.finalize())
+}
@@ -1,8 +1,49 @@
+use crate::state::{RenameOutcome, ScanSummary, ScannedFile};
+use crate::duplicates::DuplicateGroups;
+use crate::input_history::InputSnapshot;
 use druid::Selector;
 
 // カスタムコマンド（バックグラウンド処理からの進捗更新用）
 pub const RENAMING_PROGRESS: Selector<usize> = Selector::new("renaming_progress");
-pub const RENAMING_DONE: Selector<String> = Selector::new("renaming_done");
+// 完了メッセージとファイルごとの結果・失敗したファイルの一覧
+pub const RENAMING_DONE: Selector<RenameOutcome> = Selector::new("renaming_done");
+// ファイル一覧の走査結果（走査の世代, 追加分のファイル / エラーメッセージ）
+pub const SCAN_CHUNK: Selector<(u64, Vec<ScannedFile>)> = Selector::new("scan_chunk");
+// 走査の完了（走査の世代, 除外パターンのエラー・拡張子ごとのファイル数などの要約）
+pub const SCAN_DONE: Selector<(u64, ScanSummary)> = Selector::new("scan_done");
+// 適用履歴の指定バッチを取り消す（バッチ ID）
+pub const UNDO_BATCH: Selector<String> = Selector::new("undo_batch");
 pub const PREVIEW_REQUEST: Selector<()> = Selector::new("preview_request");
-
-
+pub const REMOVE_RULE: Selector<usize> = Selector::new("remove_rule");
+// 追加の対象フォルダを一覧から外す（フォルダのパス）
+pub const REMOVE_EXTRA_DIR: Selector<String> = Selector::new("remove_extra_dir");
+// 拡張子の絞り込みを切り替える（小文字の拡張子、拡張子なしは空）
+pub const TOGGLE_EXTENSION: Selector<String> = Selector::new("toggle_extension");
+// 除外の項目のチップを ON/OFF する・削除する（除外欄に書いた項目）
+pub const TOGGLE_EXCLUDE_ITEM: Selector<String> = Selector::new("toggle_exclude_item");
+pub const REMOVE_EXCLUDE_ITEM: Selector<String> = Selector::new("remove_exclude_item");
+pub const TEST_PATTERN_REQUEST: Selector<()> = Selector::new("test_pattern_request");
+pub const FILTER_PREVIEW_REQUEST: Selector<()> = Selector::new("filter_preview_request");
+// プレビュー行の並べ替え（元パス, 移動量 -1: 上へ / 1: 下へ）
+pub const MOVE_PREVIEW_ROW: Selector<(String, isize)> = Selector::new("move_preview_row");
+// プレビューの 1 行だけをすぐに適用する（元パス）
+pub const APPLY_ROW: Selector<String> = Selector::new("apply_row");
+// プレビュー行のチェックを切り替える（元パス, Shift+クリックで前回の行からの範囲）
+pub const SELECT_PREVIEW_ROW: Selector<(String, bool)> = Selector::new("select_preview_row");
+// 重複ファイル検出の進捗（ハッシュした件数, 対象件数）と結果（検出の ID, 走査の世代, 元パス → グループ番号 / 中止なら None）
+pub const DUPLICATE_PROGRESS: Selector<(usize, usize)> = Selector::new("duplicate_progress");
+pub const DUPLICATE_DONE: Selector<(u64, u64, Option<DuplicateGroups>)> = Selector::new("duplicate_done");
+// {hash} のバックグラウンドでの計算が済んだ（プレビューを更新し直す）
+pub const HASHES_READY: Selector<()> = Selector::new("hashes_ready");
+// 行の右クリックメニュー（元パス）: エクスプローラーで表示 / 既定のアプリで開く / この行を除外
+pub const REVEAL_FILE: Selector<String> = Selector::new("reveal_file");
+pub const OPEN_FILE: Selector<String> = Selector::new("open_file");
+pub const EXCLUDE_FILE: Selector<String> = Selector::new("exclude_file");
+// プレビュー行の詳細（変更の理由と適用後のフルパス）を開く/閉じる（元パス）
+pub const TOGGLE_TARGET_DETAIL: Selector<String> = Selector::new("toggle_target_detail");
+// 並び順を保ったままプレビューを確定し直す（適用範囲の変更時）
+pub const RENUMBER_PREVIEW_REQUEST: Selector<()> = Selector::new("renumber_preview_request");
+// 再読み込みボタン、または自動再読み込みで対象フォルダの外部での変更を検知した
+pub const FILES_CHANGED: Selector<()> = Selector::new("files_changed");
+// 入力欄・チェックボックスが変わった（変更前の入力、取り消し用に記録する）
+pub const RECORD_INPUT_CHANGE: Selector<InputSnapshot> = Selector::new("record_input_change");
@@ -0,0 +1,168 @@
+// 写真の撮影日時（EXIF の DateTimeOriginal）による名前付け
+//
+// プレビューの更新（入力のたび）ごとに画像を開き直さないよう、読み取った撮影日時は
+// パスごとにサイズ・更新日時と合わせてキャッシュする。
+
+use crate::transform::replace_forbidden_chars;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// EXIF を読む拡張子（それ以外のファイルは開かない）
+const IMAGE_EXTENSIONS: [&str; 8] = ["jpg", "jpeg", "tif", "tiff", "heic", "heif", "png", "webp"];
+/// `{exif}` と書式を省略したときの書式
+const DEFAULT_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// 読み取った撮影日時（サイズ・更新日時が変わっていれば使わない）
+struct CachedCapture {
+    len: u64,
+    modified: Option<SystemTime>,
+    taken: Option<NaiveDateTime>, // EXIF がなければ None
+}
+
+/// パス → 読み取った撮影日時
+static CAPTURE_CACHE: OnceLock<Mutex<HashMap<PathBuf, CachedCapture>>> = OnceLock::new();
+
+/// `{exif}` / `{exif:書式}` を含むか。
+pub fn has_exif_token(text: &str) -> bool {
+    text.contains("{exif}") || text.contains("{exif:")
+}
+
+/// `{exif:書式}` を撮影日時に置き換える（書式は strftime 形式、例 `%Y%m%d_%H%M%S`）。
+/// EXIF がなければ `fallback_mtime` に応じて更新日時を使うか空にする。書式が不正な場合も空になる。
+pub fn expand_exif_tokens(name: &str, path: &Path, fallback_mtime: bool) -> String {
+    let taken = std::fs::metadata(path).ok().and_then(|meta| {
+        cached_capture_time(path, &meta).or_else(|| if fallback_mtime { modified_time(&meta) } else { None })
+    });
+    let mut out = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find("{exif") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + "{exif".len()..];
+        let format = match after.find('}') {
+            Some(0) => DEFAULT_FORMAT,
+            Some(end) if after.starts_with(':') => &after[1..end],
+            _ => {
+                // `{exifx}` など対象外の綴りはそのまま残す
+                out.push_str("{exif");
+                rest = after;
+                continue;
+            }
+        };
+        if let Some(t) = taken {
+            let mut formatted = String::new();
+            if write!(formatted, "{}", t.format(format)).is_ok() {
+                out.push_str(&replace_forbidden_chars(&formatted));
+            }
+        }
+        rest = &after[after.find('}').map(|e| e + 1).unwrap_or(after.len())..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .map(|e| IMAGE_EXTENSIONS.contains(&e.as_str()))
+        .unwrap_or(false)
+}
+
+/// キャッシュ済みの撮影日時を返す。なければ読み取ってキャッシュに入れる。画像以外は None。
+fn cached_capture_time(path: &Path, meta: &Metadata) -> Option<NaiveDateTime> {
+    if !is_image_file(path) {
+        return None;
+    }
+    let (len, modified) = (meta.len(), meta.modified().ok());
+    let cache = CAPTURE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(cached) = cache
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(path)
+        .filter(|c| c.len == len && c.modified == modified)
+    {
+        return cached.taken;
+    }
+    let taken = capture_time(path);
+    cache
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(path.to_path_buf(), CachedCapture { len, modified, taken });
+    taken
+}
+
+/// EXIF の撮影日時（DateTimeOriginal、なければ DateTime）。EXIF なしは None。
+fn capture_time(path: &Path) -> Option<NaiveDateTime> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut reader = std::io::BufReader::new(file);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime].iter().find_map(|tag| {
+        let field = exif.get_field(*tag, exif::In::PRIMARY)?;
+        let exif::Value::Ascii(ref values) = field.value else {
+            return None;
+        };
+        let dt = exif::DateTime::from_ascii(values.first()?).ok()?;
+        NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32)?
+            .and_hms_opt(dt.hour as u32, dt.minute as u32, dt.second as u32)
+    })
+}
+
+/// ファイルの更新日時（ローカル時刻）
+fn modified_time(meta: &Metadata) -> Option<NaiveDateTime> {
+    let modified = meta.modified().ok()?;
+    Some(DateTime::<Local>::from(modified).naive_local())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::experimental::Writer;
+    use exif::{Field, In, Tag, Value};
+    use std::time::Duration;
+
+    /// 撮影日時だけを持つ TIFF を書く
+    fn write_tiff(path: &Path, taken: &str) {
+        let field = Field { tag: Tag::DateTimeOriginal, ifd_num: In::PRIMARY, value: Value::Ascii(vec![taken.as_bytes().to_vec()]) };
+        let mut writer = Writer::new();
+        writer.push_field(&field);
+        let mut buf = std::io::Cursor::new(Vec::new());
+        writer.write(&mut buf, false).expect("write exif");
+        std::fs::write(path, buf.into_inner()).expect("write");
+    }
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        std::fs::File::options().write(true).open(path).expect("open").set_modified(time).expect("set_modified");
+    }
+
+    #[test]
+    fn capture_time_is_cached_until_file_changes() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("a.tif");
+        write_tiff(&path, "2024:01:05 14:25:01");
+        let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(expand_exif_tokens("{exif:%Y%m%d}_a.tif", &path, false), "20240105_a.tif");
+
+        // サイズ・更新日時が同じなら読み直さない
+        write_tiff(&path, "2023:12:31 00:00:00");
+        set_modified(&path, mtime);
+        assert_eq!(expand_exif_tokens("{exif:%Y%m%d}", &path, false), "20240105");
+
+        // 更新日時が変われば読み直す
+        set_modified(&path, mtime + Duration::from_secs(60));
+        assert_eq!(expand_exif_tokens("{exif:%Y%m%d}", &path, false), "20231231");
+    }
+
+    #[test]
+    fn non_images_and_missing_exif_fall_back_to_mtime() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("note.txt");
+        std::fs::write(&path, "x").expect("write");
+        assert_eq!(expand_exif_tokens("{exif}note.txt", &path, false), "note.txt");
+        let expected = modified_time(&std::fs::metadata(&path).unwrap()).unwrap().format("%Y").to_string();
+        assert_eq!(expand_exif_tokens("{exif:%Y}", &path, true), expected);
+    }
+}
@@ -0,0 +1,56 @@
+// あいまい検索（検索のみモード用）
+//
+// 検索語との編集距離（1 文字の挿入・削除・置き換えの回数）が指定以下の部分を名前の中から探す。
+// 表記ゆれ・打ち間違いのあるファイルを探して直すための候補探しで、名前の変更には使わない。
+
+/// 名前の中で検索語に最も近い部分。`start`〜`end` は名前のバイト位置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FuzzyMatch {
+    pub start: usize,
+    pub end: usize,
+    pub distance: usize,
+}
+
+/// `text` の中から `pattern` との編集距離が `max_distance` 以下の部分を探し、最も近いもの（同じ距離なら先に現れるもの）を返す。
+/// 部分の始まりと終わりは自由に選べる（名前の一部に検索語が近い形で含まれていれば一致）。
+/// 何も一致しない部分まで一致とみなさないよう、距離は検索語の文字数未満に抑える。
+pub fn fuzzy_find(text: &str, pattern: &str, max_distance: usize, case_sensitive: bool) -> Option<FuzzyMatch> {
+    let fold = |c: char| if case_sensitive { c } else { c.to_lowercase().next().unwrap_or(c) };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    if pattern.is_empty() {
+        return None;
+    }
+    let text_len = text.len();
+    let text: Vec<(usize, char)> = text.char_indices().map(|(i, c)| (i, fold(c))).collect();
+    // 文字位置を名前のバイト位置にする（末尾の次は名前の長さ）
+    let byte_at = |index: usize| text.get(index).map_or(text_len, |(i, _)| *i);
+    let m = pattern.len();
+    let max_distance = max_distance.min(m - 1);
+    // 列ごとに、検索語の先頭 j 文字との距離と、その部分が始まる文字位置を持つ（始まりは自由なので 0 行目は常に 0）
+    let mut dist: Vec<usize> = (0..=m).collect();
+    let mut start: Vec<usize> = vec![0; m + 1];
+    let mut best: Option<(usize, usize, usize)> = None; // 距離, 始まり, 終わり（文字位置）
+    for (i, &(_, c)) in text.iter().enumerate() {
+        let (mut diag_dist, mut diag_start) = (dist[0], start[0]);
+        dist[0] = 0;
+        start[0] = i + 1;
+        for j in 1..=m {
+            let (up_dist, up_start) = (dist[j], start[j]);
+            let substitute = diag_dist + usize::from(pattern[j - 1] != c);
+            let (mut d, mut s) = (substitute, diag_start);
+            if dist[j - 1] + 1 < d {
+                (d, s) = (dist[j - 1] + 1, start[j - 1]);
+            }
+            if up_dist + 1 < d {
+                (d, s) = (up_dist + 1, up_start);
+            }
+            dist[j] = d;
+            start[j] = s;
+            (diag_dist, diag_start) = (up_dist, up_start);
+        }
+        if dist[m] <= max_distance && best.is_none_or(|(d, _, _)| dist[m] < d) {
+            best = Some((dist[m], start[m], i + 1));
+        }
+    }
+    best.map(|(distance, s, e)| FuzzyMatch { start: byte_at(s), end: byte_at(e), distance })
+}
@@ -0,0 +1,281 @@
+// 適用履歴のログ（CSV）と、ログからの取り消し
+//
+// ログは設定ディレクトリの rename_log.csv に追記する。1 行 1 ファイルで、列は
+//   batch_id,timestamp,op,original_path,new_path,status,error
+// - batch_id: 適用 1 回ごとの ID（開始時刻のミリ秒）
+// - timestamp: UNIX 時刻（秒）
+// - op: rename / copy / hardlink / symlink
+// - status: ok / error（error のときは error 列に理由）
+
+use crate::config::config_dir;
+use crate::state::{FileEntry, LogBatch, RenameFailure};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOG_FILE_NAME: &str = "rename_log.csv";
+const LOG_HEADER: &str = "batch_id,timestamp,op,original_path,new_path,status,error";
+
+/// 同一プロセス内の書き込みを直列化する（バッチ単位で 1 回の write にまとめて追記する）
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+/// ログファイルのパス
+pub fn log_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join(LOG_FILE_NAME))
+}
+
+/// ログの 1 行
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub batch_id: String,
+    pub timestamp: u64,
+    pub op: String,
+    pub original_path: String,
+    pub new_path: String,
+    pub ok: bool,
+}
+
+/// 適用 1 回分（`files` の各ファイルを `dest` へ）の結果をログに追記する。
+pub fn append_batch(
+    op: &str,
+    files: &[FileEntry],
+    dest: impl Fn(&FileEntry) -> PathBuf,
+    failures: &[RenameFailure],
+) -> std::io::Result<()> {
+    let Some(path) = log_path() else {
+        return Ok(());
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let batch_id = now.as_millis().to_string();
+    let failed: std::collections::HashMap<&str, &str> = failures
+        .iter()
+        .map(|f| (f.original_path.as_str(), f.reason.as_str()))
+        .collect();
+
+    let mut buf = String::new();
+    for f in files {
+        let error = failed.get(f.original_path.as_str()).copied();
+        let fields = [
+            batch_id.clone(),
+            now.as_secs().to_string(),
+            op.to_string(),
+            f.original_path.clone(),
+            dest(f).to_string_lossy().to_string(),
+            if error.is_some() { "error" } else { "ok" }.to_string(),
+            error.unwrap_or_default().to_string(),
+        ];
+        let line: Vec<String> = fields.iter().map(|s| csv_escape(s)).collect();
+        buf.push_str(&line.join(","));
+        buf.push('\n');
+    }
+
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let is_new = !path.exists();
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    if is_new {
+        buf.insert_str(0, &format!("{}\n", LOG_HEADER));
+    }
+    // 追記モードでバッチ全体を 1 回で書き込み、他のプロセスの追記と行が混ざらないようにする
+    file.write_all(buf.as_bytes())
+}
+
+/// ログを読み込む（ヘッダーと壊れた行は読み飛ばす）。
+pub fn read_log() -> Vec<LogRecord> {
+    let Some(path) = log_path() else {
+        return Vec::new();
+    };
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    parse_delimited(&text, ',')
+        .into_iter()
+        .filter(|fields| fields.len() == 7 && fields[0] != "batch_id")
+        .filter_map(|fields| {
+            Some(LogRecord {
+                batch_id: fields[0].clone(),
+                timestamp: fields[1].parse().ok()?,
+                op: fields[2].clone(),
+                original_path: fields[3].clone(),
+                new_path: fields[4].clone(),
+                ok: fields[5] == "ok",
+            })
+        })
+        .collect()
+}
+
+/// ログをバッチ単位にまとめる（新しい順）。
+pub fn list_batches() -> Vec<LogBatch> {
+    let mut batches: Vec<LogBatch> = Vec::new();
+    for r in read_log() {
+        match batches.iter_mut().find(|b| b.batch_id == r.batch_id) {
+            Some(b) => {
+                b.count += 1;
+                if !r.ok {
+                    b.errors += 1;
+                }
+            }
+            None => batches.push(LogBatch {
+                batch_id: r.batch_id.clone(),
+                timestamp: format_timestamp(r.timestamp),
+                op: r.op.clone(),
+                folder: Path::new(&r.original_path)
+                    .parent()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                count: 1,
+                errors: if r.ok { 0 } else { 1 },
+            }),
+        }
+    }
+    batches.reverse();
+    batches
+}
+
+/// 取り消しの計画
+pub struct UndoPlan {
+    pub entries: Vec<FileEntry>,
+    pub destinations: HashMap<String, PathBuf>, // 現在のパス → 戻し先のパス
+    pub skipped: usize,
+}
+
+/// 指定バッチを取り消すためのリネーム（新しいパス → 元のパス）を組み立てる。
+/// 成功したリネームのみ対象。新しいパスがもう無い、または元のパスが使われている項目は飛ばし、その件数も返す。
+pub fn plan_undo(batch_id: &str) -> Result<UndoPlan, String> {
+    let records: Vec<LogRecord> = read_log()
+        .into_iter()
+        .filter(|r| r.batch_id == batch_id && r.ok)
+        .collect();
+    if records.is_empty() {
+        return Err("取り消せる項目がありません。".to_string());
+    }
+    if records.iter().any(|r| r.op != "rename") {
+        return Err("コピー・リンク作成の履歴は取り消せません（作成したファイル・リンクを削除してください）。".to_string());
+    }
+    let mut entries = Vec::new();
+    let mut destinations = HashMap::new();
+    let mut skipped = 0usize;
+    let mut seen: HashSet<String> = HashSet::new();
+    for r in records {
+        let original = Path::new(&r.original_path);
+        let current = Path::new(&r.new_path);
+        if !current.exists() || original.exists() || !seen.insert(r.original_path.clone()) {
+            skipped += 1;
+            continue;
+        }
+        let original_name = original
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // フラット化では元のフォルダが異なるため、戻し先のパスを記録どおりに指定する
+        destinations.insert(r.new_path.clone(), original.to_path_buf());
+        entries.push(FileEntry {
+            original_path: r.new_path.clone(),
+            root: String::new(),
+            new_name: original_name.clone(),
+            name_template: original_name,
+            search_pattern: String::new(),
+            replace_pattern: String::new(),
+            case_sensitive: false,
+            use_regex: false,
+            regex_unicode: true,
+            regex_dot_all: false,
+            whole_match: false,
+            replace_count: crate::state::ReplaceCount::All,
+            replace_nth: 1,
+            conflicts_existing: false,
+            path_error: String::new(),
+            name_note: String::new(),
+            target_path: String::new(),
+            show_target: false,
+            in_range: true,
+            selected: true,
+            duplicate_group: 0,
+            audio_tags: None,
+            group_header: String::new(),
+            read_only: false,
+            change_reason: String::new(),
+            search_only: false,
+            auto_numbered: false,
+            overwrite_existing: false,
+            fuzzy_max_distance: None,
+            hash_error: String::new(),
+            name_collision: false,
+        });
+    }
+    if entries.is_empty() {
+        return Err(format!("取り消せる項目がありません（スキップ {} 件）。", skipped));
+    }
+    Ok(UndoPlan { entries, destinations, skipped })
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// 引用符（`""` エスケープ、改行を含むフィールド）に対応した簡易 CSV パーサ（区切り文字は `delimiter`）
+pub fn parse_delimited(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// UNIX 時刻（秒）を "YYYY-MM-DD hh:mm:ss UTC" にする。
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // 1970-01-01 からの日数をグレゴリオ暦の年月日に変換する
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
@@ -0,0 +1,290 @@
+// 入力欄・チェックボックスの変更の取り消し/やり直し（Ctrl+Z / Ctrl+Y）
+//
+// ファイル操作の取り消し（適用履歴）とは別で、セッション中の条件の変更だけを対象にする。
+// 変更のたびに変更前の入力をスナップショットとして積み、取り消しで戻す。
+// 同じ入力欄への連続した文字入力は 1 回の変更としてまとめる。
+
+use crate::state::{AppState, ReplaceCount, ReplaceRule, Separator, UnicodeNormalization};
+use druid::im::Vector;
+use druid::Data;
+
+/// 取り消せる変更の最大回数
+const INPUT_HISTORY_LIMIT: usize = 100;
+
+/// 取り消しの対象になる入力（検索・置換・除外などの入力欄とチェックボックス）
+#[derive(Clone, Data)]
+pub struct InputSnapshot {
+    search_pattern: String,
+    replace_pattern: String,
+    extra_rules: Vector<ReplaceRule>,
+    exclude_pattern: String,
+    disabled_excludes: Vector<String>,
+    min_size: String,
+    max_size: String,
+    prefix: String,
+    suffix: String,
+    insert_text: String,
+    insert_position: isize,
+    swap_parts: bool,
+    swap_delimiter: String,
+    swap_joiner: String,
+    swap_split_first: bool,
+    reformat_date: bool,
+    date_input_format: String,
+    date_output_format: String,
+    use_script: bool,
+    script_expr: String,
+    case_sensitive: bool,
+    use_regex: bool,
+    fuzzy_search: bool,
+    fuzzy_distance: usize,
+    regex_unicode: bool,
+    regex_dot_all: bool,
+    whole_match: bool,
+    replace_count: ReplaceCount,
+    replace_nth: usize,
+    include_subdirectories: bool,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    exclude_hidden: bool,
+    use_audio_tags: bool,
+    flatten: bool,
+    sort_into_subfolders: bool,
+    match_full_path: bool,
+    strip_trailing_dots: bool,
+    strip_copy_suffixes: bool,
+    renumber: bool,
+    renumber_whole: bool,
+    reverse_numbering: bool,
+    strip_existing_number: bool,
+    lowercase_extension: bool,
+    normalize_extension_aliases: bool,
+    compound_extensions: bool,
+    fullwidth_to_halfwidth: bool,
+    normalize_separators: bool,
+    separator_from: Separator,
+    separator_to: Separator,
+    separator_collapse: bool,
+    separator_trim: bool,
+    strip_diacritics: bool,
+    transliterate_ascii: bool,
+    unicode_normalization: UnicodeNormalization,
+    restrict_chars: bool,
+}
+
+impl InputSnapshot {
+    pub fn capture(data: &AppState) -> Self {
+        Self {
+            search_pattern: data.search_pattern.clone(),
+            replace_pattern: data.replace_pattern.clone(),
+            extra_rules: data.extra_rules.clone(),
+            exclude_pattern: data.exclude_pattern.clone(),
+            disabled_excludes: data.disabled_excludes.clone(),
+            min_size: data.min_size.clone(),
+            max_size: data.max_size.clone(),
+            prefix: data.prefix.clone(),
+            suffix: data.suffix.clone(),
+            insert_text: data.insert_text.clone(),
+            insert_position: data.insert_position,
+            swap_parts: data.swap_parts,
+            swap_delimiter: data.swap_delimiter.clone(),
+            swap_joiner: data.swap_joiner.clone(),
+            swap_split_first: data.swap_split_first,
+            reformat_date: data.reformat_date,
+            date_input_format: data.date_input_format.clone(),
+            date_output_format: data.date_output_format.clone(),
+            use_script: data.use_script,
+            script_expr: data.script_expr.clone(),
+            case_sensitive: data.case_sensitive,
+            use_regex: data.use_regex,
+            fuzzy_search: data.fuzzy_search,
+            fuzzy_distance: data.fuzzy_distance,
+            regex_unicode: data.regex_unicode,
+            regex_dot_all: data.regex_dot_all,
+            whole_match: data.whole_match,
+            replace_count: data.replace_count,
+            replace_nth: data.replace_nth,
+            include_subdirectories: data.include_subdirectories,
+            max_depth: data.max_depth,
+            follow_symlinks: data.follow_symlinks,
+            exclude_hidden: data.exclude_hidden,
+            use_audio_tags: data.use_audio_tags,
+            flatten: data.flatten,
+            sort_into_subfolders: data.sort_into_subfolders,
+            match_full_path: data.match_full_path,
+            strip_trailing_dots: data.strip_trailing_dots,
+            strip_copy_suffixes: data.strip_copy_suffixes,
+            renumber: data.renumber,
+            renumber_whole: data.renumber_whole,
+            reverse_numbering: data.reverse_numbering,
+            strip_existing_number: data.strip_existing_number,
+            lowercase_extension: data.lowercase_extension,
+            normalize_extension_aliases: data.normalize_extension_aliases,
+            compound_extensions: data.compound_extensions,
+            fullwidth_to_halfwidth: data.fullwidth_to_halfwidth,
+            normalize_separators: data.normalize_separators,
+            separator_from: data.separator_from,
+            separator_to: data.separator_to,
+            separator_collapse: data.separator_collapse,
+            separator_trim: data.separator_trim,
+            strip_diacritics: data.strip_diacritics,
+            transliterate_ascii: data.transliterate_ascii,
+            unicode_normalization: data.unicode_normalization,
+            restrict_chars: data.restrict_chars,
+        }
+    }
+
+    fn restore(self, data: &mut AppState) {
+        data.search_pattern = self.search_pattern;
+        data.replace_pattern = self.replace_pattern;
+        data.extra_rules = self.extra_rules;
+        data.exclude_pattern = self.exclude_pattern;
+        data.disabled_excludes = self.disabled_excludes;
+        data.min_size = self.min_size;
+        data.max_size = self.max_size;
+        data.prefix = self.prefix;
+        data.suffix = self.suffix;
+        data.insert_text = self.insert_text;
+        data.insert_position = self.insert_position;
+        data.swap_parts = self.swap_parts;
+        data.swap_delimiter = self.swap_delimiter;
+        data.swap_joiner = self.swap_joiner;
+        data.swap_split_first = self.swap_split_first;
+        data.reformat_date = self.reformat_date;
+        data.date_input_format = self.date_input_format;
+        data.date_output_format = self.date_output_format;
+        data.use_script = self.use_script;
+        data.script_expr = self.script_expr;
+        data.case_sensitive = self.case_sensitive;
+        data.use_regex = self.use_regex;
+        data.fuzzy_search = self.fuzzy_search;
+        data.fuzzy_distance = self.fuzzy_distance;
+        data.regex_unicode = self.regex_unicode;
+        data.regex_dot_all = self.regex_dot_all;
+        data.whole_match = self.whole_match;
+        data.replace_count = self.replace_count;
+        data.replace_nth = self.replace_nth;
+        data.include_subdirectories = self.include_subdirectories;
+        data.max_depth = self.max_depth;
+        data.follow_symlinks = self.follow_symlinks;
+        data.exclude_hidden = self.exclude_hidden;
+        data.use_audio_tags = self.use_audio_tags;
+        data.flatten = self.flatten;
+        data.sort_into_subfolders = self.sort_into_subfolders;
+        data.match_full_path = self.match_full_path;
+        data.strip_trailing_dots = self.strip_trailing_dots;
+        data.strip_copy_suffixes = self.strip_copy_suffixes;
+        data.renumber = self.renumber;
+        data.renumber_whole = self.renumber_whole;
+        data.reverse_numbering = self.reverse_numbering;
+        data.strip_existing_number = self.strip_existing_number;
+        data.lowercase_extension = self.lowercase_extension;
+        data.normalize_extension_aliases = self.normalize_extension_aliases;
+        data.compound_extensions = self.compound_extensions;
+        data.fullwidth_to_halfwidth = self.fullwidth_to_halfwidth;
+        data.normalize_separators = self.normalize_separators;
+        data.separator_from = self.separator_from;
+        data.separator_to = self.separator_to;
+        data.separator_collapse = self.separator_collapse;
+        data.separator_trim = self.separator_trim;
+        data.strip_diacritics = self.strip_diacritics;
+        data.transliterate_ascii = self.transliterate_ascii;
+        data.unicode_normalization = self.unicode_normalization;
+        data.restrict_chars = self.restrict_chars;
+    }
+
+    /// 変更が 1 つの入力欄の文字だけなら、その欄の名前を返す（連続した入力をまとめるため）。
+    fn single_text_change(&self, other: &Self) -> Option<&'static str> {
+        let mut without_text = other.clone();
+        without_text.search_pattern = self.search_pattern.clone();
+        without_text.replace_pattern = self.replace_pattern.clone();
+        without_text.exclude_pattern = self.exclude_pattern.clone();
+        without_text.min_size = self.min_size.clone();
+        without_text.max_size = self.max_size.clone();
+        without_text.prefix = self.prefix.clone();
+        without_text.suffix = self.suffix.clone();
+        without_text.insert_text = self.insert_text.clone();
+        without_text.swap_delimiter = self.swap_delimiter.clone();
+        without_text.swap_joiner = self.swap_joiner.clone();
+        without_text.date_input_format = self.date_input_format.clone();
+        without_text.date_output_format = self.date_output_format.clone();
+        without_text.script_expr = self.script_expr.clone();
+        if !without_text.same(self) {
+            return None;
+        }
+        let changed: Vec<&'static str> = [
+            ("search_pattern", self.search_pattern != other.search_pattern),
+            ("replace_pattern", self.replace_pattern != other.replace_pattern),
+            ("exclude_pattern", self.exclude_pattern != other.exclude_pattern),
+            ("min_size", self.min_size != other.min_size),
+            ("max_size", self.max_size != other.max_size),
+            ("prefix", self.prefix != other.prefix),
+            ("suffix", self.suffix != other.suffix),
+            ("insert_text", self.insert_text != other.insert_text),
+            ("swap_delimiter", self.swap_delimiter != other.swap_delimiter),
+            ("swap_joiner", self.swap_joiner != other.swap_joiner),
+            ("date_input_format", self.date_input_format != other.date_input_format),
+            ("date_output_format", self.date_output_format != other.date_output_format),
+            ("script_expr", self.script_expr != other.script_expr),
+        ]
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(field, _)| field)
+        .collect();
+        match changed.as_slice() {
+            [field] => Some(field),
+            _ => None,
+        }
+    }
+}
+
+/// 入力が `before` から現在の値に変わったことを記録する。取り消し/やり直しによる変更は記録しない。
+pub fn record_input_change(data: &mut AppState, before: InputSnapshot) {
+    let after = InputSnapshot::capture(data);
+    if data.input_restoring {
+        data.input_restoring = false;
+        return;
+    }
+    if before.same(&after) {
+        return;
+    }
+    let field = before.single_text_change(&after).unwrap_or_default();
+    // 同じ入力欄への続けての入力は、入力を始める前の状態だけを残す
+    if field.is_empty() || field != data.input_last_field || !data.input_redo.is_empty() {
+        data.input_undo.push_back(before);
+        if data.input_undo.len() > INPUT_HISTORY_LIMIT {
+            data.input_undo.pop_front();
+        }
+    }
+    data.input_last_field = field.to_string();
+    data.input_redo.clear();
+}
+
+/// 直前の入力の変更を取り消す。
+pub fn undo_input(data: &mut AppState) {
+    let Some(snapshot) = data.input_undo.pop_back() else {
+        data.set_status("取り消せる入力の変更はありません");
+        return;
+    };
+    data.input_redo.push_back(InputSnapshot::capture(data));
+    restore(data, snapshot);
+    data.set_status(format!("入力の変更を取り消しました（あと {} 回取り消せます）", data.input_undo.len()));
+}
+
+/// 取り消した入力の変更をやり直す。
+pub fn redo_input(data: &mut AppState) {
+    let Some(snapshot) = data.input_redo.pop_back() else {
+        data.set_status("やり直せる入力の変更はありません");
+        return;
+    };
+    data.input_undo.push_back(InputSnapshot::capture(data));
+    restore(data, snapshot);
+    data.set_status(format!("入力の変更をやり直しました（あと {} 回やり直せます）", data.input_redo.len()));
+}
+
+fn restore(data: &mut AppState, snapshot: InputSnapshot) {
+    // 戻した変更を記録し直さないよう、次の 1 回の記録を読み飛ばす
+    data.input_restoring = !snapshot.same(&InputSnapshot::capture(data));
+    data.input_last_field = String::new();
+    snapshot.restore(data);
+}
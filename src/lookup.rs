@@ -0,0 +1,87 @@
+// 置換表（旧→新の文字列の対応表、2 列の CSV/TSV）による一括置換
+//
+// 1 行に「置換前,置換後」（TSV ならタブ区切り）。`#` で始まる行と空行は読み飛ばす。
+// 置換前が同じ行が複数あれば後の行を使う。
+
+use crate::history::parse_delimited;
+use crate::preview::describe_regex_error;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// 読み込んだ置換表
+#[derive(Debug)]
+pub struct LookupTable {
+    pub source: String, // 読み込んだファイル名（表示用）
+    pub skipped: usize, // 列が足りない・置換前が空のため読み飛ばした行数
+    pairs: HashMap<String, String>,
+    re: Regex,
+}
+
+impl LookupTable {
+    /// ファイルから読み込む。拡張子が tsv か、最初の行にタブがあればタブ区切りとして扱う。
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("置換表を読み込めません: {}", e))?;
+        let source = path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let is_tsv = path
+            .extension()
+            .map(|e| e.eq_ignore_ascii_case("tsv"))
+            .unwrap_or(false);
+        let first_line = text.lines().next().unwrap_or("");
+        let delimiter = if is_tsv || first_line.contains('\t') { '\t' } else { ',' };
+        Self::parse(&text, delimiter, source)
+    }
+
+    pub fn parse(text: &str, delimiter: char, source: String) -> Result<Self, String> {
+        let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+        let mut pairs = HashMap::new();
+        let mut skipped = 0usize;
+        for row in parse_delimited(text, delimiter) {
+            if row.iter().all(|f| f.trim().is_empty()) || row[0].starts_with('#') {
+                continue;
+            }
+            match row.as_slice() {
+                [from, to, ..] if !from.is_empty() => {
+                    pairs.insert(from.clone(), to.clone());
+                }
+                _ => skipped += 1,
+            }
+        }
+        if pairs.is_empty() {
+            return Err(format!("置換表 {} に有効な行がありません（1 行に「置換前,置換後」）", source));
+        }
+        // 同じ位置で複数の置換前が一致する場合は長いほうを優先する（正規表現の選択は先に書いたものが勝つ）
+        let mut keys: Vec<&String> = pairs.keys().collect();
+        keys.sort_by(|a, b| b.chars().count().cmp(&a.chars().count()).then_with(|| a.cmp(b)));
+        let pattern = keys.iter().map(|k| regex::escape(k)).collect::<Vec<_>>().join("|");
+        let re = Regex::new(&pattern)
+            .map_err(|e| format!("置換表 {} を処理できません: {}", source, describe_regex_error(&e, true)))?;
+        Ok(Self { source, skipped, pairs, re })
+    }
+
+    /// 置換ルールの件数
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// 読み込み結果の表示（例「置換表 map.csv を読み込み: 120 件（読み飛ばし 2 行）」）
+    pub fn summary(&self) -> String {
+        if self.skipped > 0 {
+            format!("置換表 {} を読み込み: {} 件（読み飛ばし {} 行）", self.source, self.len(), self.skipped)
+        } else {
+            format!("置換表 {} を読み込み: {} 件", self.source, self.len())
+        }
+    }
+
+    /// 名前の中の置換前の文字列をすべて置換後に置き換える（左から順に、重ならないように）。
+    pub fn apply(&self, name: &str) -> String {
+        self.re
+            .replace_all(name, |caps: &regex::Captures| {
+                self.pairs.get(&caps[0]).cloned().unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+}
@@ -7,11 +7,33 @@ mod rename;
 mod widgets;
 mod controller;
 mod ui;
+mod transform;
+mod theme;
+mod tags;
+mod exif_date;
+mod name_date;
+mod lookup;
+mod duplicates;
+mod content_hash;
+mod script;
+mod fuzzy;
+mod plan;
+mod input_history;
+mod watch;
+mod paths;
+mod config;
+mod history;
+mod cli;
 
-use druid::{AppLauncher, WindowDesc};
+use clap::Parser;
+use cli::CliArgs;
+use config::{Settings, WindowGeometry};
+use paths::folder_to_open;
+use druid::{AppLauncher, Size, WindowDesc};
 use tracing_subscriber::EnvFilter;
 use state::AppState;
 use ui::build_ui;
+use widgets::clamp_split_ratio;
 
 pub fn main() {
     let filter = if let Ok(s) = std::env::var("RUST_LOG") {
@@ -19,15 +41,64 @@ pub fn main() {
     } else {
         EnvFilter::new("filename_change=debug,druid=warn,druid_shell=off")
     };
-    tracing_subscriber::fmt().with_env_filter(filter).with_target(true).init();
+    // ログは標準エラーへ（ヘッドレスモードの標準出力を結果だけにするため）
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(true)
+        .with_writer(std::io::stderr)
+        .init();
 
+    // --dir 指定時は GUI を起動せずにヘッドレスで実行する
+    let args = CliArgs::parse();
+    if args.dir.is_some() {
+        attach_parent_console();
+        std::process::exit(cli::run(args));
+    }
+
+    // 前回終了時のウィンドウサイズを復元する（位置は接続中のモニターを確認してから controller で復元）
+    let settings = Settings::load();
+    let window_size = WindowGeometry::load(&settings)
+        .map(|g| g.restored_size())
+        .unwrap_or(Size::new(900.0, 600.0));
     let main_window = WindowDesc::new(build_ui())
         .title("ファイル名一括変更")
-        .window_size((900.0, 600.0));
-    let initial_state = AppState::new();
+        .window_size(window_size);
+    let mut initial_state = AppState::new();
+    initial_state.dark_mode = settings.get("dark_mode").unwrap_or(true);
+    initial_state.auto_reload = settings.get("auto_reload").unwrap_or(false);
+    initial_state.backup_before_apply = settings.get("backup_before_apply").unwrap_or(false);
+    if let Some(handling) = settings.get("read_only_handling") {
+        initial_state.read_only_handling = handling;
+    }
+    if let Some(threshold) = settings.get("large_batch_threshold") {
+        initial_state.large_batch_threshold = threshold;
+    }
+    if let Some(threads) = settings.get::<usize>("rename_threads") {
+        initial_state.rename_threads = threads.max(1);
+    }
+    initial_state.split_ratio = clamp_split_ratio(settings.get("split_ratio").unwrap_or(0.5));
+    // 起動時に渡されたフォルダはウィンドウを開いてから読み込む（controller の WindowConnected）
+    if let Some(arg) = &args.open {
+        match folder_to_open(arg) {
+            Ok(dir) => initial_state.selected_dir = dir.to_string_lossy().to_string(),
+            Err(e) => initial_state.set_status(format!("指定されたフォルダを開けません: {}", e)),
+        }
+    }
     AppLauncher::with_window(main_window)
         .launch(initial_state)
         .expect("Failed to launch application");
 }
 
 
+
+/// リリースビルドの Windows ではコンソールを持たないため、起動元のコンソールに出力をつなぐ。
+#[cfg(windows)]
+fn attach_parent_console() {
+    use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_parent_console() {}
@@ -0,0 +1,170 @@
+// ファイル名に含まれる日付の書き換え（例 `20240105_meeting.txt` → `2024-01-05_meeting.txt`）
+//
+// 元の書式（strftime 形式）に合う部分を名前から探し、日付として読めたものを新しい書式で書き直す。
+// 撮影日時・更新日時ではなく、名前に書かれている日付を使う。
+
+use crate::transform::replace_forbidden_chars;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+use std::fmt::Write as _;
+
+/// コンパイル済みの日付の書き換え
+pub struct DateReformat {
+    re: Regex,
+    input: String,
+    output: String,
+    has_time: bool, // 元の書式に時刻を含む
+}
+
+impl DateReformat {
+    /// 元の書式から名前の中を探す正規表現を作る。年・月・日のない書式や、対応していない指定はエラー。
+    pub fn new(input: &str, output: &str) -> Result<Self, String> {
+        if output.is_empty() {
+            return Err("新しい書式が空です".to_string());
+        }
+        if StrftimeItems::new(output).any(|item| matches!(item, Item::Error)) {
+            return Err(format!("新しい書式が不正です: {}", output));
+        }
+        let mut pattern = String::new();
+        let (mut year, mut month, mut day, mut has_time) = (false, false, false, false);
+        let mut chars = input.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                pattern.push_str(&regex::escape(&c.to_string()));
+                continue;
+            }
+            // `%-d` のようなゼロ埋めなしの指定は 1〜2 桁にする
+            let mut spec = chars.next().ok_or_else(|| "元の書式の末尾の % が不完全です".to_string())?;
+            let unpadded = spec == '-';
+            if unpadded {
+                spec = chars.next().ok_or_else(|| "元の書式の末尾の %- が不完全です".to_string())?;
+            }
+            let digits = |n: usize| if unpadded { r"\d{1,2}".to_string() } else { format!(r"\d{{{}}}", n) };
+            let part = match spec {
+                'Y' => {
+                    year = true;
+                    r"\d{4}".to_string()
+                }
+                'y' => {
+                    year = true;
+                    digits(2)
+                }
+                'm' => {
+                    month = true;
+                    digits(2)
+                }
+                'b' | 'h' => {
+                    month = true;
+                    "[A-Za-z]{3}".to_string()
+                }
+                'B' => {
+                    month = true;
+                    "[A-Za-z]{3,9}".to_string()
+                }
+                'd' => {
+                    day = true;
+                    digits(2)
+                }
+                'e' => {
+                    day = true;
+                    r" ?\d{1,2}".to_string()
+                }
+                'H' | 'I' | 'M' | 'S' => {
+                    has_time = true;
+                    digits(2)
+                }
+                'p' => "(?i:am|pm)".to_string(),
+                '%' => "%".to_string(),
+                _ => return Err(format!("元の書式の %{} には対応していません（%Y %y %m %b %B %d %e %H %I %M %S %p）", spec)),
+            };
+            pattern.push_str(&part);
+        }
+        if !(year && month && day) {
+            return Err("元の書式には年・月・日（例 %Y%m%d）が必要です".to_string());
+        }
+        let re = Regex::new(&pattern).map_err(|e| e.to_string())?;
+        Ok(Self { re, input: input.to_string(), output: output.to_string(), has_time })
+    }
+
+    /// 名前の中で最初に日付として読める部分を新しい書式に書き換える。読める日付がなければ None。
+    /// 前後に数字が続く部分（`120240105` の途中など）は日付とみなさない。
+    pub fn apply(&self, name: &str) -> Option<String> {
+        let mut at = 0;
+        while let Some(m) = self.re.find_at(name, at) {
+            let touches_digit = name[..m.start()].ends_with(|c: char| c.is_ascii_digit())
+                && m.as_str().starts_with(|c: char| c.is_ascii_digit())
+                || name[m.end()..].starts_with(|c: char| c.is_ascii_digit())
+                    && m.as_str().ends_with(|c: char| c.is_ascii_digit());
+            if !touches_digit {
+                if let Some(formatted) = self.parse(m.as_str()).and_then(|dt| self.format(dt)) {
+                    return Some(format!("{}{}{}", &name[..m.start()], formatted, &name[m.end()..]));
+                }
+            }
+            // 重なる位置からも探し直す
+            at = m.start() + name[m.start()..].chars().next().map_or(1, char::len_utf8);
+        }
+        None
+    }
+
+    fn parse(&self, text: &str) -> Option<NaiveDateTime> {
+        if self.has_time {
+            NaiveDateTime::parse_from_str(text, &self.input).ok()
+        } else {
+            NaiveDate::parse_from_str(text, &self.input).ok().map(|d| d.and_time(NaiveTime::MIN))
+        }
+    }
+
+    fn format(&self, dt: NaiveDateTime) -> Option<String> {
+        let mut formatted = String::new();
+        write!(formatted, "{}", dt.format(&self.output)).ok()?;
+        Some(replace_forbidden_chars(&formatted))
+    }
+}
+
+/// 日付の書き換えの書式の誤り（入力中の表示用、問題なければ空）
+pub fn describe_date_formats(input: &str, output: &str) -> String {
+    DateReformat::new(input, output).err().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reformat(input: &str, output: &str, name: &str) -> Option<String> {
+        DateReformat::new(input, output).expect("format").apply(name)
+    }
+
+    #[test]
+    fn rewrites_embedded_date() {
+        assert_eq!(reformat("%Y%m%d", "%Y-%m-%d", "20240105_meeting.txt").as_deref(), Some("2024-01-05_meeting.txt"));
+        assert_eq!(reformat("%d.%m.%Y", "%Y%m%d", "Rechnung 05.01.2024.pdf").as_deref(), Some("Rechnung 20240105.pdf"));
+        assert_eq!(reformat("%b %d %Y", "%Y-%m-%d", "notes Jan 05 2024.md").as_deref(), Some("notes 2024-01-05.md"));
+    }
+
+    #[test]
+    fn skips_invalid_dates_and_digit_runs() {
+        // 20241305 は 13 月のため日付でない。2 つ目の日付を使う
+        assert_eq!(reformat("%Y%m%d", "%Y-%m-%d", "20241305_20240106.txt").as_deref(), Some("20241305_2024-01-06.txt"));
+        assert_eq!(reformat("%Y%m%d", "%Y-%m-%d", "IMG_1202401051.jpg"), None);
+        assert_eq!(reformat("%Y%m%d", "%Y-%m-%d", "meeting.txt"), None);
+    }
+
+    #[test]
+    fn time_and_forbidden_characters() {
+        assert_eq!(
+            reformat("%Y%m%d_%H%M%S", "%Y-%m-%d %H:%M", "20240105_142501.jpg").as_deref(),
+            Some("2024-01-05 14_25.jpg")
+        );
+        assert_eq!(reformat("%Y%m%d", "%Y/%m/%d %H", "20240105.txt").as_deref(), Some("2024_01_05 00.txt"));
+    }
+
+    #[test]
+    fn rejects_incomplete_or_unknown_formats() {
+        assert!(DateReformat::new("%Y%m", "%Y").is_err());
+        assert!(DateReformat::new("%Y%m%d%Q", "%Y").is_err());
+        assert!(DateReformat::new("%Y%m%d", "").is_err());
+        assert!(DateReformat::new("%Y%m%d", "%Y-%").is_err());
+        assert_eq!(describe_date_formats("%Y%m%d", "%Y-%m-%d"), "");
+    }
+}
@@ -0,0 +1,143 @@
+// Windows の長いパス（MAX_PATH 超）・UNC パスの扱い
+
+use std::path::{Component, Path, PathBuf};
+
+/// Windows で拡張長プレフィックスが必要になるパスの長さ（MAX_PATH）
+const MAX_PATH: usize = 260;
+
+/// ファイル操作に渡すパスを返す。Windows で MAX_PATH 以上の絶対パスには
+/// 拡張長プレフィックス（`\\?\` / `\\?\UNC\`）を付ける。Windows 以外では何もしない。
+pub fn to_extended_path(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(extended_path_string(&path.to_string_lossy()))
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// 拡張長プレフィックスを付けたパス文字列を返す（プラットフォームに依存しない文字列処理）。
+/// 短いパス・相対パス・既にプレフィックス付きのパスはそのまま返す。
+pub fn extended_path_string(path: &str) -> String {
+    if path.starts_with(r"\\?\") || path.chars().count() < MAX_PATH {
+        return path.to_string();
+    }
+    // 拡張長パスでは `/` が区切りとして解釈されないため `\` に揃える
+    let path = path.replace('/', "\\");
+    if let Some(rest) = path.strip_prefix(r"\\") {
+        // UNC: \\server\share\... → \\?\UNC\server\share\...
+        format!(r"\\?\UNC\{}", rest)
+    } else if path.chars().nth(1) == Some(':') && path.chars().nth(2) == Some('\\') {
+        format!(r"\\?\{}", path)
+    } else {
+        path
+    }
+}
+
+/// 拡張長プレフィックスを外し、画面表示用の通常のパス文字列に戻す。
+pub fn strip_extended_prefix(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+        rest.to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+/// 起動時に渡されたパス（エクスプローラーの右クリックメニューなど）から、開くフォルダを決める。
+/// ファイルならそのファイルのあるフォルダを開く。相対パスは現在のフォルダから解決する。
+pub fn folder_to_open(arg: &str) -> Result<PathBuf, String> {
+    // `"%1"` で渡されるドライブ直下（`"C:\"`）は、末尾の `\"` が引用符のエスケープとみなされ `C:"` で届く
+    let mut trimmed = arg.trim().trim_matches('"').to_string();
+    if trimmed.is_empty() {
+        return Err("パスが空です".to_string());
+    }
+    // `C:` だけではそのドライブの現在のフォルダになるため、ドライブ直下にする
+    if trimmed.len() == 2 && trimmed.ends_with(':') {
+        trimmed.push('\\');
+    }
+    let path = std::path::absolute(Path::new(&trimmed)).map_err(|e| format!("{}: {}", trimmed, e))?;
+    let meta = std::fs::metadata(to_extended_path(&path)).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if meta.is_dir() {
+        return Ok(path);
+    }
+    path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| format!("{}: フォルダを開けません", path.display()))
+}
+
+/// 適用前に警告を出す変更件数の既定値（これ以上なら確認ダイアログの設定に関係なく確認する。設定で変更可）
+pub const LARGE_BATCH_WARNING_THRESHOLD: usize = 1000;
+
+/// 中身を変更すると OS やアプリが壊れるおそれのあるフォルダ（配下も含む）
+const SENSITIVE_DIRS: &[&str] = &[
+    r"C:\Windows",
+    r"C:\Program Files",
+    r"C:\Program Files (x86)",
+    r"C:\ProgramData",
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/lib",
+    "/proc",
+    "/sbin",
+    "/sys",
+    "/usr",
+    "/System",
+    "/Library",
+    "/Applications",
+];
+
+/// 対象フォルダがルート・ホーム・システムフォルダなら、その理由を返す。
+/// シンボリックリンクや `..` は解決してから判定する。
+pub fn sensitive_dir_reason(dir: &Path) -> Option<String> {
+    let resolved = dir
+        .canonicalize()
+        .map(|p| PathBuf::from(strip_extended_prefix(&p.to_string_lossy())))
+        .unwrap_or_else(|_| dir.to_path_buf());
+    if resolved.parent().is_none() {
+        return Some("ドライブ（ファイルシステム）のルートです".to_string());
+    }
+    // Windows のパスは大文字小文字を区別しない
+    let normalize = |p: &Path| -> PathBuf {
+        if cfg!(windows) {
+            PathBuf::from(p.to_string_lossy().to_lowercase())
+        } else {
+            p.to_path_buf()
+        }
+    };
+    let target = normalize(&resolved);
+    let home = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    if let Some(home) = home.filter(|h| !h.is_empty()) {
+        let home = Path::new(&home);
+        let home = home.canonicalize().unwrap_or_else(|_| home.to_path_buf());
+        if target == normalize(Path::new(&strip_extended_prefix(&home.to_string_lossy()))) {
+            return Some("ホームフォルダです".to_string());
+        }
+    }
+    SENSITIVE_DIRS
+        .iter()
+        .find(|d| target.starts_with(normalize(Path::new(d))))
+        .map(|d| format!("システムフォルダ（{}）です", d))
+}
+
+/// 新しい名前がパスの区切り（`/`、Windows では `\` も）を含む場合に、使えない理由を返す。
+/// `allow_subfolders`（サブフォルダへの振り分け）が ON なら、対象フォルダの中に収まる相対パスだけを許す
+/// （絶対パス・ドライブ指定・`..`・`.`・空のフォルダ名・末尾の区切りは不可）。
+pub fn subfolder_name_error(new_name: &str, allow_subfolders: bool) -> Option<&'static str> {
+    if !new_name.chars().any(std::path::is_separator) {
+        return None;
+    }
+    if !allow_subfolders {
+        return Some("名前に / が含まれています（サブフォルダへ振り分けるには「/ でサブフォルダへ振り分け」を ON）");
+    }
+    let ends_with_separator = new_name.chars().last().is_some_and(std::path::is_separator);
+    let empty_folder = new_name.split(std::path::is_separator).any(|part| part.is_empty());
+    let all_normal = Path::new(new_name).components().all(|c| matches!(c, Component::Normal(_)));
+    if ends_with_separator || empty_folder || !all_normal {
+        return Some("サブフォルダの指定が不正です（絶対パス・.. ・空のフォルダ名は使えません）");
+    }
+    None
+}
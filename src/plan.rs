@@ -0,0 +1,336 @@
+// 適用の計画（重複時の連番・衝突の検出）
+//
+// 画面やファイルシステムに触れない純粋な処理だけを置き、プレビュー・適用・ヘッドレスモードから使う。
+// 変更後のパスに既存のファイルがあるかどうかは、呼び出し側が調べて渡す。
+
+use crate::transform::{expand_sequence, split_basename_ext, truncate_base};
+use std::collections::{HashMap, HashSet};
+use unicode_normalization::UnicodeNormalization;
+
+/// 重複判定用のキー。NFC に正規化し、大小無視のファイルシステムでだけ小文字化する
+/// （macOS の NFD 名と NFC 入力が別名扱いされないように）。大小を区別するファイルシステムでは
+/// `A.txt` と `a.txt` は別のファイルなので重複にしない。NTFS・APFS は `Ä` と `ä` も同じ名前とみなすため、
+/// 小文字化は ASCII に限らず Unicode で行う。
+pub fn collision_key(s: &str, case_insensitive_fs: bool) -> String {
+    let normalized: String = s.nfc().collect();
+    if case_insensitive_fs {
+        normalized.to_lowercase()
+    } else {
+        normalized
+    }
+}
+
+/// 重複時の連番の書式を検査する。`{n}` を含み、パス区切りを含まないこと。
+pub fn validate_number_template(template: &str) -> Result<(), String> {
+    if expand_sequence(template, 1, 1) == expand_sequence(template, 2, 2) {
+        return Err("{n} が含まれていません".to_string());
+    }
+    if template.contains('/') || template.contains('\\') {
+        return Err("パス区切りは使えません".to_string());
+    }
+    Ok(())
+}
+
+/// 重複時の連番の付け方
+pub struct NumberingOptions<'a> {
+    pub template: &'a str, // 連番の書式（{n} を含むこと、例 " ({n})"）
+    pub start: usize,      // 最初に試す番号
+    pub compound: bool,    // `.tar.gz` などを 1 つの拡張子として、その前に連番を付ける
+    pub max_name_length: usize, // 最大文字数（0 なら制限なし）。超える場合は連番を残して拡張子前を切り詰める
+    pub truncate_with_ellipsis: bool,
+    pub case_insensitive_fs: bool,
+}
+
+/// `name` が同じフォルダで使われている名前 `used`（`collision_key` 済み）と重なるなら、
+/// 重ならなくなるまで番号を増やして連番を付けた名前を返す。重ならなければ None。
+pub fn numbered_name(name: &str, used: &HashSet<String>, options: &NumberingOptions) -> Option<String> {
+    if !used.contains(&collision_key(name, options.case_insensitive_fs)) {
+        return None;
+    }
+    // `.gitignore` のような拡張子のない名前は、名前全体の後に連番を付ける
+    let (base, ext) = match split_basename_ext(name, options.compound) {
+        Some((b, e)) => (b.to_string(), format!(".{}", e)),
+        None => (name.to_string(), String::new()),
+    };
+    let mut n = options.start;
+    loop {
+        let candidate = truncate_base(
+            &base,
+            &format!("{}{}", expand_sequence(options.template, n, n), ext),
+            options.max_name_length,
+            options.truncate_with_ellipsis,
+        );
+        if !used.contains(&collision_key(&candidate, options.case_insensitive_fs)) {
+            return Some(candidate);
+        }
+        n += 1;
+    }
+}
+
+/// 適用する 1 件（元のパスと適用後のパス、適用後のパスにファイルが既にあるか）
+pub struct PlannedMove<'a> {
+    pub source: &'a str,
+    pub destination: String,
+    pub destination_exists: bool,
+}
+
+/// 変更後のパスで見つかった衝突
+#[derive(Debug, Default)]
+pub struct Collisions {
+    pub duplicates: Vec<(String, Vec<String>)>, // 新しいパスが重なる組（正規化した新しいパス, 元のパス）
+    pub existing: Vec<String>,                  // 一覧外の既存ファイルと重なる新しいパス
+    pub sources: HashSet<String>,               // 衝突にかかわるファイルの元のパス
+}
+
+impl Collisions {
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    pub fn message(&self) -> String {
+        format!(
+            "衝突を検出: 新名の重複 {} 件、既存ファイルとの衝突 {} 件",
+            self.duplicates.len(),
+            self.existing.len()
+        )
+    }
+}
+
+/// 変更後のパスに（自分自身ではない）ファイルが既にあるか。
+/// `link` ならリンクの作成で、元のファイルが残るため大文字小文字だけ違う元のファイル自身とも衝突する。
+pub fn conflicts_with_existing_path(
+    source: &str,
+    destination: &str,
+    destination_exists: bool,
+    link: bool,
+    case_insensitive_fs: bool,
+) -> bool {
+    if !destination_exists {
+        return false;
+    }
+    link || collision_key(destination, case_insensitive_fs) != collision_key(source, case_insensitive_fs)
+}
+
+/// 衝突の確認で上書きを選べる移動先か。移動先が今回変更するほかのファイル（`sources` は元のパス）なら選べない。
+/// 上書きでは既存のファイルを退避して削除するため、a→b・b→c で b を上書きすると、並列に処理する順によって
+/// b の変更が失敗して b の中身が失われる（a→b が先）か、a の変更が失敗する（b→c が先）。
+pub fn can_overwrite<'a>(destination: &str, sources: impl IntoIterator<Item = &'a str>, case_insensitive_fs: bool) -> bool {
+    let key = collision_key(destination, case_insensitive_fs);
+    !sources.into_iter().any(|source| collision_key(source, case_insensitive_fs) == key)
+}
+
+/// 変更後のパスが互いに重なるもの・既存のファイルと重なるものを探す。
+/// 既存のファイルが今回変更するほかのファイルでも衝突とみなす（並列に処理するため、先に動く保証がない）。
+pub fn detect_collisions(moves: &[PlannedMove], link: bool, case_insensitive_fs: bool) -> Collisions {
+    let mut by_destination: HashMap<String, Vec<String>> = HashMap::new();
+    let mut collisions = Collisions::default();
+    for m in moves {
+        by_destination
+            .entry(collision_key(&m.destination, case_insensitive_fs))
+            .or_default()
+            .push(m.source.to_string());
+        if conflicts_with_existing_path(m.source, &m.destination, m.destination_exists, link, case_insensitive_fs) {
+            collisions.existing.push(m.destination.clone());
+            collisions.sources.insert(m.source.to_string());
+        }
+    }
+    collisions.duplicates = by_destination.into_iter().filter(|(_, sources)| sources.len() > 1).collect();
+    collisions.duplicates.sort();
+    collisions
+        .sources
+        .extend(collisions.duplicates.iter().flat_map(|(_, sources)| sources.iter().cloned()));
+    collisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(template: &str) -> NumberingOptions<'_> {
+        NumberingOptions {
+            template,
+            start: 2,
+            compound: false,
+            max_name_length: 0,
+            truncate_with_ellipsis: false,
+            case_insensitive_fs: false,
+        }
+    }
+
+    fn used(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn planned<'a>(source: &'a str, destination: &str, destination_exists: bool) -> PlannedMove<'a> {
+        PlannedMove { source, destination: destination.to_string(), destination_exists }
+    }
+
+    #[test]
+    fn numbering_leaves_free_names_alone() {
+        assert_eq!(numbered_name("a.txt", &used(&["b.txt"]), &options(" ({n})")), None);
+    }
+
+    #[test]
+    fn numbering_skips_numbers_in_use() {
+        let used = used(&["a.txt", "a (2).txt", "a (3).txt"]);
+        assert_eq!(numbered_name("a.txt", &used, &options(" ({n})")).as_deref(), Some("a (4).txt"));
+    }
+
+    #[test]
+    fn numbering_starts_at_given_number_and_pads() {
+        let mut options = options("_{n:3}");
+        options.start = 1;
+        assert_eq!(numbered_name("a.txt", &used(&["a.txt"]), &options).as_deref(), Some("a_001.txt"));
+    }
+
+    #[test]
+    fn numbering_puts_number_after_dotfile_name() {
+        let used = used(&[".gitignore"]);
+        assert_eq!(numbered_name(".gitignore", &used, &options(" ({n})")).as_deref(), Some(".gitignore (2)"));
+    }
+
+    #[test]
+    fn numbering_keeps_compound_extension() {
+        let used = used(&["backup.tar.gz"]);
+        assert_eq!(numbered_name("backup.tar.gz", &used, &options(" ({n})")).as_deref(), Some("backup.tar (2).gz"));
+        let mut options = options(" ({n})");
+        options.compound = true;
+        assert_eq!(numbered_name("backup.tar.gz", &used, &options).as_deref(), Some("backup (2).tar.gz"));
+    }
+
+    #[test]
+    fn numbering_respects_case_insensitive_fs() {
+        let used = used(&["photo.jpg"]);
+        assert_eq!(numbered_name("Photo.JPG", &used, &options(" ({n})")), None);
+        let mut options = options(" ({n})");
+        options.case_insensitive_fs = true;
+        assert_eq!(numbered_name("Photo.JPG", &used, &options).as_deref(), Some("Photo (2).JPG"));
+    }
+
+    #[test]
+    fn numbering_truncates_base_but_keeps_number() {
+        let mut options = options(" ({n})");
+        options.max_name_length = 11;
+        let used = used(&["abcdef.txt"]);
+        assert_eq!(numbered_name("abcdef.txt", &used, &options).as_deref(), Some("abc (2).txt"));
+        options.truncate_with_ellipsis = true;
+        assert_eq!(numbered_name("abcdef.txt", &used, &options).as_deref(), Some("ab… (2).txt"));
+    }
+
+    #[test]
+    fn no_collisions_for_distinct_new_paths() {
+        let moves = [planned("/d/a.txt", "/d/x.txt", false), planned("/d/b.txt", "/d/y.txt", false)];
+        let collisions = detect_collisions(&moves, false, false);
+        assert!(collisions.is_empty());
+        assert_eq!(collisions.message(), "衝突を検出: 新名の重複 0 件、既存ファイルとの衝突 0 件");
+    }
+
+    #[test]
+    fn duplicate_new_paths_collide() {
+        let moves = [
+            planned("/d/b.txt", "/d/x.txt", false),
+            planned("/d/a.txt", "/d/x.txt", false),
+            planned("/d/c.txt", "/d/z.txt", false),
+        ];
+        let collisions = detect_collisions(&moves, false, false);
+        assert_eq!(collisions.duplicates, vec![("/d/x.txt".to_string(), vec!["/d/b.txt".to_string(), "/d/a.txt".to_string()])]);
+        assert!(collisions.existing.is_empty());
+        assert_eq!(collisions.sources, used(&["/d/a.txt", "/d/b.txt"]));
+    }
+
+    #[test]
+    fn duplicates_differing_only_in_case_collide_on_case_insensitive_fs() {
+        let moves = [planned("/d/a.txt", "/d/X.txt", false), planned("/d/b.txt", "/d/x.txt", false)];
+        assert!(detect_collisions(&moves, false, false).is_empty());
+        let collisions = detect_collisions(&moves, false, true);
+        assert_eq!(collisions.duplicates.len(), 1);
+        assert_eq!(collisions.duplicates[0].0, "/d/x.txt");
+    }
+
+    #[test]
+    fn existing_file_collides() {
+        let moves = [planned("/d/a.txt", "/d/b.txt", true)];
+        let collisions = detect_collisions(&moves, false, false);
+        assert_eq!(collisions.existing, vec!["/d/b.txt".to_string()]);
+        assert_eq!(collisions.sources, used(&["/d/a.txt"]));
+    }
+
+    #[test]
+    fn case_only_rename_is_not_a_conflict() {
+        // 大文字小文字を区別しない環境では、変更後のパスに見える「既存のファイル」は元のファイル自身
+        let moves = [planned("/d/photo.jpg", "/d/Photo.jpg", true)];
+        assert!(detect_collisions(&moves, false, true).is_empty());
+        // 区別する環境で変更後のパスに別のファイルがあれば衝突
+        assert!(!detect_collisions(&moves, false, false).is_empty());
+    }
+
+    #[test]
+    fn link_conflicts_with_its_own_source() {
+        // リンクの作成では元のファイルが残るため、自分自身とも衝突する
+        let moves = [planned("/d/photo.jpg", "/d/Photo.jpg", true)];
+        assert!(!detect_collisions(&moves, true, true).is_empty());
+        assert!(conflicts_with_existing_path("/d/a", "/d/A", true, true, true));
+        assert!(!conflicts_with_existing_path("/d/a", "/d/A", false, true, true));
+    }
+
+    #[test]
+    fn chains_and_swaps_are_conflicts() {
+        // 並列に変更するため、今回変更される既存ファイルへの変更（a→b, b→c や入れ替え）も衝突として止める
+        let chain = [planned("/d/a", "/d/b", true), planned("/d/b", "/d/c", false)];
+        let collisions = detect_collisions(&chain, false, false);
+        assert_eq!(collisions.existing, vec!["/d/b".to_string()]);
+        assert_eq!(collisions.sources, used(&["/d/a"]));
+
+        let swap = [planned("/d/a", "/d/b", true), planned("/d/b", "/d/a", true)];
+        let collisions = detect_collisions(&swap, false, false);
+        assert_eq!(collisions.existing.len(), 2);
+        assert!(collisions.duplicates.is_empty());
+    }
+
+    #[test]
+    fn destinations_renamed_in_the_same_batch_cannot_be_overwritten() {
+        let chain = [planned("/d/a", "/d/b", true), planned("/d/b", "/d/c", false)];
+        let sources = || chain.iter().map(|m| m.source);
+        let collisions = detect_collisions(&chain, false, false);
+        assert_eq!(collisions.existing, vec!["/d/b".to_string()]);
+        assert!(!can_overwrite(&collisions.existing[0], sources(), false));
+        assert!(!can_overwrite("/d/B", sources(), true));
+        // 一覧外の既存ファイルなら上書きを選べる
+        assert!(can_overwrite("/d/x", sources(), false));
+        assert!(can_overwrite("/d/B", sources(), false));
+    }
+
+    #[test]
+    fn unicode_normalization_forms_collide() {
+        // NFC と NFD で表した同じ名前は重複とみなす
+        let moves = [planned("/d/1", "/d/\u{304C}", false), planned("/d/2", "/d/\u{304B}\u{3099}", false)];
+        assert_eq!(detect_collisions(&moves, false, false).duplicates.len(), 1);
+    }
+
+    // 「が」の合成済み（NFC）と、「か」+ 濁点に分解した形（NFD）
+    const GA_NFC: &str = "\u{304C}.txt";
+    const GA_NFD: &str = "\u{304B}\u{3099}.txt";
+
+    #[test]
+    fn collision_keys_match_across_normalization_forms() {
+        assert_ne!(GA_NFC, GA_NFD);
+        assert_eq!(collision_key(GA_NFC, false), collision_key(GA_NFD, false));
+        assert_eq!(collision_key("Cafe\u{301}.TXT", true), collision_key("caf\u{E9}.txt", true));
+    }
+
+    #[test]
+    fn case_only_rename_depends_on_filesystem() {
+        // 大小無視のファイルシステムでは `File.txt` → `file.txt` は自分自身への変更で、衝突しない
+        assert_eq!(collision_key("/d/File.txt", true), collision_key("/d/file.txt", true));
+        // 区別するファイルシステムでは別の名前として扱う
+        assert_ne!(collision_key("/d/File.txt", false), collision_key("/d/file.txt", false));
+        assert_eq!(collision_key("/d/file.txt", false), "/d/file.txt");
+    }
+
+    #[test]
+    fn number_templates_need_counter_without_separators() {
+        assert!(validate_number_template(" ({n})").is_ok());
+        assert!(validate_number_template(" (n)").is_err());
+        assert!(validate_number_template("/{n}").is_err());
+    }
+}
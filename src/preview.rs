@@ -1,195 +1,1838 @@
-use crate::state::{AppState, FileEntry};
-use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use crate::events::{SCAN_CHUNK, SCAN_DONE};
+use crate::paths::{strip_extended_prefix, subfolder_name_error, to_extended_path};
+use crate::script::{NameScript, ScriptVars};
+use crate::fuzzy::fuzzy_find;
+use crate::plan::{collision_key, numbered_name, validate_number_template, NumberingOptions};
+use crate::rename::{conflicts_with_existing, is_read_only, ApplyMode, BACKUP_DIR_NAME};
+use crate::state::{AppState, CopyFormat, ExcludeChip, ExtensionChip, FileEntry, ReplaceCount, ScanOptions, ScanSummary, ScannedFile};
+use crate::watch::{stop_watching, watch_dir};
+use crate::exif_date::{expand_exif_tokens, has_exif_token};
+use crate::name_date::DateReformat;
+use crate::content_hash::{content_hash, expand_hash_tokens, has_hash_token, hash_in_background, ContentHash};
+use crate::tags::{expand_tag_tokens, has_tag_tokens, read_audio_tags};
+use crate::transform::{
+    expand_counter, expand_sequence, fullwidth_to_halfwidth, insert_at_char, normalize_extension, normalize_separators, normalize_unicode, parse_allowed_chars, restrict_to_allowed, strip_diacritics,
+    split_basename_ext, split_existing_number, COMPOUND_EXTENSIONS, strip_trailing_dots_spaces, swap_parts, transliterate_to_ascii, truncate_name,
+};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use regex::{Regex, RegexBuilder, NoExpand};
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use walkdir::WalkDir;
 use druid::im::Vector;
-use tracing::debug;
+use druid::{Application, ExtEventSink, Target};
+use tracing::{debug, warn};
 
-/// 指定ディレクトリ（およびサブディレクトリも含む場合）のファイル一覧を読み込み、
-/// 除外パターンに合致するファイルを除外した上で AppState の files に反映する。
-pub fn load_files(data: &mut AppState) {
-    let path = Path::new(&data.selected_dir);
-    let mut files = Vector::new();
-    if path.exists() && path.is_dir() {
-        let walker = if data.include_subdirectories {
-            WalkDir::new(path)
-        } else {
-            WalkDir::new(path).max_depth(1)
-        };
+/// グロブ／`re:` 正規表現／素の文字列のパターン集合。いずれも大小無視でマッチする。
+/// 各パターンは追加した順の番号を持ち、どの項目に一致したかを返せる。
+#[derive(Default)]
+struct PatternSet {
+    globs: Vec<(Glob, usize)>,
+    glob_set: Option<GlobSet>,
+    glob_indices: Vec<usize>, // GlobSet 内の番号 → 項目の番号
+    regexes: Vec<(Regex, usize)>,
+    filename_substrings: Vec<(String, usize)>,
+    path_substrings: Vec<(String, usize)>,
+    len: usize,
+}
 
-        // Exclude: 3系統サポート（ケース非依存）
-        let mut glob_builder = GlobSetBuilder::new();
-        let mut regex_excludes: Vec<Regex> = Vec::new();
-        let mut filename_substrings: Vec<String> = Vec::new();
-        let mut path_substrings: Vec<String> = Vec::new();
-        for raw in data
-            .exclude_pattern
-            .split(',')
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-        {
-            let is_regex = raw.to_ascii_lowercase().starts_with("re:");
-            if is_regex {
-                let pat = &raw[3..];
-                let mut rb = RegexBuilder::new(pat);
-                rb.case_insensitive(true);
-                match rb.build() {
-                    Ok(re) => regex_excludes.push(re),
-                    Err(_) => {
-                        data.status_message = format!("Exclude regex error: {}", pat);
-                        debug!(target: "exclude", err = %pat, "exclude_regex_error");
-                    }
-                }
+impl PatternSet {
+    /// 解釈済みのパターンを 1 つ追加する。
+    fn add(&mut self, kind: &ExcludeKind) {
+        let index = self.len;
+        self.len += 1;
+        match kind {
+            ExcludeKind::Glob(g) => self.globs.push((g.clone(), index)),
+            ExcludeKind::Regex(re) => self.regexes.push((re.clone(), index)),
+            ExcludeKind::PathSubstring(sub) => self.path_substrings.push((sub.to_ascii_lowercase(), index)),
+            ExcludeKind::FilenameSubstring(sub) => self.filename_substrings.push((sub.to_ascii_lowercase(), index)),
+        }
+    }
+
+    fn build(&mut self) {
+        let mut builder = GlobSetBuilder::new();
+        for (g, index) in self.globs.drain(..) {
+            builder.add(g);
+            self.glob_indices.push(index);
+        }
+        self.glob_set = builder.build().ok();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 一致した項目のうち、除外欄で最も前に書いた項目の番号を返す。
+    fn first_match(&self, full_path: &Path) -> Option<usize> {
+        let mut first: Option<usize> = None;
+        let mut found = |index: usize| first = Some(first.map_or(index, |f| f.min(index)));
+        if let Some(gs) = &self.glob_set {
+            for i in gs.matches(full_path) {
+                found(self.glob_indices[i]);
+            }
+        }
+        let full_path_str = full_path.to_string_lossy();
+        for (re, index) in &self.regexes {
+            if re.is_match(&full_path_str) {
+                found(*index);
+            }
+        }
+        let path_lower = full_path_str.to_ascii_lowercase();
+        let file_name_lower = full_path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_ascii_lowercase())
+            .unwrap_or_default();
+        for (tok, index) in &self.filename_substrings {
+            if file_name_lower.contains(tok.as_str()) {
+                found(*index);
+            }
+        }
+        for (sub, index) in &self.path_substrings {
+            if path_lower.contains(sub.as_str()) {
+                found(*index);
+            }
+        }
+        first
+    }
+}
+
+/// 除外欄の 1 項目の種類
+#[derive(Clone, Debug)]
+pub enum ExcludeKind {
+    Glob(Glob),
+    Regex(Regex),
+    /// 区切り文字を含む素の文字列（パス全体への部分一致）
+    PathSubstring(String),
+    /// 素の文字列（ファイル名への部分一致）
+    FilenameSubstring(String),
+}
+
+/// 除外欄の 1 項目の解釈結果
+#[derive(Clone, Debug)]
+pub struct ExcludeToken {
+    pub raw: String,
+    /// `!` 付き（一致するものだけを対象にする）
+    pub include: bool,
+    pub kind: Result<ExcludeKind, String>,
+}
+
+/// 除外欄をカンマ区切りで項目に分け、それぞれグロブ／正規表現／部分一致として解釈する。
+/// `{jpg,png}` のような波括弧の中のカンマでは区切らない。空の項目と `!` だけの項目は無視する。
+pub fn parse_excludes(exclude_pattern: &str) -> Vec<ExcludeToken> {
+    split_exclude_items(exclude_pattern)
+        .into_iter()
+        .filter_map(|raw| {
+            let (include, pat) = match raw.strip_prefix('!') {
+                Some(pat) => (true, pat.trim()),
+                None => (false, raw.as_str()),
+            };
+            if pat.is_empty() {
+                return None;
+            }
+            Some(ExcludeToken { kind: parse_exclude_kind(pat), include, raw })
+        })
+        .collect()
+}
+
+fn split_exclude_items(exclude_pattern: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    for c in exclude_pattern.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                items.push(std::mem::take(&mut current));
                 continue;
             }
-            let has_glob_meta = raw.contains('*') || raw.contains('?') || raw.contains('[') || raw.contains('{');
-            let has_sep = raw.contains('/') || raw.contains('\\');
-            if has_glob_meta {
-                match GlobBuilder::new(raw).case_insensitive(true).build() {
-                    Ok(g) => {
-                        glob_builder.add(g);
-                    }
-                    Err(_) => {
-                        data.status_message = format!("Exclude glob error: {}", raw);
-                        debug!(target: "exclude", err = %raw, "exclude_glob_error");
-                    }
+            _ => {}
+        }
+        current.push(c);
+    }
+    items.push(current);
+    items.into_iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+fn parse_exclude_kind(raw: &str) -> Result<ExcludeKind, String> {
+    if raw.to_ascii_lowercase().starts_with("re:") {
+        let pat = &raw[3..];
+        let mut rb = RegexBuilder::new(pat);
+        rb.case_insensitive(true);
+        return rb.build().map(ExcludeKind::Regex).map_err(|e| {
+            debug!(target: "exclude", err = %pat, "exclude_regex_error");
+            format!("正規表現として無効です: {}", first_line(&describe_regex_error(&e, true)))
+        });
+    }
+    let has_glob_meta = raw.contains('*') || raw.contains('?') || raw.contains('[') || raw.contains('{');
+    if has_glob_meta {
+        return GlobBuilder::new(raw)
+            .case_insensitive(true)
+            .build()
+            .map(ExcludeKind::Glob)
+            .map_err(|e| {
+                debug!(target: "exclude", err = %raw, "exclude_glob_error");
+                format!("グロブとして無効です: {}", e.kind())
+            });
+    }
+    if raw.contains('/') || raw.contains('\\') {
+        Ok(ExcludeKind::PathSubstring(raw.to_string()))
+    } else {
+        Ok(ExcludeKind::FilenameSubstring(raw.to_string()))
+    }
+}
+
+/// regex のエラーは複数行（パターンと位置の図示）になるため、最後の説明行だけを使う。
+fn first_line(message: &str) -> String {
+    message.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or(message).trim().to_string()
+}
+
+/// 除外欄の各項目の解釈を、入力中に表示する説明とエラーの一覧にする。
+/// 波括弧のグロブは展開結果も示す（例 `*.{jpg,png}` → `*.jpg | *.png`）。
+pub fn describe_excludes(exclude_pattern: &str) -> (String, String) {
+    let mut described = Vec::new();
+    let mut errors = Vec::new();
+    for token in parse_excludes(exclude_pattern) {
+        let prefix = if token.include { "対象のみ " } else { "" };
+        match &token.kind {
+            Ok(ExcludeKind::Glob(g)) => {
+                let expanded = expand_braces(g.glob());
+                if expanded.len() > 1 {
+                    described.push(format!("{}グロブ {} → {}", prefix, g.glob(), expanded.join(" | ")));
+                } else {
+                    described.push(format!("{}グロブ {}", prefix, g.glob()));
                 }
-            } else if has_sep {
-                path_substrings.push(raw.to_ascii_lowercase());
-            } else {
-                filename_substrings.push(raw.to_ascii_lowercase());
             }
+            Ok(ExcludeKind::Regex(re)) => described.push(format!("{}正規表現 {}", prefix, re.as_str())),
+            Ok(ExcludeKind::PathSubstring(sub)) => described.push(format!("{}パス部分一致 {}", prefix, sub)),
+            Ok(ExcludeKind::FilenameSubstring(sub)) => described.push(format!("{}名前部分一致 {}", prefix, sub)),
+            Err(e) => errors.push(format!("✗ {} … {}", token.raw, e)),
         }
-        let glob_set: Option<GlobSet> = glob_builder.build().ok();
-        for entry in walker.into_iter().filter_map(|e| e.ok()) {
-            if entry.file_type().is_file() {
-                let full_path = entry.path();
-                if glob_set
-                    .as_ref()
-                    .map(|gs| gs.is_match(full_path))
-                    .unwrap_or(false)
-                {
-                    debug!(target: "exclude", path = %full_path.display(), "excluded by glob");
-                    continue;
+    }
+    (described.join(" / "), errors.join("\n"))
+}
+
+/// 除外の項目のチップ（除外欄の項目の後に、OFF にした項目を並べる）。種類は `parse_excludes` の解釈による。
+pub fn exclude_chips(exclude_pattern: &str, disabled: &Vector<String>) -> Vector<ExcludeChip> {
+    let enabled = parse_excludes(exclude_pattern).into_iter().map(|token| (token, true));
+    let disabled = disabled.iter().flat_map(|raw| parse_excludes(raw)).map(|token| (token, false));
+    enabled
+        .chain(disabled)
+        .map(|(token, enabled)| {
+            let kind = match &token.kind {
+                Ok(ExcludeKind::Glob(_)) => "グロブ",
+                Ok(ExcludeKind::Regex(_)) => "正規表現",
+                Ok(ExcludeKind::PathSubstring(_)) => "パス",
+                Ok(ExcludeKind::FilenameSubstring(_)) => "名前",
+                Err(_) => "無効",
+            };
+            ExcludeChip { raw: token.raw, kind: kind.to_string(), enabled }
+        })
+        .collect()
+}
+
+/// 追加欄の項目（カンマ区切りで複数可）を除外欄の末尾に加える。無効な項目があれば加えずに知らせる。加えたら true。
+pub fn add_exclude_items(data: &mut AppState) -> bool {
+    let tokens = parse_excludes(&data.exclude_draft);
+    if tokens.is_empty() {
+        return false;
+    }
+    if let Some((raw, e)) = tokens.iter().find_map(|t| t.kind.as_ref().err().map(|e| (&t.raw, e))) {
+        data.set_status(format!("除外の項目を追加できません: {} … {}", raw, e));
+        return false;
+    }
+    let mut items = split_exclude_items(&data.exclude_pattern);
+    items.extend(tokens.into_iter().map(|t| t.raw));
+    data.exclude_pattern = items.join(", ");
+    data.exclude_draft = String::new();
+    true
+}
+
+/// 除外の項目を ON/OFF する。OFF にした項目は除外欄から外して覚えておき、ON に戻すと除外欄の末尾に加える。
+pub fn toggle_exclude_item(data: &mut AppState, raw: &str) {
+    let mut items = split_exclude_items(&data.exclude_pattern);
+    if let Some(i) = items.iter().position(|item| item == raw) {
+        items.remove(i);
+        data.disabled_excludes.push_back(raw.to_string());
+    } else if let Some(i) = data.disabled_excludes.iter().position(|item| item == raw) {
+        data.disabled_excludes.remove(i);
+        items.push(raw.to_string());
+    }
+    data.exclude_pattern = items.join(", ");
+}
+
+/// 除外の項目を除外欄（OFF にしていれば OFF の一覧）から削除する。
+pub fn remove_exclude_item(data: &mut AppState, raw: &str) {
+    let mut items = split_exclude_items(&data.exclude_pattern);
+    if let Some(i) = items.iter().position(|item| item == raw) {
+        items.remove(i);
+        data.exclude_pattern = items.join(", ");
+    } else if let Some(i) = data.disabled_excludes.iter().position(|item| item == raw) {
+        data.disabled_excludes.remove(i);
+    }
+}
+
+/// グロブの `{a,b}` を展開した一覧（表示用。入れ子にも対応し、多すぎる場合は途中で打ち切る）。
+fn expand_braces(glob: &str) -> Vec<String> {
+    const MAX_EXPANSIONS: usize = 8;
+    let Some(open) = glob.find('{') else {
+        return vec![glob.to_string()];
+    };
+    // 対応する閉じ括弧と、その階層のカンマ位置を探す
+    let mut depth = 0usize;
+    let mut close = None;
+    let mut commas = Vec::new();
+    for (i, c) in glob[open..].char_indices() {
+        let i = open + i;
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
                 }
-                let full_path_str = full_path.to_string_lossy();
-                if regex_excludes.iter().any(|re| re.is_match(&full_path_str)) {
-                    debug!(target: "exclude", path = %full_path.display(), "excluded by regex");
-                    continue;
+            }
+            ',' if depth == 1 => commas.push(i),
+            _ => {}
+        }
+    }
+    let Some(close) = close else {
+        return vec![glob.to_string()];
+    };
+    let (head, tail) = (&glob[..open], &glob[close + 1..]);
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+    let mut out = Vec::new();
+    for pair in bounds.windows(2) {
+        let alternative = &glob[pair[0] + 1..pair[1]];
+        for expanded in expand_braces(&format!("{}{}{}", head, alternative, tail)) {
+            if out.len() >= MAX_EXPANSIONS {
+                out.push("…".to_string());
+                return out;
+            }
+            out.push(expanded);
+        }
+    }
+    out
+}
+
+/// 除外欄のパターン。`!` で始まるものは「これに一致するものだけを対象にする」（ホワイトリスト）。
+struct ExcludeMatcher {
+    excludes: PatternSet,
+    includes: PatternSet,
+    labels: Vec<String>, // 除外の内訳の項目（除外の各項目、`!` の指定があれば最後に「一致しない」）
+}
+
+/// `!` の指定に一致せずに除外したファイルの内訳の項目名
+const NOT_INCLUDED_LABEL: &str = "（! の指定に一致しない）";
+
+impl ExcludeMatcher {
+    /// 除外欄を解釈する。無効なパターンは読み飛ばし、最後のエラーを返す。
+    fn new(exclude_pattern: &str) -> (Self, Option<String>) {
+        let mut error = None;
+        let mut excludes = PatternSet::default();
+        let mut includes = PatternSet::default();
+        let mut labels = Vec::new();
+        for token in parse_excludes(exclude_pattern) {
+            match &token.kind {
+                Ok(kind) if token.include => includes.add(kind),
+                Ok(kind) => {
+                    excludes.add(kind);
+                    labels.push(token.raw.clone());
                 }
-                let path_lower = full_path_str.to_ascii_lowercase();
-                let file_name_lower = full_path
-                    .file_name()
-                    .and_then(|s| s.to_str())
-                    .map(|s| s.to_ascii_lowercase())
-                    .unwrap_or_default();
-                if !filename_substrings.is_empty() {
-                    if filename_substrings.iter().any(|tok| file_name_lower.contains(tok)) {
-                        debug!(target: "exclude", path = %full_path.display(), reason = "filename_substring");
-                        continue;
-                    }
+                Err(e) => error = Some(format!("除外パターンのエラー: {} … {}", token.raw, e)),
+            }
+        }
+        excludes.build();
+        includes.build();
+        if !includes.is_empty() {
+            labels.push(NOT_INCLUDED_LABEL.to_string());
+        }
+        (Self { excludes, includes, labels }, error)
+    }
+
+    /// 除外するなら、除外の理由になった内訳の項目の番号（`labels` の位置）を返す。
+    /// 除外に一致するか、`!` の指定があるのにどれにも一致しなければ除外する。
+    /// 複数の項目に一致する場合は、除外欄で前に書いた項目に数える。
+    fn excluded_by(&self, full_path: &Path) -> Option<usize> {
+        if let Some(index) = self.excludes.first_match(full_path) {
+            debug!(target: "exclude", path = %full_path.display(), token = %self.labels[index], "excluded");
+            return Some(index);
+        }
+        if !self.includes.is_empty() && self.includes.first_match(full_path).is_none() {
+            debug!(target: "exclude", path = %full_path.display(), reason = "not_included", "excluded");
+            return Some(self.labels.len() - 1);
+        }
+        None
+    }
+}
+
+/// ファイルサイズの範囲による除外（最小・最大はそれぞれ省略でき、どちらもその値を含む）
+struct SizeFilter {
+    min: Option<u64>,
+    max: Option<u64>,
+    label: String, // 除外の内訳の項目名（例 サイズ（最小 1KB、最大 10MB））
+}
+
+impl SizeFilter {
+    /// 最小・最大の入力を解釈する。無効な入力はサイズでは除外せず、エラーを返す。
+    fn new(min_size: &str, max_size: &str) -> (Self, Option<String>) {
+        let none = Self { min: None, max: None, label: String::new() };
+        let (min, max) = match (parse_size(min_size), parse_size(max_size)) {
+            (Ok(min), Ok(max)) => (min, max),
+            (Err(e), _) | (_, Err(e)) => return (none, Some(e)),
+        };
+        if let (Some(lo), Some(hi)) = (min, max) {
+            if lo > hi {
+                return (none, Some(format!("サイズの指定が不正です: 最小 {} が最大 {} より大きい", min_size.trim(), max_size.trim())));
+            }
+        }
+        let parts: Vec<String> = [("最小", min_size, min), ("最大", max_size, max)]
+            .into_iter()
+            .filter(|(_, _, value)| value.is_some())
+            .map(|(name, text, _)| format!("{} {}", name, text.trim()))
+            .collect();
+        (Self { min, max, label: format!("サイズ（{}）", parts.join("、")) }, None)
+    }
+
+    fn is_active(&self) -> bool {
+        self.min.is_some() || self.max.is_some()
+    }
+
+    /// サイズ `len`（バイト）のファイルを除外するか
+    fn rejects(&self, len: u64) -> bool {
+        self.min.is_some_and(|min| len < min) || self.max.is_some_and(|max| len > max)
+    }
+}
+
+/// `10MB` `1.5 GB` `500k` `0` のようなサイズをバイト数にする（単位は 1024 基準、単位なしはバイト）。空欄なら None。
+pub fn parse_size(text: &str) -> Result<Option<u64>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let invalid = || format!("サイズの指定が不正です: {}（例 500KB, 10MB）", text);
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let value: f64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+    let bytes = value * multiplier as f64;
+    if !bytes.is_finite() || bytes > u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(Some(bytes.round() as u64))
+}
+
+/// サイズの範囲の入力のエラー（入力中の表示用、問題なければ空）
+pub fn describe_size_filter(min_size: &str, max_size: &str) -> String {
+    SizeFilter::new(min_size, max_size).1.unwrap_or_default()
+}
+
+/// 走査の世代カウンタ。新しい走査を始めると古い走査スレッドは途中で打ち切られる。
+static SCAN_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// 走査結果を UI スレッドへ送る単位
+const SCAN_CHUNK_SIZE: usize = 500;
+
+/// プレビューを更新する。読み込み済みの一覧が現在の走査条件と一致し、読み直しの必要もなければ
+/// ディレクトリを走査せずに新ファイル名だけを計算し直す（検索/置換だけを変えた場合）。
+/// フォルダ・除外などの走査条件が変わった場合や、適用後など `rescan_needed` が立っている場合は読み直す。
+pub fn request_preview(data: &mut AppState, sink: ExtEventSink) {
+    if data.scan_in_progress || data.rescan_needed || data.scan_options != ScanOptions::from_state(data) {
+        start_scan(data, sink, true);
+        return;
+    }
+    update_preview(data);
+    start_pending_hashes(data, sink);
+}
+
+/// `{hash}` の計算が済んでいない大きいファイルのハッシュをバックグラウンドで計算する（済んだら HASHES_READY）。
+pub fn start_pending_hashes(data: &AppState, sink: ExtEventSink) {
+    let paths: Vec<String> = data.hash_pending.iter().cloned().collect();
+    hash_in_background(&paths, sink);
+}
+
+/// 指定ディレクトリ（およびサブディレクトリも含む場合）のファイル一覧をバックグラウンドで読み込む。
+/// 除外パターンに合致するファイルを除き、SCAN_CHUNK / SCAN_DONE コマンドで AppState の files に反映する。
+/// `then_preview` が true なら走査完了後にプレビューを更新する。
+pub fn start_scan(data: &mut AppState, sink: ExtEventSink, then_preview: bool) {
+    // 拡張子の絞り込みはフォルダごと（フォルダを変えると解除）
+    if data.scan_options.roots() != ScanOptions::from_state(data).roots() {
+        data.excluded_extensions = Vector::new();
+    }
+    let options = ScanOptions::from_state(data);
+    if data.scan_in_progress && data.scan_options == options {
+        // 同じ条件で走査中なら二重に開始せず、完了後のプレビューだけ予約する
+        data.preview_after_scan |= then_preview;
+        return;
+    }
+    // フォルダや条件が変わった場合は古い走査を打ち切って新しく始める
+    if data.scan_options.roots() != options.roots() {
+        data.session_excluded = Vector::new();
+    }
+    let scan_id = SCAN_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    data.scan_id = scan_id;
+    data.rescan_needed = false;
+    data.scan_options = options.clone();
+    data.preview_after_scan = then_preview;
+    data.files = Vector::new();
+    data.preview_files = Vector::new();
+    data.visible_preview = Vector::new();
+
+    if let Some(missing) = missing_root(&options) {
+        data.scan_in_progress = false;
+        data.set_status(missing);
+        return;
+    }
+    follow_folder(data, &options, sink.clone());
+    data.scan_in_progress = true;
+    data.status_message = "読み込み中...".to_string();
+
+    std::thread::spawn(move || {
+        let mut chunk: Vec<ScannedFile> = Vec::new();
+        let summary = scan_paths(&options, |root, path| {
+            if SCAN_GENERATION.load(Ordering::SeqCst) != scan_id {
+                debug!(scan_id, "scan_cancelled");
+                return false;
+            }
+            chunk.push(scanned_file(root, path, &options));
+            if chunk.len() >= SCAN_CHUNK_SIZE {
+                let files = std::mem::take(&mut chunk);
+                let _ = sink.submit_command(SCAN_CHUNK, (scan_id, files), Target::Global);
+            }
+            true
+        });
+        if SCAN_GENERATION.load(Ordering::SeqCst) != scan_id {
+            return;
+        }
+        if !chunk.is_empty() {
+            let _ = sink.submit_command(SCAN_CHUNK, (scan_id, chunk), Target::Global);
+        }
+        let _ = sink.submit_command(SCAN_DONE, (scan_id, summary), Target::Global);
+    });
+}
+
+/// 自動再読み込みが ON なら読み込んだフォルダの監視を始める。監視できなければ OFF に戻して知らせる。
+fn follow_folder(data: &mut AppState, options: &ScanOptions, sink: ExtEventSink) {
+    if !data.auto_reload {
+        return;
+    }
+    if let Err(e) = watch_dir(&options.roots(), options.include_subdirectories, sink) {
+        warn!(dir = %options.dir, err = %e, "watch_failed");
+        data.auto_reload = false;
+        stop_watching();
+        data.set_status(format!("フォルダを監視できないため自動再読み込みを OFF にしました: {}", e));
+    }
+}
+
+/// 見つからない対象フォルダ（追加フォルダを含む）があれば、そのメッセージ。
+fn missing_root(options: &ScanOptions) -> Option<String> {
+    let roots = options.roots();
+    if roots.is_empty() {
+        return Some("ディレクトリが見つかりません".to_string());
+    }
+    roots.into_iter().find(|root| !Path::new(root).is_dir()).map(|root| format!("ディレクトリが見つかりません: {}", root))
+}
+
+/// 走査条件に従って各対象フォルダのファイルを列挙し、除外されなかったパスを対象フォルダと共に `on_path` に渡す。
+/// `on_path` が false を返すと走査を打ち切る。除外パターンのエラー・拡張子ごとのファイル数などの要約を返す。
+fn scan_paths(options: &ScanOptions, mut on_path: impl FnMut(&str, String) -> bool) -> ScanSummary {
+    let (matcher, exclude_error) = ExcludeMatcher::new(&options.exclude_pattern);
+    let (size_filter, size_error) = SizeFilter::new(&options.min_size, &options.max_size);
+    let mut excluded_by: Vec<(String, usize)> = matcher.labels.iter().map(|label| (label.clone(), 0)).collect();
+    // サイズで除外した件数は除外欄の項目の後ろに数える
+    if size_filter.is_active() {
+        excluded_by.push((size_filter.label.clone(), 0));
+    }
+    let exclude_error = match (exclude_error, size_error) {
+        (Some(a), Some(b)) => Some(format!("{} ／ {}", a, b)),
+        (a, b) => a.or(b),
+    };
+    let mut summary = ScanSummary { exclude_error, excluded_by, ..Default::default() };
+    // 対象フォルダが入れ子の場合に同じファイルを 2 回並べない
+    let mut seen: HashSet<String> = HashSet::new();
+    for dir in options.roots() {
+        if !scan_root(&dir, options, &matcher, &size_filter, &mut seen, &mut summary, &mut on_path) {
+            break;
+        }
+    }
+    summary
+}
+
+/// 1 つの対象フォルダを走査する。打ち切られたら false。
+fn scan_root(
+    dir: &str,
+    options: &ScanOptions,
+    matcher: &ExcludeMatcher,
+    size_filter: &SizeFilter,
+    seen: &mut HashSet<String>,
+    summary: &mut ScanSummary,
+    on_path: &mut impl FnMut(&str, String) -> bool,
+) -> bool {
+    // 長いパスのフォルダも走査できるよう、必要なら拡張長プレフィックスを付ける
+    let root = to_extended_path(Path::new(dir));
+    // 連番 {n} の順序が実行ごとに変わらないよう名前順に列挙する。
+    // 各フォルダでファイルをサブフォルダより先に並べ、同じフォルダのファイルが連続するようにする
+    // リンクをたどる場合、walkdir は祖先のフォルダへ戻るリンク（ループ）をエラーとして返すので読み飛ばす
+    let walker = WalkDir::new(&root).follow_links(options.follow_symlinks);
+    let walker = match (options.include_subdirectories, options.max_depth) {
+        (false, _) => walker.max_depth(1),
+        (true, Some(depth)) => walker.max_depth(depth),
+        (true, None) => walker,
+    };
+    let walker = walker.sort_by(|a, b| {
+        a.file_type()
+            .is_dir()
+            .cmp(&b.file_type().is_dir())
+            .then_with(|| a.file_name().cmp(b.file_name()))
+    });
+    // 隠しフォルダは配下ごと読み飛ばす（起点のフォルダ自体は対象外）。適用前のバックアップのフォルダは常に読み飛ばす
+    let entries = walker.into_iter().filter_entry(|e| {
+        let backup_dir = e.file_type().is_dir() && e.file_name() == BACKUP_DIR_NAME;
+        !(e.depth() > 0 && (backup_dir || (options.exclude_hidden && is_hidden(e))))
+    });
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                if let Some(ancestor) = e.loop_ancestor() {
+                    debug!(path = ?e.path(), ancestor = %ancestor.display(), "symlink_loop_skipped");
                 }
-                if !path_substrings.is_empty() {
-                    if path_substrings.iter().any(|sub| path_lower.contains(sub)) {
-                        debug!(target: "exclude", path = %full_path.display(), reason = "substring");
-                        continue;
+                continue;
+            }
+        };
+        // たどらない場合、リンクの file_type はリンク自体（ファイルではない）なので一覧に出ない
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        // 除外判定と表示はプレフィックスを外した通常のパスで行う
+        let path = strip_extended_prefix(&entry.path().to_string_lossy());
+        if let Some(index) = matcher.excluded_by(Path::new(&path)) {
+            summary.excluded_by[index].1 += 1;
+            continue;
+        }
+        // サイズを読めないファイルはサイズでは除外しない
+        if size_filter.is_active() {
+            if let Ok(meta) = entry.metadata() {
+                if size_filter.rejects(meta.len()) {
+                    debug!(target: "exclude", path = %path, size = meta.len(), "excluded_by_size");
+                    summary.size_filtered += 1;
+                    if let Some(last) = summary.excluded_by.last_mut() {
+                        last.1 += 1;
                     }
-                }
-                if let Some(file_name) = full_path.file_name().and_then(|s| s.to_str()) {
-                    let original_path = full_path.to_string_lossy().to_string();
-                    let new_name = file_name.to_string();
-                    files.push_back(FileEntry {
-                        original_path,
-                        new_name,
-                        search_pattern: data.search_pattern.clone(),
-                        replace_pattern: data.replace_pattern.clone(),
-                        case_sensitive: data.case_sensitive,
-                    });
+                    continue;
                 }
             }
         }
-        data.files = files;
-        data.status_message = format!("ファイル {} 件を読み込み", data.files.len());
-        debug!("loaded_files: {}", data.files.len());
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        // UTF-8 でない名前は置き換え文字（�）入りの近似でしか扱えず、その名前では元のファイルを指せないため読み込まない
+        if entry.path().to_str().is_none() {
+            warn!(path = %path, "non_utf8_name_skipped");
+            summary.invalid_names.push(path);
+            continue;
+        }
+        let ext = extension_key(&path);
+        *summary.extensions.entry(ext.clone()).or_default() += 1;
+        if options.excluded_extensions.contains(&ext) {
+            continue;
+        }
+        if !on_path(dir, path) {
+            return false;
+        }
+    }
+    true
+}
+
+/// 拡張子の絞り込みで使う拡張子（小文字、拡張子がない・`.` で始まるだけの名前は空）。
+/// 読み込み時に数えるため、複合拡張子の設定によらず最後の `.` 以降だけを見る（`a.tar.gz` は `gz`）。
+pub fn extension_key(path: &str) -> String {
+    let name = Path::new(path).file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    split_basename_ext(&name, false).map(|(_, ext)| ext.to_lowercase()).unwrap_or_default()
+}
+
+/// 走査で数えた拡張子ごとのファイル数から、拡張子の絞り込みの一覧を作り直す。
+pub fn set_extension_chips(data: &mut AppState, extensions: &BTreeMap<String, usize>) {
+    data.extension_chips = extensions
+        .iter()
+        .map(|(ext, count)| ExtensionChip {
+            ext: ext.clone(),
+            count: *count,
+            enabled: !data.excluded_extensions.contains(ext),
+        })
+        .collect();
+}
+
+/// 拡張子の絞り込みを切り替え、一覧を読み直す。
+pub fn toggle_extension(data: &mut AppState, ext: &str, sink: ExtEventSink) {
+    if let Some(i) = data.excluded_extensions.iter().position(|e| e == ext) {
+        data.excluded_extensions.remove(i);
     } else {
-        data.status_message = "ディレクトリが見つかりません".to_string();
+        data.excluded_extensions.push_back(ext.to_string());
+    }
+    for chip in data.extension_chips.iter_mut().filter(|c| c.ext == ext) {
+        chip.enabled = !data.excluded_extensions.contains(&chip.ext);
+    }
+    request_preview(data, sink);
+}
+
+/// 隠しファイルか（`.` で始まる名前、Windows では隠し/システム属性も）。
+fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    if entry.file_name().to_string_lossy().starts_with('.') {
+        return true;
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+        if let Ok(meta) = entry.metadata() {
+            if meta.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0 {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// ファイル一覧を同期的に読み込む（ヘッドレスモード用）。
+pub fn load_files(data: &mut AppState) {
+    let options = ScanOptions::from_state(data);
+    if let Some(missing) = missing_root(&options) {
+        data.set_status(missing);
         data.files = Vector::new();
+        return;
+    }
+    let mut scanned: Vec<ScannedFile> = Vec::new();
+    let summary = scan_paths(&options, |root, p| {
+        scanned.push(scanned_file(root, p, &options));
+        true
+    });
+    set_extension_chips(data, &summary.extensions);
+    data.invalid_names = summary.invalid_names.iter().cloned().collect();
+    data.exclude_breakdown = summary.exclude_breakdown();
+    data.files = scanned.into_iter().map(|f| file_entry(f, data)).collect();
+    data.set_status(summary.status(data.files.len()));
+    debug!("loaded_files: {}", data.files.len());
+}
+
+/// 走査で見つかったパスに、読み取り専用かどうかと、有効なら音楽タグを添える（走査スレッドで呼ぶ）。
+fn scanned_file(root: &str, path: String, options: &ScanOptions) -> ScannedFile {
+    let audio_tags = if options.read_audio_tags {
+        read_audio_tags(Path::new(&path)).map(Arc::new)
+    } else {
+        None
+    };
+    let read_only = is_read_only(&to_extended_path(Path::new(&path)));
+    ScannedFile { path, root: root.to_string(), audio_tags, read_only }
+}
+
+/// 走査結果を現在の検索設定付きの FileEntry に変換する。
+pub fn file_entry(scanned: ScannedFile, data: &AppState) -> FileEntry {
+    let ScannedFile { path: original_path, root, audio_tags, read_only } = scanned;
+    let new_name = Path::new(&original_path)
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    FileEntry {
+        original_path,
+        root,
+        name_template: new_name.clone(),
+        new_name,
+        search_pattern: data.search_pattern.clone(),
+        replace_pattern: data.replace_pattern.clone(),
+        case_sensitive: data.case_sensitive,
+        use_regex: data.use_regex,
+        regex_unicode: data.regex_unicode,
+        regex_dot_all: data.regex_dot_all,
+        whole_match: data.whole_match,
+        replace_count: data.replace_count,
+        replace_nth: data.replace_nth,
+        conflicts_existing: false,
+        path_error: String::new(),
+        name_note: String::new(),
+        target_path: String::new(),
+        show_target: false,
+        in_range: true,
+        selected: true,
+        duplicate_group: 0,
+        audio_tags,
+        group_header: String::new(),
+        read_only,
+        change_reason: String::new(),
+        auto_numbered: false,
+        search_only: false,
+        overwrite_existing: false,
+        fuzzy_max_distance: None,
+        hash_error: String::new(),
+        name_collision: false,
+    }
+}
+
+/// プレビューの表示フィルタを適用する。元の名前か新しい名前に部分一致（大小無視）する行だけを表示する。
+/// 表示専用のため、適用（apply_changes）の対象は変わらない。
+pub fn apply_preview_filter(data: &mut AppState) {
+    let filter = data.preview_filter.trim().to_lowercase();
+    let mut visible: Vec<FileEntry> = data
+        .preview_files
+        .iter()
+        .filter(|f| {
+            let original_name = Path::new(&f.original_path)
+                .file_name()
+                .map(|s| s.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            filter.is_empty() || original_name.contains(&filter) || f.new_name.to_lowercase().contains(&filter)
+        })
+        .cloned()
+        .collect();
+    // 問題のある行を先頭に（同じ順位の行はプレビューの順のまま）
+    if data.problems_first {
+        visible.sort_by_key(problem_rank);
+    }
+    // サブフォルダを含める場合・追加フォルダがある場合は、フォルダが変わる行に見出しを付ける。
+    // 追加フォルダがあれば、どの対象フォルダのファイルか分かるようにフォルダのパスをそのまま出す
+    let multiple_roots = ScanOptions::from_state(data).roots().len() > 1;
+    let mut current_dir: Option<PathBuf> = None;
+    for f in visible.iter_mut() {
+        let parent = Path::new(&f.original_path)
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default();
+        let grouped = data.include_subdirectories || multiple_roots;
+        f.group_header = if grouped && current_dir.as_ref() != Some(&parent) {
+            match parent.strip_prefix(&f.root) {
+                _ if multiple_roots => parent.to_string_lossy().to_string(),
+                Ok(rel) if rel.as_os_str().is_empty() => "（対象フォルダ直下）".to_string(),
+                Ok(rel) => rel.to_string_lossy().to_string(),
+                Err(_) => parent.to_string_lossy().to_string(),
+            }
+        } else {
+            String::new()
+        };
+        current_dir = Some(parent);
+    }
+    data.visible_preview = visible.into_iter().collect();
+}
+
+/// 問題のある行を先頭に並べるための順位。0: 適用できない（衝突・使えない名前）、1: 注意（名前の補正・内容を読めない）、2: 問題なし
+fn problem_rank(f: &FileEntry) -> u8 {
+    if f.conflicts_existing || f.name_collision || !f.path_error.is_empty() {
+        0
+    } else if !f.name_note.is_empty() || !f.hash_error.is_empty() {
+        1
+    } else {
+        2
+    }
+}
+
+/// コンパイル済みの置換ルール（メインの検索/置換と追加ルール）
+pub struct CompiledRule {
+    pub re: Regex,
+    pub replace: String,
+    pub use_regex: bool,
+    pub count: ReplaceCount, // 置換する一致（追加ルールは常に All）
+    pub nth: usize,
+    pub label: String, // 変更の理由として表示する名前（例 検索/置換、追加ルール 2）
+}
+
+/// ダウンロードやコピーで付く重複風の接尾辞（拡張子の直前）。大小無視。
+/// ブラウザ・OS は番号を 0 埋めせず 1 から数えるため、`(01)` や `(2019)` のような名前の一部は対象外。
+/// macOS（英語）の ` copy` は普通の単語でもあるため、ここには含めず strip_macos_copy_suffix で扱う。
+const COPY_SUFFIX_PATTERNS: &[&str] = &[
+    r"\s?\([1-9]\d{0,2}\)",                         // file (1) / file(2)
+    r"\s-\s(?:copy|コピー)(?:\s\([1-9]\d{0,2}\))?", // Windows: file - Copy (2) / file - コピー (2)
+    r"\s?のコピー(?:\s[1-9]\d{0,2})?",                // macOS 日本語: file のコピー 2
+];
+
+/// 重複風の接尾辞を取り除くプリセットのルール（連続する接尾辞もまとめて除く）。
+/// `compound` なら `archive (1).tar.gz` の `.tar.gz` を 1 つの拡張子として、その前の接尾辞を取り除く。
+fn copy_suffix_rule(compound: bool) -> CompiledRule {
+    let ext = if compound {
+        let compound: Vec<String> = COMPOUND_EXTENSIONS.iter().map(|e| regex::escape(e)).collect();
+        format!(r"\.(?:{})|\.[^.]*", compound.join("|"))
+    } else {
+        r"\.[^.]*".to_string()
+    };
+    let pattern = format!(r"(?i)^(.+?)(?:{})+({})?$", COPY_SUFFIX_PATTERNS.join("|"), ext);
+    CompiledRule {
+        re: Regex::new(&pattern).unwrap(),
+        replace: "${1}${2}".to_string(),
+        use_regex: true,
+        count: ReplaceCount::All,
+        nth: 0,
+        label: "コピー接尾辞の除去".to_string(),
     }
 }
 
-/// プレビュー更新処理
+/// macOS（英語）の複製で付く ` copy` / ` copy 2` を取り除いた名前。付いていなければ None。
+/// `Hard copy.txt` のような普通の名前と区別できないため、呼び出し側は取り除いた名前のファイル（コピー元）が
+/// 同じフォルダにある場合だけ使う。
+fn strip_macos_copy_suffix(name: &str, compound: bool) -> Option<String> {
+    let (base, ext) = match split_basename_ext(name, compound) {
+        Some((base, ext)) => (base, format!(".{}", ext)),
+        None => (name, String::new()),
+    };
+    let stripped = base.strip_suffix(" copy").or_else(|| {
+        let (rest, n) = base.rsplit_once(" copy ")?;
+        let numbered = (1..=3).contains(&n.len()) && !n.starts_with('0') && n.bytes().all(|b| b.is_ascii_digit());
+        numbered.then_some(rest)
+    })?;
+    (!stripped.is_empty()).then(|| format!("{}{}", stripped, ext))
+}
+
+/// 検索パターンから置換用の正規表現を組み立てる。
+/// 正規表現モードでなければ検索文字列はリテラルとしてエスケープし、`unicode` / `dot_all` は使わない。
+///
+/// チェックボックスの設定（大小区別・Unicode・`.` の改行一致）はパターン全体の既定値で、
+/// パターン中のインラインフラグ（`(?i)` `(?-i)` `(?u)` `(?s)` や `(?i:...)`）がその範囲で優先する。
+/// 複数行モード（`^` `$` を行頭・行末に一致させる）は常に OFF（インラインの `(?m)` では使える）。
+pub fn build_rule_regex(
+    search: &str,
+    use_regex: bool,
+    case_sensitive: bool,
+    unicode: bool,
+    dot_all: bool,
+    whole: bool,
+) -> Result<Regex, String> {
+    // 完全一致では名前全体に一致させる（グループで囲むため、正規表現の | や $1 の番号はそのまま使える）
+    let anchor = |pattern: String| if whole { format!("^(?:{})$", pattern) } else { pattern };
+    if !use_regex {
+        // エスケープした文字列でも、長すぎると大きさの上限でエラーになりうる
+        return RegexBuilder::new(&anchor(regex::escape(search)))
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map_err(|e| describe_regex_error(&e, true));
+    }
+    RegexBuilder::new(&anchor(search.to_string()))
+        .case_insensitive(!case_sensitive)
+        .unicode(unicode)
+        .dot_matches_new_line(dot_all)
+        .multi_line(false)
+        .build()
+        .map_err(|e| describe_regex_error(&e, unicode))
+}
+
+/// 正規表現を組み立てられなかった理由を、ステータスに出す説明にする。
+pub fn describe_regex_error(e: &regex::Error, unicode: bool) -> String {
+    match e {
+        regex::Error::CompiledTooBig(limit) => {
+            format!("パターンが大きすぎます（上限 約 {} KB）。検索する文字列や置換表を分けてください", limit / 1024)
+        }
+        // Unicode OFF では `.` や `[^a]` が UTF-8 でないバイト列に一致しうるためエラーになる
+        _ if !unicode && e.to_string().contains("invalid UTF-8") => {
+            format!("{}\n（Unicode を OFF にすると `.` や `[^…]` は使えません。`(?u:.)` のように書くとその部分だけ Unicode で判定します）", e)
+        }
+        _ => e.to_string(),
+    }
+}
+
+/// メインの検索/置換 → 追加ルールの順にコンパイルする。検索が空のルールは読み飛ばす。
+pub fn compile_rules(data: &AppState) -> Result<Vec<CompiledRule>, String> {
+    let mut rules = Vec::new();
+    if data.strip_copy_suffixes {
+        rules.push(copy_suffix_rule(data.compound_extensions));
+    }
+    if !data.search_pattern.is_empty() {
+        let re = build_rule_regex(
+            &data.search_pattern,
+            data.use_regex,
+            data.case_sensitive,
+            data.regex_unicode,
+            data.regex_dot_all,
+            data.whole_match,
+        )?;
+        rules.push(CompiledRule {
+            re,
+            replace: data.replace_pattern.clone(),
+            use_regex: data.use_regex,
+            count: data.replace_count,
+            nth: data.replace_nth,
+            label: "検索/置換".to_string(),
+        });
+    }
+    for (i, rule) in data.extra_rules.iter().enumerate() {
+        if rule.search.is_empty() {
+            continue;
+        }
+        let re = build_rule_regex(&rule.search, rule.use_regex, rule.case_sensitive, data.regex_unicode, data.regex_dot_all, false)
+            .map_err(|e| format!("追加ルール {}: {}", i + 1, e))?;
+        rules.push(CompiledRule {
+            re,
+            replace: rule.replace.clone(),
+            use_regex: rule.use_regex,
+            count: ReplaceCount::All,
+            nth: 0,
+            label: format!("追加ルール {}", i + 1),
+        });
+    }
+    Ok(rules)
+}
+
+/// 1 ファイル分の新ファイル名を算出する（置換ルールを順に適用 → 後処理）。
+/// `parent` は `{parent}` に展開する親フォルダの名前。名前を変えた処理の名前を順に `reasons` に追加する。
+pub fn compute_new_name(
+    original_name: &str,
+    parent: &str,
+    rules: &[CompiledRule],
+    data: &AppState,
+    reasons: &mut Vec<String>,
+) -> String {
+    // 元の番号は {num} で使えるように取っておき、除く設定なら名前から除いてから他の処理をする
+    let compound = data.compound_extensions;
+    let existing = split_existing_number(original_name, compound);
+    let mut name = original_name.to_string();
+    if let Some((_, rest)) = existing.as_ref().filter(|_| data.strip_existing_number) {
+        apply_step(&mut name, rest.clone(), "元の番号の除去", reasons);
+    }
+    for rule in rules {
+        let replaced = replace_with_rule(&name, rule);
+        apply_step(&mut name, replaced, &rule.label, reasons);
+    }
+    if let Some(table) = &data.lookup_table {
+        let replaced = table.apply(&name);
+        apply_step(&mut name, replaced, "置換表", reasons);
+    }
+    if data.swap_parts {
+        let swapped = swap_parts(&name, &data.swap_delimiter, &data.swap_joiner, data.swap_split_first, compound);
+        apply_step(&mut name, swapped, "前後の入れ替え", reasons);
+    }
+    if data.renumber {
+        let renumbered = renumber_token(&name, data.renumber_digits, data.renumber_whole, compound);
+        apply_step(&mut name, renumbered, "連番の振り直し", reasons);
+    }
+    if !data.insert_text.is_empty() {
+        let inserted = insert_at_char(&name, data.insert_position, &data.insert_text, compound);
+        apply_step(&mut name, inserted, "挿入", reasons);
+    }
+    if !data.prefix.is_empty() || !data.suffix.is_empty() {
+        let added = add_prefix_suffix(&name, &data.prefix, &data.suffix, compound);
+        apply_step(&mut name, added, "接頭辞/接尾辞", reasons);
+    }
+    if name.contains("{num") {
+        // 元の番号のないファイルは変更しない
+        let Some((number, _)) = &existing else {
+            reasons.clear();
+            return original_name.to_string();
+        };
+        name = expand_existing_number(&name, number);
+    }
+    // 親フォルダ名は後処理（ASCII化・最大文字数など）より前に展開し、フォルダ名にも後処理を効かせる
+    if name.contains("{parent}") {
+        let expanded = name.replace("{parent}", parent);
+        apply_step(&mut name, expanded, "{parent}", reasons);
+    }
+    if data.normalize_extension_aliases || data.lowercase_extension {
+        let normalized = normalize_extension(&name, data.normalize_extension_aliases, data.lowercase_extension, compound);
+        apply_step(&mut name, normalized, "拡張子の正規化", reasons);
+    }
+    if data.fullwidth_to_halfwidth {
+        let converted = fullwidth_to_halfwidth(&name);
+        apply_step(&mut name, converted, "全角→半角", reasons);
+    }
+    if data.normalize_separators {
+        let normalized = normalize_separators(
+            &name,
+            data.separator_from,
+            data.separator_to,
+            data.separator_collapse,
+            data.separator_trim,
+            compound,
+        );
+        apply_step(&mut name, normalized, "区切り文字の正規化", reasons);
+    }
+    if data.strip_diacritics {
+        let stripped = strip_diacritics(&name);
+        apply_step(&mut name, stripped, "アクセント除去", reasons);
+    }
+    if data.transliterate_ascii {
+        let converted = transliterate_to_ascii(&name, &data.ascii_placeholder, compound);
+        apply_step(&mut name, converted, "ASCII化", reasons);
+    }
+    let normalized = normalize_unicode(&name, data.unicode_normalization);
+    apply_step(&mut name, normalized, "Unicode 正規化", reasons);
+    if data.max_name_length > 0 {
+        let truncated = truncate_name(&name, data.max_name_length, data.truncate_with_ellipsis, compound);
+        apply_step(&mut name, truncated, "最大文字数", reasons);
+    }
+    name
+}
+
+/// 処理の結果 `next` で名前を置き換え、名前が変わったなら処理の名前 `reason` を記録する。
+fn apply_step(name: &mut String, next: String, reason: &str, reasons: &mut Vec<String>) {
+    if *name != next {
+        reasons.push(reason.to_string());
+        *name = next;
+    }
+}
+
+/// 許可文字の制限（有効な場合）。タグや日時などのトークンを展開した後の最後の処理として適用する。
+/// 後で展開する連番 `{n}` / `{n:桁数}` / `{n:auto}` は私用領域の文字に退避して置き換えの対象から外す。
+fn restrict_name(name: String, data: &AppState) -> String {
+    if !data.restrict_chars {
+        return name;
+    }
+    static SEQUENCE_TOKEN: OnceLock<Regex> = OnceLock::new();
+    let token_re = SEQUENCE_TOKEN.get_or_init(|| Regex::new(r"\{n(?::(?:\d+|auto))?\}").unwrap());
+    let mut tokens: Vec<String> = Vec::new();
+    let protected = token_re.replace_all(&name, |caps: &regex::Captures| {
+        tokens.push(caps[0].to_string());
+        char::from_u32(TOKEN_PLACEHOLDER_BASE + tokens.len() as u32 - 1).unwrap_or('_').to_string()
+    });
+    let mut allowed = parse_allowed_chars(&data.allowed_chars);
+    if !tokens.is_empty() {
+        let last = char::from_u32(TOKEN_PLACEHOLDER_BASE + tokens.len() as u32 - 1).unwrap_or('_');
+        allowed.push((char::from_u32(TOKEN_PLACEHOLDER_BASE).unwrap_or('_'), last));
+    }
+    let mut restricted = restrict_to_allowed(
+        &protected,
+        &allowed,
+        &data.restrict_replacement,
+        data.collapse_replacements,
+        data.compound_extensions,
+    );
+    for (i, token) in tokens.iter().enumerate() {
+        if let Some(placeholder) = char::from_u32(TOKEN_PLACEHOLDER_BASE + i as u32) {
+            restricted = restricted.replace(placeholder, token);
+        }
+    }
+    restricted
+}
+
+/// restrict_name で連番トークンを退避する私用領域の先頭
+const TOKEN_PLACEHOLDER_BASE: u32 = 0xE000;
+
+/// 正規表現テスト欄のサンプル文字列に現在の検索/置換を適用した結果
+pub fn apply_to_test_input(data: &AppState) -> String {
+    if data.test_input.is_empty() {
+        return String::new();
+    }
+    let rules = match compile_rules(data) {
+        Ok(rules) => rules,
+        Err(e) => return format!("正規表現エラー: {}", e),
+    };
+    let source_name = match compile_script(data) {
+        Ok(Some(script)) => match script.eval(&ScriptVars::sample(&data.test_input, 1, data.compound_extensions)) {
+            Ok(name) => name,
+            Err(e) => return format!("式のエラー: {}", e),
+        },
+        Ok(None) => data.test_input.clone(),
+        Err(e) => return format!("式のエラー: {}", e),
+    };
+    let source_name = match compile_date_reformat(data) {
+        Ok(Some(date)) => match date.apply(&source_name) {
+            Some(name) => name,
+            None => return format!("{}（日付が見つからないため変更しません）", data.test_input),
+        },
+        Ok(None) => source_name,
+        Err(e) => return format!("日付の書式のエラー: {}", e),
+    };
+    // サンプル名にはフォルダがないため、{parent} は対象フォルダの名前で試す
+    let parent = Path::new(&data.selected_dir).file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let new_name = restrict_name(compute_new_name(&source_name, &parent, &rules, data, &mut Vec::new()), data);
+    let new_name = if cfg!(windows) && data.strip_trailing_dots { strip_trailing_dots_spaces(&new_name) } else { new_name };
+    expand_sequence(&new_name, data.sequence_start, data.sequence_start)
+}
+
+/// 「名前の日付を書き換える」が ON なら書式をコンパイルする（OFF なら None）。
+fn compile_date_reformat(data: &AppState) -> Result<Option<DateReformat>, String> {
+    if !data.reformat_date {
+        return Ok(None);
+    }
+    DateReformat::new(&data.date_input_format, &data.date_output_format).map(Some)
+}
+
+/// 「式で名前を決める」が ON なら式をコンパイルする（OFF なら None）。
+fn compile_script(data: &AppState) -> Result<Option<NameScript>, String> {
+    if !data.use_script {
+        return Ok(None);
+    }
+    NameScript::compile(&data.script_expr).map(Some)
+}
+
+/// 1 つのルールで置換する（すべての置換はここを通る）。
+/// 置換文字列の `{m}` / `{m:桁数}` はファイルごと・ルールごとに 1 から数える一致の通し番号に、
+/// 正規表現モードでは続けて `$1` などをキャプチャで展開する。
+/// ファイルごとの連番 `{n}` はここでは展開せず、プレビューの並び順が決まってから展開する。
+fn replace_with_rule(name: &str, rule: &CompiledRule) -> String {
+    let has_match_counter = rule.replace.contains("{m");
+    if !has_match_counter && rule.count == ReplaceCount::All {
+        return if rule.use_regex {
+            // 正規表現モードでは置換文字列の $1 などを展開する
+            rule.re.replace_all(name, rule.replace.as_str()).to_string()
+        } else {
+            rule.re.replace_all(name, NoExpand(rule.replace.as_str())).to_string()
+        };
+    }
+    // 最初/最後/N番目のみ置換する場合、対象外の一致は元の文字列のまま残す
+    let total = if rule.count == ReplaceCount::Last {
+        rule.re.find_iter(name).count()
+    } else {
+        0
+    };
+    let mut match_index = 0usize;
+    rule.re
+        .replace_all(name, |caps: &regex::Captures| {
+            match_index += 1;
+            if !rule.count.selects(rule.nth, match_index - 1, total) {
+                return caps[0].to_string();
+            }
+            let replacement = expand_counter(&rule.replace, 'm', match_index, match_index);
+            if rule.use_regex {
+                let mut out = String::new();
+                caps.expand(&replacement, &mut out);
+                out
+            } else {
+                replacement
+            }
+        })
+        .into_owned()
+}
+
+/// 連番の振り直し: 拡張子の前の部分の最後の数字（例 `ep07` の `07`）を連番トークン `{n:桁数}` に置き換える。
+/// 元の番号は無視し、プレビューの並び順で振り直す。`whole` なら拡張子の前の部分全体を番号にする。
+/// 数字のない名前は末尾に番号を付ける。
+fn renumber_token(name: &str, digits: usize, whole: bool, compound: bool) -> String {
+    let token = if digits > 0 { format!("{{n:{}}}", digits) } else { "{n}".to_string() };
+    let (base, ext) = match split_basename_ext(name, compound) {
+        Some((base, ext)) => (base, format!(".{}", ext)),
+        None => (name, String::new()),
+    };
+    if whole {
+        return format!("{}{}", token, ext);
+    }
+    static DIGITS: OnceLock<Regex> = OnceLock::new();
+    let digits_re = DIGITS.get_or_init(|| Regex::new(r"[0-9]+").unwrap());
+    match digits_re.find_iter(base).last() {
+        Some(m) => format!("{}{}{}{}", &base[..m.start()], token, &base[m.end()..], ext),
+        None => format!("{}{}{}", base, token, ext),
+    }
+}
+
+/// `{num}` / `{num:桁数}` を元の番号 `number`（桁数に満たなければゼロ埋め）で置き換える。
+/// `{num}` は番号の値なので、元の `007` は `7`、`{num:3}` なら `007` になる。
+fn expand_existing_number(name: &str, number: &str) -> String {
+    static NUM_TOKEN: OnceLock<Regex> = OnceLock::new();
+    let re = NUM_TOKEN.get_or_init(|| Regex::new(r"\{num(?::(\d+))?\}").unwrap());
+    re.replace_all(name, |caps: &regex::Captures| {
+        let width = caps.get(1).and_then(|m| m.as_str().parse::<usize>().ok()).unwrap_or(0);
+        match number.parse::<u64>() {
+            Ok(value) => format!("{:0width$}", value, width = width),
+            // 桁数が多すぎて数値にできない番号はそのまま使う
+            Err(_) => number.to_string(),
+        }
+    })
+    .into_owned()
+}
+
+/// 拡張子の前の部分に接頭辞・接尾辞を付ける。拡張子がなければ接尾辞は末尾に付く。
+fn add_prefix_suffix(name: &str, prefix: &str, suffix: &str, compound: bool) -> String {
+    match split_basename_ext(name, compound) {
+        Some((base, ext)) => format!("{}{}{}.{}", prefix, base, suffix, ext),
+        None => format!("{}{}{}", prefix, name, suffix),
+    }
+}
+
+/// プレビュー更新処理（読み込み済みのファイル一覧に対して新ファイル名を計算する）
 pub fn update_preview(data: &mut AppState) {
-    load_files(data);
+    data.hash_pending = Vector::new();
+    if data.search_only {
+        update_search_results(data);
+        return;
+    }
     let search_pattern = data.search_pattern.clone();
     let replace_pattern = data.replace_pattern.clone();
     let case_sensitive = data.case_sensitive;
-    let re = if search_pattern.is_empty() {
-        None
+    let use_regex = data.use_regex;
+    let regex_unicode = data.regex_unicode;
+    let regex_dot_all = data.regex_dot_all;
+    let whole_match = data.whole_match;
+    let replace_count = data.replace_count;
+    let replace_nth = data.replace_nth;
+    let rules = match compile_rules(data) {
+        Ok(rules) => rules,
+        Err(e) => {
+            data.set_status(format!("検索パターンのエラー: {}", e));
+            debug!(err = %e, "search_regex_error");
+            return;
+        }
+    };
+    let script = match compile_script(data) {
+        Ok(script) => script,
+        Err(e) => {
+            data.set_status(format!("式のエラー: {}", e));
+            debug!(err = %e, "script_compile_error");
+            return;
+        }
+    };
+    let date_reformat = match compile_date_reformat(data) {
+        Ok(date) => date,
+        Err(e) => {
+            data.set_status(format!("日付の書式のエラー: {}", e));
+            return;
+        }
+    };
+    let settings = data.clone();
+    // ` copy` を取り除くのはコピー元が一覧にある場合だけ（重複の判定と同じく、読み込んだファイルで判定する）
+    let loaded_paths: HashSet<String> = if settings.strip_copy_suffixes {
+        data.files.iter().map(|f| collision_key(&f.original_path, settings.case_insensitive_fs)).collect()
     } else {
-        let escaped = regex::escape(&search_pattern);
-        let mut builder = RegexBuilder::new(&escaped);
-        builder.case_insensitive(!case_sensitive);
-        Some(builder.build().unwrap())
+        HashSet::new()
     };
-    for file in data.files.iter_mut() {
+    let mut matched = 0usize;
+    let mut script_errors: Vec<String> = Vec::new();
+    let mut hash_pending: Vector<String> = Vector::new();
+    for (i, file) in data.files.iter_mut().enumerate() {
         let path = Path::new(&file.original_path);
-        let original_name = path
-            .file_name()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_default();
-        if let Some(ref re) = re {
-            let replaced = re
-                .replace_all(&original_name, NoExpand(replace_pattern.as_str()))
-                .to_string();
-            debug!(orig = %original_name, new = %replaced, "preview_rename");
-            file.new_name = replaced;
+        let original_name = scope_name(file, &settings);
+        let macos_copy = strip_macos_copy_suffix(&original_name, settings.compound_extensions).filter(|stripped| {
+            let file_name = Path::new(stripped).file_name().unwrap_or_default();
+            loaded_paths.contains(&collision_key(&path.with_file_name(file_name).to_string_lossy(), settings.case_insensitive_fs))
+        });
+        if macos_copy.is_some() || rules_match(&original_name, &rules) {
+            matched += 1;
+        }
+        // 式を使う場合は式の結果に対して置換・後処理を行う
+        let mut reasons: Vec<String> = Vec::new();
+        let pasted = settings.pasted_names.get(&file.original_path).cloned();
+        let source_name = match &script {
+            _ if pasted.is_some() => None,
+            Some(script) => match script.eval(&ScriptVars::from_file(path, i + 1, data.compound_extensions)) {
+                Ok(name) => {
+                    if name != original_name {
+                        reasons.push("式".to_string());
+                    }
+                    Some(name)
+                }
+                Err(e) => {
+                    debug!(orig = %original_name, err = %e, "script_eval_error");
+                    script_errors.push(format!("{}: {}", original_name, e));
+                    None
+                }
+            },
+            None => match macos_copy {
+                Some(stripped) => {
+                    reasons.push("コピー接尾辞の除去".to_string());
+                    Some(stripped)
+                }
+                None => Some(original_name.clone()),
+            },
+        };
+        // 名前の日付の書き換えは置換より前に行う。日付が見つからないファイルは変更しない
+        let source_name = match (&date_reformat, source_name) {
+            (Some(date), Some(name)) => date.apply(&name).inspect(|_| reasons.push("日付の書き換え".to_string())),
+            (_, source_name) => source_name,
+        };
+        file.hash_error = String::new();
+        let new_name = match (pasted, source_name) {
+            // 貼り付けた名前はそのまま使う（検索・置換や後処理はしない）
+            (Some(pasted), _) => {
+                reasons.push("貼り付けた名前".to_string());
+                pasted
+            }
+            (None, Some(source_name)) => {
+                let mut new_name = compute_new_name(&source_name, &parent_name(path), &rules, &settings, &mut reasons);
+                if new_name.contains("{dir}") {
+                    new_name = new_name.replace("{dir}", &relative_dir_token(&file.original_path, &file.root));
+                    reasons.push("{dir}".to_string());
+                }
+                if settings.use_audio_tags && has_tag_tokens(&new_name) {
+                    // タグのないファイル（音楽以外を含む）は変更しない
+                    new_name = match &file.audio_tags {
+                        Some(tags) => {
+                            reasons.push("音楽タグ".to_string());
+                            expand_tag_tokens(&new_name, tags)
+                        }
+                        None => {
+                            reasons.clear();
+                            original_name.clone()
+                        }
+                    };
+                }
+                if has_exif_token(&new_name) {
+                    new_name = expand_exif_tokens(&new_name, path, settings.exif_fallback_mtime);
+                    reasons.push("撮影日時".to_string());
+                }
+                if has_hash_token(&new_name) {
+                    // 計算が済んでいないファイルは、済むまで変更しない。読めないファイルはトークンを空にして知らせる
+                    new_name = match content_hash(&file.original_path) {
+                        ContentHash::Ready(hash) => {
+                            reasons.push("内容のハッシュ".to_string());
+                            expand_hash_tokens(&new_name, Some(&hash))
+                        }
+                        ContentHash::Unreadable(e) => {
+                            file.hash_error = e;
+                            reasons.push("内容のハッシュ（読めないため空）".to_string());
+                            expand_hash_tokens(&new_name, None)
+                        }
+                        ContentHash::Pending => {
+                            hash_pending.push_back(file.original_path.clone());
+                            reasons.clear();
+                            original_name.clone()
+                        }
+                    };
+                }
+                let restricted = restrict_name(new_name.clone(), &settings);
+                apply_step(&mut new_name, restricted, "許可文字のみ", &mut reasons);
+                new_name
+            }
+            // 式を評価できなかったファイル・日付が見つからないファイルは変更しない
+            (None, None) => {
+                reasons.clear();
+                original_name.clone()
+            }
+        };
+        // Windows は末尾の . と空白を黙って取り除くため、前もって取り除き、意図した名前を残して知らせる
+        file.name_note = String::new();
+        let new_name = if cfg!(windows) && settings.strip_trailing_dots && new_name != original_name {
+            let stripped = strip_trailing_dots_spaces(&new_name);
+            if stripped != new_name {
+                file.name_note = new_name;
+                reasons.push("末尾の . ・空白の除去".to_string());
+            }
+            stripped
         } else {
-            file.new_name = original_name;
+            new_name
+        };
+        if new_name != original_name {
+            debug!(orig = %original_name, new = %new_name, "preview_rename");
         }
+        if new_name == original_name {
+            reasons.clear();
+        }
+        file.change_reason = reasons.join(" → ");
+        file.name_template = new_name.clone();
+        file.new_name = new_name;
+        file.in_range = true;
         file.search_pattern = search_pattern.clone();
         file.replace_pattern = replace_pattern.clone();
         file.case_sensitive = case_sensitive;
+        file.use_regex = use_regex;
+        file.regex_unicode = regex_unicode;
+        file.regex_dot_all = regex_dot_all;
+        file.whole_match = whole_match;
+        file.replace_count = replace_count;
+        file.replace_nth = replace_nth;
+        file.search_only = false;
+        file.fuzzy_max_distance = None;
     }
-    let mut preview = druid::im::Vector::new();
+    // フラット化ではサブフォルダ内のファイルは名前が同じでも移動するため一覧に含める
+    let flatten = data.flatten && data.include_subdirectories && !data.match_full_path;
+    let mut preview = Vector::new();
     for file in data.files.iter() {
         let path = Path::new(&file.original_path);
-        let original_name = path
-            .file_name()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_default();
-        if original_name != file.new_name {
+        let root = Path::new(&file.root);
+        let original_name = scope_name(file, data);
+        if original_name != file.new_name || (flatten && path.parent() != Some(root)) {
             preview.push_back(file.clone());
         }
     }
+    // 検索ルールがなければ一致件数は表示しない
+    data.matched_count = if rules.is_empty() { None } else { Some(matched) };
+    data.script_error = match script_errors.first() {
+        Some(first) => format!("式のエラー {} 件（変更しません）: {}", script_errors.len(), first),
+        None => String::new(),
+    };
+    data.hash_pending = hash_pending;
+    finalize_preview(data, preview);
+    if !data.hash_pending.is_empty() {
+        data.status_message.push_str(&format!("（内容のハッシュを計算中 {} 件、済んだら更新します）", data.hash_pending.len()));
+    }
+}
+
+/// 名前の変更の対象になる文字列。「パス全体を対象」なら対象フォルダからの相対パス（区切りは /）、
+/// それ以外はファイル名。
+pub fn scope_name(f: &FileEntry, data: &AppState) -> String {
+    let path = Path::new(&f.original_path);
+    if data.match_full_path {
+        if let Ok(rel) = path.strip_prefix(&f.root) {
+            return rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+        }
+    }
+    path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+/// `{parent}` に展開する、ファイルのあるフォルダの名前。対象フォルダ直下のファイルでは対象フォルダ自体の名前
+/// （ドライブの直下など名前のないフォルダでは空）。
+fn parent_name(path: &Path) -> String {
+    path.parent()
+        .and_then(|p| p.file_name())
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// `{dir}` に展開する、対象フォルダからのサブフォルダの相対パス（区切りは `_`、直下のファイルは空）
+fn relative_dir_token(original_path: &str, root: &str) -> String {
+    Path::new(original_path)
+        .parent()
+        .and_then(|p| p.strip_prefix(root).ok())
+        .map(|rel| {
+            rel.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("_")
+        })
+        .unwrap_or_default()
+}
+
+/// 新しい名前を置くフォルダ（フラット化・パス全体を対象では対象フォルダ直下、それ以外は元のフォルダ）
+fn destination_dir(f: &FileEntry, data: &AppState) -> PathBuf {
+    if (data.flatten && data.include_subdirectories) || data.match_full_path {
+        return PathBuf::from(&f.root);
+    }
+    Path::new(&f.original_path)
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default()
+}
+
+/// いずれかのルールが（前のルールを適用した途中結果に対して）一致するか。
+fn rules_match(original_name: &str, rules: &[CompiledRule]) -> bool {
+    let mut name = original_name.to_string();
+    for rule in rules {
+        if rule.re.is_match(&name) {
+            return true;
+        }
+        name = replace_with_rule(&name, rule);
+    }
+    false
+}
+
+/// 検索のみモード: メインの検索に一致するファイル（名前、パス全体を対象にする場合はパス）を探して一覧にする。
+/// 置換・後処理は行わず、名前は変えない（適用もできない）。
+fn update_search_results(data: &mut AppState) {
+    data.auto_numbered_count = 0;
+    if data.fuzzy_search && !data.search_pattern.is_empty() {
+        update_fuzzy_results(data);
+        return;
+    }
+    let re = if data.search_pattern.is_empty() {
+        None
+    } else {
+        match build_rule_regex(
+            &data.search_pattern,
+            data.use_regex,
+            data.case_sensitive,
+            data.regex_unicode,
+            data.regex_dot_all,
+            data.whole_match,
+        ) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                data.set_status(format!("検索パターンのエラー: {}", e));
+                debug!(err = %e, "search_regex_error");
+                return;
+            }
+        }
+    };
+    let settings = data.clone();
+    let mut results = Vector::new();
+    for file in data.files.iter_mut() {
+        let name = scope_name(file, &settings);
+        file.name_template = name.clone();
+        file.new_name = name;
+        file.change_reason = String::new();
+        file.in_range = true;
+        file.search_pattern = settings.search_pattern.clone();
+        file.replace_pattern = String::new();
+        file.case_sensitive = settings.case_sensitive;
+        file.use_regex = settings.use_regex;
+        file.regex_unicode = settings.regex_unicode;
+        file.regex_dot_all = settings.regex_dot_all;
+        file.whole_match = settings.whole_match;
+        file.replace_count = ReplaceCount::All;
+        file.replace_nth = 0;
+        file.search_only = true;
+        file.fuzzy_max_distance = None;
+        if re.as_ref().is_some_and(|re| re.is_match(&file.new_name)) {
+            results.push_back(file.clone());
+        }
+    }
+    data.matched_count = re.as_ref().map(|_| results.len());
+    data.preview_files = results;
+    apply_preview_filter(data);
+    if re.is_none() {
+        data.set_status("検索のみ: 探す名前を検索欄に入力してください");
+    } else {
+        data.set_status(format!("検索のみ: 一致 {} 件（名前は変更しません）", data.preview_files.len()));
+    }
+}
+
+/// あいまい検索: 検索語との編集距離が設定以下の部分を含む名前を探す（正規表現・完全一致の設定は使わない）。
+/// 結果は近い順（同じ距離なら一覧の順）に並べる。
+fn update_fuzzy_results(data: &mut AppState) {
+    let settings = data.clone();
+    let mut results: Vec<(usize, FileEntry)> = Vec::new();
+    for file in data.files.iter_mut() {
+        let name = scope_name(file, &settings);
+        let found = fuzzy_find(&name, &settings.search_pattern, settings.fuzzy_distance, settings.case_sensitive);
+        file.name_template = name.clone();
+        file.new_name = name;
+        file.change_reason = String::new();
+        file.in_range = true;
+        file.search_pattern = settings.search_pattern.clone();
+        file.replace_pattern = String::new();
+        file.case_sensitive = settings.case_sensitive;
+        file.replace_count = ReplaceCount::All;
+        file.replace_nth = 0;
+        file.search_only = true;
+        file.fuzzy_max_distance = Some(settings.fuzzy_distance);
+        if let Some(found) = found {
+            results.push((found.distance, file.clone()));
+        }
+    }
+    results.sort_by_key(|(distance, _)| *distance);
+    let exact = results.iter().filter(|(distance, _)| *distance == 0).count();
+    data.matched_count = Some(results.len());
+    data.preview_files = results.into_iter().map(|(_, f)| f).collect();
+    apply_preview_filter(data);
+    data.set_status(format!(
+        "検索のみ（あいまい、距離 {} まで）: 一致 {} 件、うち完全に一致 {} 件（名前は変更しません）",
+        data.fuzzy_distance,
+        data.preview_files.len(),
+        exact
+    ));
+}
+
+/// プレビュー行の並び順で連番 `{n}` を振り直す（行の並べ替え後に呼ぶ）。
+/// ファイル一覧は再読み込みしないため、並び順は次のプレビュー更新まで保持される。
+pub fn renumber_preview(data: &mut AppState) {
+    // 検索のみモードの結果は名前を変えないため、表示だけを更新する
+    if data.search_only {
+        apply_preview_filter(data);
+        return;
+    }
+    let preview = data.preview_files.clone();
+    finalize_preview(data, preview);
+}
+
+/// プレビュー行のチェックを切り替える。`extend`（Shift+クリック）なら前回クリックした行からこの行までを
+/// 前回の行と同じ状態にそろえる。範囲は表示中（絞り込み後）の並び順で決める。
+pub fn select_preview_rows(data: &mut AppState, path: &str, extend: bool) {
+    let visible: Vec<String> = data.visible_preview.iter().map(|f| f.original_path.clone()).collect();
+    let Some(clicked) = visible.iter().position(|p| p == path) else {
+        return;
+    };
+    let anchor = if extend {
+        visible.iter().position(|p| *p == data.selection_anchor)
+    } else {
+        None
+    };
+    let (targets, value): (HashSet<String>, bool) = match anchor {
+        Some(anchor) => {
+            let (lo, hi) = (anchor.min(clicked), anchor.max(clicked));
+            (visible[lo..=hi].iter().cloned().collect(), data.visible_preview[anchor].selected)
+        }
+        None => {
+            // 起点がない（絞り込みで隠れた）場合は 1 行だけ切り替え、この行を起点にする
+            data.selection_anchor = path.to_string();
+            (HashSet::from([path.to_string()]), !data.visible_preview[clicked].selected)
+        }
+    };
+    set_selected(data, &targets, value);
+}
+
+/// 適用する変更（元のパスと変更後のパス、1 行 1 件）をクリップボードへコピーする。
+/// チェックを外した行・適用範囲外の行は含めない。
+pub fn copy_preview_to_clipboard(data: &mut AppState) {
+    let separator = match data.copy_format {
+        CopyFormat::Arrow => " → ",
+        CopyFormat::Tab => "\t",
+    };
+    let lines: Vec<String> = data
+        .preview_files
+        .iter()
+        .filter(|f| f.will_apply())
+        .map(|f| {
+            let new_path = destination_dir(f, data).join(&f.new_name);
+            format!("{}{}{}", f.original_path, separator, new_path.display())
+        })
+        .collect();
+    if lines.is_empty() {
+        data.set_status("コピーする変更がありません");
+        return;
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Application::global().clipboard().put_string(text);
+    data.set_status(format!("{} 件の変更をクリップボードにコピーしました", lines.len()));
+}
+
+/// 読み込んだすべてのファイルの元の名前を一覧の順に 1 行 1 件でクリップボードへコピーする（外部のエディタでの一括編集用）。
+pub fn copy_original_names(data: &mut AppState) {
+    if data.files.is_empty() {
+        data.set_status("コピーするファイルがありません");
+        return;
+    }
+    let mut text = data.files.iter().map(|f| scope_name(f, data)).collect::<Vec<_>>().join("\n");
+    text.push('\n');
+    Application::global().clipboard().put_string(text);
+    data.set_status(format!("{} 件の元の名前をクリップボードにコピーしました", data.files.len()));
+}
+
+/// クリップボードの名前（1 行 1 件）を一覧の順に新しい名前として使う。行数がファイル数と合わなければ使わない。
+/// 貼り付けに成功したら true を返す（プレビューの更新が必要）。
+pub fn paste_new_names(data: &mut AppState) -> bool {
+    let Some(text) = Application::global().clipboard().get_string() else {
+        data.set_status("クリップボードに文字がありません");
+        return false;
+    };
+    match map_pasted_names(&text, &data.files) {
+        Ok(names) => {
+            let changed = data.files.iter().filter(|f| names.get(&f.original_path) != Some(&scope_name(f, data))).count();
+            data.pasted_names = names;
+            data.set_status(format!("貼り付けた名前を使います（{} 件中 {} 件が変わります）", data.files.len(), changed));
+            true
+        }
+        Err(e) => {
+            data.set_status(format!("貼り付けできません: {}", e));
+            false
+        }
+    }
+}
+
+/// 貼り付けた文字を 1 行 1 件として、一覧の同じ位置のファイルに対応付ける。
+/// 末尾の空行と行末の CR は無視する。行数の違いや空の行はエラー。
+fn map_pasted_names(text: &str, files: &Vector<FileEntry>) -> Result<druid::im::HashMap<String, String>, String> {
+    let mut lines: Vec<&str> = text.lines().map(|l| l.trim_end_matches('\r')).collect();
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    if lines.len() != files.len() {
+        return Err(format!("行数（{} 行）がファイル数（{} 件）と一致しません", lines.len(), files.len()));
+    }
+    if let Some(i) = lines.iter().position(|l| l.trim().is_empty()) {
+        return Err(format!("{} 行目が空です", i + 1));
+    }
+    Ok(files.iter().zip(lines).map(|(f, name)| (f.original_path.clone(), name.to_string())).collect())
+}
+
+/// 表示中（絞り込み後）のプレビュー行すべてのチェックを入れる／外す。
+pub fn select_all_visible(data: &mut AppState, value: bool) {
+    let targets: HashSet<String> = data.visible_preview.iter().map(|f| f.original_path.clone()).collect();
+    set_selected(data, &targets, value);
+}
+
+/// 指定した行のチェックを変え、並び順を保ったまま連番付与・衝突判定をやり直す。
+pub fn set_selected(data: &mut AppState, targets: &HashSet<String>, value: bool) {
+    for f in data.files.iter_mut().filter(|f| targets.contains(&f.original_path)) {
+        f.selected = value;
+    }
+    for f in data.preview_files.iter_mut().filter(|f| targets.contains(&f.original_path)) {
+        f.selected = value;
+    }
+    renumber_preview(data);
+}
+
+/// プレビュー行の詳細（変更の理由と適用後のフルパス）を開く/閉じる（プレビューを更新しても開いたまま）。
+pub fn toggle_target_detail(data: &mut AppState, path: &str) {
+    let show = !data.preview_files.iter().any(|f| f.original_path == path && f.show_target);
+    for f in data.files.iter_mut().filter(|f| f.original_path == path) {
+        f.show_target = show;
+    }
+    for f in data.preview_files.iter_mut().filter(|f| f.original_path == path) {
+        f.show_target = show;
+    }
+    apply_preview_filter(data);
+}
+
+/// 1 件を一覧とプレビューから外す。同じフォルダを読み直しても戻さない（フォルダを変えると解除）。
+pub fn exclude_file(data: &mut AppState, path: &str) {
+    data.files.retain(|f| f.original_path != path);
+    data.preview_files.retain(|f| f.original_path != path);
+    if !data.session_excluded.iter().any(|p| p == path) {
+        data.session_excluded.push_back(path.to_string());
+    }
+    renumber_preview(data);
+    let name = Path::new(path).file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    data.set_status(format!("{} を一覧から除外しました（このフォルダでは {} 件を除外中）", name, data.session_excluded.len()));
+}
+
+/// 適用範囲（プレビューの何番目から何番目か、1 から数える）。範囲を限定しなければ None。
+/// 末尾は一覧の件数に丸め、開始が末尾より後なら空の範囲を返す。
+fn preview_range(data: &AppState, len: usize) -> Option<std::ops::RangeInclusive<usize>> {
+    if !data.limit_range {
+        return None;
+    }
+    let start = data.range_start.max(1);
+    let end = if data.range_end == 0 { len } else { data.range_end.min(len) };
+    Some(start..=end)
+}
+
+/// 検索と置換に同じ文字列が入っているか（どちらかを変え忘れた可能性がある）。
+/// 大小を区別しない検索で英字を含む場合は、大小をそろえる置換として意味があるため対象外。
+fn search_equals_replace(data: &AppState) -> bool {
+    let search = &data.search_pattern;
+    !search.is_empty()
+        && *search == data.replace_pattern
+        && (data.case_sensitive || search.to_lowercase() == search.to_uppercase())
+}
+
+/// プレビュー行の並び順で連番を展開し、重複検出・連番付与を行って状態へ反映する。
+fn finalize_preview(data: &mut AppState, mut preview: Vector<FileEntry>) {
+    // {n:auto} の桁数は一覧全体の最後の番号に合わせる。逆順なら最後の行が開始番号になる
+    let len = preview.len();
+    let last = data.sequence_start + len.saturating_sub(1);
+    for (i, f) in preview.iter_mut().enumerate() {
+        let position = if data.reverse_numbering { len - 1 - i } else { i };
+        f.new_name = expand_sequence(&f.name_template, data.sequence_start + position, last);
+    }
+    // 範囲外・チェックを外した行は一覧に残すが、名前は変えず連番付与・衝突判定の対象からも外す
+    let range = preview_range(data, preview.len());
+    for (i, f) in preview.iter_mut().enumerate() {
+        f.in_range = range.as_ref().is_none_or(|r| r.contains(&(i + 1)));
+    }
+    let case_insensitive_fs = data.case_insensitive_fs;
     let mut seen: HashSet<String> = HashSet::new();
     let mut dup_count = 0usize;
-    for f in preview.iter() {
-        let parent = Path::new(&f.original_path)
-            .parent()
-            .map(|p| p.to_path_buf())
-            .unwrap_or_default();
-        let new_path = parent.join(&f.new_name);
-        let key = new_path.to_string_lossy().to_string().to_ascii_lowercase();
+    for f in preview.iter().filter(|f| f.will_apply()) {
+        let new_path = destination_dir(f, data).join(&f.new_name);
+        let key = collision_key(&new_path.to_string_lossy(), case_insensitive_fs);
         if !seen.insert(key) {
             dup_count += 1;
         }
     }
 
     let mut numbered_count = 0usize;
+    let mut template_error = None;
+    for f in preview.iter_mut() {
+        f.auto_numbered = false;
+    }
     if data.auto_number_on_conflict && !preview.is_empty() {
+        // 番号のない書式では一意な名前を作れず無限ループになるため、既定の書式に戻す
+        let template = match validate_number_template(&data.auto_number_template) {
+            Ok(()) => data.auto_number_template.clone(),
+            Err(e) => {
+                template_error = Some(e);
+                " ({n})".to_string()
+            }
+        };
         let mut used_by_parent: HashMap<String, HashSet<String>> = HashMap::new();
         for f in data.files.iter() {
             let parent = Path::new(&f.original_path)
                 .parent()
                 .map(|p| p.to_path_buf())
                 .unwrap_or_default();
-            let parent_key = parent.to_string_lossy().to_string().to_ascii_lowercase();
+            let parent_key = collision_key(&parent.to_string_lossy(), case_insensitive_fs);
             let orig_name_lower = Path::new(&f.original_path)
                 .file_name()
                 .and_then(|s| s.to_str())
-                .map(|s| s.to_ascii_lowercase())
+                .map(|s| collision_key(s, case_insensitive_fs))
                 .unwrap_or_default();
             used_by_parent
                 .entry(parent_key)
@@ -197,71 +1840,442 @@ pub fn update_preview(data: &mut AppState) {
                 .insert(orig_name_lower);
         }
 
+        let options = NumberingOptions {
+            template: &template,
+            start: data.auto_number_start,
+            compound: data.compound_extensions,
+            max_name_length: data.max_name_length,
+            truncate_with_ellipsis: data.truncate_with_ellipsis,
+            case_insensitive_fs,
+        };
+        for f in preview.iter_mut().filter(|f| f.will_apply()) {
+            let parent = destination_dir(f, data);
+            let parent_key = collision_key(&parent.to_string_lossy(), case_insensitive_fs);
+            let used = used_by_parent.entry(parent_key).or_default();
+            if let Some(numbered) = numbered_name(&f.new_name, used, &options) {
+                f.new_name = numbered;
+                f.auto_numbered = true;
+                numbered_count += 1;
+            }
+            used.insert(collision_key(&f.new_name, case_insensitive_fs));
+        }
+    }
+    // 適用時と同じ判定で、一覧外の既存ファイルとの衝突を前もって示す
+    let mut existing_conflicts = 0usize;
+    let mut path_errors = 0usize;
+    for f in preview.iter_mut() {
+        f.path_error = match subfolder_name_error(&f.new_name, data.sort_into_subfolders || data.match_full_path) {
+            Some(e) if f.will_apply() => e.to_string(),
+            _ => String::new(),
+        };
+        if !f.path_error.is_empty() {
+            path_errors += 1;
+        }
+    }
+    let mode = ApplyMode::from_state(data);
+    for f in preview.iter_mut() {
+        f.target_path = match &mode {
+            Ok(mode) => mode.destination(f).to_string_lossy().to_string(),
+            Err(_) => String::new(),
+        };
+    }
+    if let Ok(mode) = mode {
         for f in preview.iter_mut() {
-            let parent = Path::new(&f.original_path)
-                .parent()
-                .map(|p| p.to_path_buf())
-                .unwrap_or_default();
-            let parent_key = parent.to_string_lossy().to_string().to_ascii_lowercase();
-            let used = used_by_parent.entry(parent_key.clone()).or_default();
-
-            let mut candidate = f.new_name.clone();
-            let mut candidate_lower = candidate.to_ascii_lowercase();
-
-            if used.contains(&candidate_lower) {
-                let (base, ext) = match candidate.rsplit_once('.') {
-                    Some((b, e)) => (b.to_string(), format!(".{}", e)),
-                    None => (candidate.clone(), String::new()),
-                };
-                let mut n: usize = 2;
-                loop {
-                    let c = format!("{} ({}){}", base, n, ext);
-                    let c_lower = c.to_ascii_lowercase();
-                    if !used.contains(&c_lower) {
-                        candidate = c;
-                        candidate_lower = c_lower;
-                        numbered_count += 1;
-                        break;
-                    }
-                    n += 1;
-                }
+            f.conflicts_existing = f.will_apply() && conflicts_with_existing(f, &mode, case_insensitive_fs);
+            if f.conflicts_existing {
+                existing_conflicts += 1;
             }
-
-            used.insert(candidate_lower);
-            f.new_name = candidate;
         }
     }
+    // 連番を付けた後も重なる行に印を付ける（問題のある行を先頭に並べる表示用）
+    let mut destinations: HashMap<String, usize> = HashMap::new();
+    let destination_key = |f: &FileEntry| collision_key(&destination_dir(f, data).join(&f.new_name).to_string_lossy(), case_insensitive_fs);
+    for f in preview.iter().filter(|f| f.will_apply()) {
+        *destinations.entry(destination_key(f)).or_default() += 1;
+    }
+    for f in preview.iter_mut() {
+        f.name_collision = f.will_apply() && destinations.get(&destination_key(f)).is_some_and(|n| *n > 1);
+    }
     if !preview.is_empty() {
-        let mut map_by_original: HashMap<String, String> = HashMap::new();
+        let mut map_by_original: HashMap<String, (String, bool)> = HashMap::new();
         for f in preview.iter() {
-            map_by_original.insert(f.original_path.clone(), f.new_name.clone());
+            map_by_original.insert(f.original_path.clone(), (f.new_name.clone(), f.in_range));
         }
         for f in data.files.iter_mut() {
-            if let Some(new_name) = map_by_original.get(&f.original_path) {
+            if let Some((new_name, in_range)) = map_by_original.get(&f.original_path) {
                 f.new_name = new_name.clone();
+                f.in_range = *in_range;
             }
         }
     }
     data.preview_files = preview;
+    apply_preview_filter(data);
+    data.auto_numbered_count = numbered_count;
+    // 例: プレビュー更新 (一致 10 件, 変更 8 件, 連番付与 2 件)
+    let mut parts: Vec<String> = Vec::new();
+    if let Some(matched) = data.matched_count {
+        parts.push(format!("一致 {} 件", matched));
+    }
+    parts.push(format!("変更 {} 件", data.preview_files.len()));
     if data.auto_number_on_conflict {
         if numbered_count > 0 {
-            data.status_message = format!(
-                "プレビュー更新 (変更 {} 件, 連番付与 {} 件)",
-                data.preview_files.len(),
-                numbered_count
-            );
-        } else {
-            data.status_message = format!("プレビュー更新 (変更 {} 件)", data.preview_files.len());
+            parts.push(format!("連番付与 {} 件", numbered_count));
         }
     } else if dup_count > 0 {
-        data.status_message = format!(
-            "プレビュー更新 (変更 {} 件, 重複 {} 件)",
-            data.preview_files.len(),
-            dup_count
-        );
-    } else {
-        data.status_message = format!("プレビュー更新 (変更 {} 件)", data.preview_files.len());
+        parts.push(format!("重複 {} 件", dup_count));
     }
+    if existing_conflicts > 0 {
+        parts.push(format!("既存ファイルと衝突 {} 件", existing_conflicts));
+    }
+    if path_errors > 0 {
+        parts.push(format!("不正なサブフォルダ指定 {} 件", path_errors));
+    }
+    let read_only = data.preview_files.iter().filter(|f| f.read_only).count();
+    if read_only > 0 {
+        parts.push(format!("読み取り専用 {} 件", read_only));
+    }
+    let noted = data.preview_files.iter().filter(|f| !f.name_note.is_empty()).count();
+    if noted > 0 {
+        parts.push(format!("末尾の . ・空白を除去 {} 件", noted));
+    }
+    let unselected = data.preview_files.iter().filter(|f| !f.selected).count();
+    if unselected > 0 {
+        parts.push(format!("選択 {} / {} 件", data.preview_files.len() - unselected, data.preview_files.len()));
+    }
+    if data.limit_range {
+        match &range {
+            Some(r) if !r.is_empty() => {
+                parts.push(format!("適用範囲 {}〜{} 番目の {} 件", r.start(), r.end(), r.end() - r.start() + 1))
+            }
+            _ => parts.push("適用範囲に該当なし".to_string()),
+        }
+    }
+    let mut status = format!("プレビュー更新 ({})", parts.join(", "));
+    // 検索と置換が同じなら、名前が変わらない理由としてその旨だけを知らせる（適用は止めない）
+    let same_text = search_equals_replace(data);
+    if let Some(matched) = data.matched_count {
+        if matched == 0 {
+            status.push_str(" ※検索に一致するファイルがありません");
+        } else if data.preview_files.is_empty() && !same_text {
+            status.push_str(" ※一致しましたが名前は変わりません");
+        }
+    }
+    if same_text {
+        status.push_str(" ※検索と置換が同じです（置換しても名前は変わりません）");
+    }
+    if !data.invalid_names.is_empty() {
+        status.push_str(&format!(" ※名前が UTF-8 でないファイル {} 件は読み込んでいません", data.invalid_names.len()));
+    }
+    if let Some(e) = template_error {
+        status.push_str(&format!(" ※連番の書式エラー: {}（既定の書式を使用）", e));
+    }
+    if !data.script_error.is_empty() {
+        status.push_str(&format!(" ※{}", data.script_error));
+    }
+    data.set_status(status);
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ReplaceRule, UnicodeNormalization};
+
+    fn new_name(original_name: &str, data: &AppState) -> (String, Vec<String>) {
+        let rules = compile_rules(data).expect("rules");
+        let mut reasons = Vec::new();
+        let name = compute_new_name(original_name, "parent", &rules, data, &mut reasons);
+        (name, reasons)
+    }
+
+    #[test]
+    fn replaces_and_records_reason() {
+        let mut data = AppState::new();
+        data.search_pattern = "IMG".to_string();
+        data.replace_pattern = "photo".to_string();
+        assert_eq!(new_name("IMG_001.jpg", &data), ("photo_001.jpg".to_string(), vec!["検索/置換".to_string()]));
+        assert_eq!(new_name("DSC_001.jpg", &data), ("DSC_001.jpg".to_string(), Vec::new()));
+    }
+
+    // 「が」の合成済み（NFC）と、「か」+ 濁点に分解した形（NFD）
+    const GA_NFC: &str = "\u{304C}.txt";
+    const GA_NFD: &str = "\u{304B}\u{3099}.txt";
+
+    #[test]
+    fn names_are_normalized_to_selected_form() {
+        let mut data = AppState::new();
+        assert_eq!(new_name(GA_NFD, &data).0, GA_NFD);
+        data.unicode_normalization = UnicodeNormalization::Nfc;
+        assert_eq!(new_name(GA_NFD, &data).0, GA_NFC);
+        data.unicode_normalization = UnicodeNormalization::Nfd;
+        assert_eq!(new_name(GA_NFC, &data).0, GA_NFD);
+    }
+
+    fn rule(search: &str, replace: &str) -> ReplaceRule {
+        ReplaceRule {
+            search: search.to_string(),
+            replace: replace.to_string(),
+            ..ReplaceRule::new(1)
+        }
+    }
+
+    /// 存在しないフォルダの `names` を読み込んだことにしてプレビューを更新し、プレビューの新しい名前を返す
+    fn preview_names(data: &mut AppState, names: &[&str]) -> Vec<String> {
+        data.files = names
+            .iter()
+            .map(|name| {
+                let scanned = ScannedFile {
+                    path: format!("/filename-change-test/{}", name),
+                    root: "/filename-change-test".to_string(),
+                    audio_tags: None,
+                    read_only: false,
+                };
+                file_entry(scanned, data)
+            })
+            .collect();
+        update_preview(data);
+        data.preview_files.iter().map(|f| f.new_name.clone()).collect()
+    }
+
+    /// 数字を取り除く変更のプレビューのステータスを返す
+    fn preview_status_without_digits(names: &[&str], case_insensitive_fs: bool) -> String {
+        let mut data = AppState::new();
+        data.use_regex = true;
+        data.search_pattern = r"\d".to_string();
+        data.case_insensitive_fs = case_insensitive_fs;
+        preview_names(&mut data, names);
+        data.status_message
+    }
+
+    #[test]
+    fn invalid_patterns_report_errors_without_changing_preview() {
+        for pattern in ["(", "[a-", "a{2,1}"] {
+            assert!(build_rule_regex(pattern, true, false, true, false, false).is_err(), "{}", pattern);
+            // 正規表現モードでなければ文字どおりに検索する
+            assert!(build_rule_regex(pattern, false, false, true, false, false).is_ok(), "{}", pattern);
+        }
+        let unicode_off = build_rule_regex("a.b", true, false, false, false, false).unwrap_err();
+        assert!(unicode_off.contains("Unicode を OFF にすると"), "{}", unicode_off);
+
+        let mut data = AppState::new();
+        data.search_pattern = "a".to_string();
+        data.replace_pattern = "b".to_string();
+        assert_eq!(preview_names(&mut data, &["a.txt"]), ["b.txt"]);
+        data.use_regex = true;
+        data.search_pattern = "(".to_string();
+        assert!(compile_rules(&data).is_err());
+        update_preview(&mut data);
+        assert!(data.status_message.starts_with("検索パターンのエラー: "), "{}", data.status_message);
+        assert_eq!(data.preview_files.len(), 1);
+        assert_eq!(data.preview_files[0].new_name, "b.txt");
+        // 追加ルールのエラーは何番目のルールかを示す
+        data.search_pattern = String::new();
+        data.extra_rules.push_back(ReplaceRule { use_regex: true, ..rule("[a-", "") });
+        assert!(compile_rules(&data).err().unwrap().starts_with("追加ルール 1: "));
+        data.test_input = "a.txt".to_string();
+        assert!(apply_to_test_input(&data).contains("追加ルール 1: "));
+    }
+
+    #[test]
+    fn match_counter_counts_per_match_and_sequence_per_file() {
+        let mut data = AppState::new();
+        data.use_regex = true;
+        data.search_pattern = r"(\d+)".to_string();
+        // {m} は 1 つの名前の中で一致ごとに増え、ファイルごとに 1 に戻る
+        data.replace_pattern = "${1}_{m}".to_string();
+        assert_eq!(new_name("a1b22.txt", &data).0, "a1_1b22_2.txt");
+        assert_eq!(preview_names(&mut data, &["a1b22.txt", "c3d4.txt"]), ["a1_1b22_2.txt", "c3_1d4_2.txt"]);
+        // {n} はファイルごとに増え、同じ名前の中の一致はすべて同じ番号
+        data.replace_pattern = "${1}_{n}".to_string();
+        assert_eq!(preview_names(&mut data, &["a1b22.txt", "c3d4.txt"]), ["a1_1b22_1.txt", "c3_2d4_2.txt"]);
+        // 最後の一致だけを置換する場合も、{m} は名前の中で何番目の一致かを表す
+        data.replace_pattern = "${1}_{m}".to_string();
+        data.replace_count = ReplaceCount::Last;
+        assert_eq!(new_name("a1b22.txt", &data).0, "a1b22_2.txt");
+    }
+
+    #[test]
+    fn duplicates_follow_filesystem_case_sensitivity() {
+        // 大小を区別するファイルシステムでは `A.txt` と `a.txt` は別のファイルで、重複にしない
+        assert!(!preview_status_without_digits(&["A1.txt", "a2.txt"], false).contains("重複"));
+        assert!(!preview_status_without_digits(&["\u{C4}1.txt", "\u{E4}2.txt"], false).contains("重複"));
+        // 大小無視のファイルシステムでは ASCII 以外（`Ä` と `ä`）も同じ名前になる
+        assert!(preview_status_without_digits(&["A1.txt", "a2.txt"], true).contains("重複 1 件"));
+        assert!(preview_status_without_digits(&["\u{C4}1.txt", "\u{E4}2.txt"], true).contains("重複 1 件"));
+        // 大小を区別しても、まったく同じ名前は重複
+        assert!(preview_status_without_digits(&["a1.txt", "a2.txt"], false).contains("重複 1 件"));
+    }
+
+    #[test]
+    fn duplicates_ignore_rows_outside_range() {
+        let mut data = AppState::new();
+        data.use_regex = true;
+        data.search_pattern = r"\d".to_string();
+        data.limit_range = true;
+        data.range_start = 1;
+        data.range_end = 1;
+        preview_names(&mut data, &["a1.txt", "a2.txt"]);
+        assert!(!data.status_message.contains("重複"), "{}", data.status_message);
+        data.range_end = 2;
+        preview_names(&mut data, &["a1.txt", "a2.txt"]);
+        assert!(data.status_message.contains("重複 1 件"), "{}", data.status_message);
+    }
+
+    /// `dir` を走査して見つかったファイルの `dir` からの相対パス
+    #[cfg(unix)]
+    fn scanned_paths(dir: &Path, follow_symlinks: bool) -> Vec<String> {
+        let mut data = AppState::new();
+        data.selected_dir = dir.to_string_lossy().to_string();
+        data.include_subdirectories = true;
+        data.follow_symlinks = follow_symlinks;
+        let mut paths = Vec::new();
+        scan_paths(&ScanOptions::from_state(&data), |_, path| {
+            paths.push(Path::new(&path).strip_prefix(dir).unwrap().to_string_lossy().to_string());
+            true
+        });
+        paths
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_followed_only_when_enabled_and_loops_are_skipped() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let root = dir.path();
+        std::fs::create_dir(root.join("sub")).expect("mkdir");
+        std::fs::write(root.join("a.txt"), "a").expect("write");
+        std::fs::write(root.join("sub/b.txt"), "b").expect("write");
+        std::os::unix::fs::symlink(root.join("a.txt"), root.join("link.txt")).expect("symlink");
+        // 祖先のフォルダへ戻るリンク（ループ）
+        std::os::unix::fs::symlink(root, root.join("sub/loop")).expect("symlink");
+
+        assert_eq!(scanned_paths(root, false), ["a.txt", "sub/b.txt"]);
+        assert_eq!(scanned_paths(root, true), ["a.txt", "link.txt", "sub/b.txt"]);
+    }
+
+    #[test]
+    fn extra_rules_apply_in_order() {
+        let mut data = AppState::new();
+        data.search_pattern = "copy".to_string();
+        data.extra_rules.push_back(rule("  ", " "));
+        data.extra_rules.push_back(rule("draft", "final"));
+        assert_eq!(new_name("draft  copy.txt", &data).0, "final .txt");
+        // 後のルールは前のルールの結果に適用する
+        data.extra_rules = vec![rule("a", "b"), rule("b", "c")].into();
+        data.search_pattern = String::new();
+        assert_eq!(new_name("a.txt", &data).0, "c.txt");
+        data.extra_rules = vec![rule("b", "c"), rule("a", "b")].into();
+        assert_eq!(new_name("a.txt", &data).0, "b.txt");
+        // 検索が空のルールは読み飛ばす
+        data.extra_rules.push_back(rule("", "x"));
+        assert_eq!(new_name("a.txt", &data).0, "b.txt");
+    }
+
+    #[test]
+    fn regex_groups_and_extra_rules_apply_in_order() {
+        let mut data = AppState::new();
+        data.use_regex = true;
+        data.search_pattern = r"(\d+)_(\w+)".to_string();
+        data.replace_pattern = "${2}_$1".to_string();
+        data.extra_rules.push_back(rule("_", "-"));
+        assert_eq!(new_name("01_intro.txt", &data).0, "intro-01.txt");
+    }
+
+    #[test]
+    fn prefix_and_suffix_keep_extension() {
+        let mut data = AppState::new();
+        data.prefix = "new_".to_string();
+        data.suffix = "_v2".to_string();
+        assert_eq!(new_name("report.pdf", &data).0, "new_report_v2.pdf");
+        data.compound_extensions = true;
+        assert_eq!(new_name("logs.tar.gz", &data).0, "new_logs_v2.tar.gz");
+    }
+
+    #[test]
+    fn existing_number_token_skips_files_without_number() {
+        let mut data = AppState::new();
+        data.strip_existing_number = true;
+        data.prefix = "{num}_".to_string();
+        assert_eq!(new_name("track 7.mp3", &data).0, "7_track.mp3");
+        assert_eq!(new_name("intro.mp3", &data), ("intro.mp3".to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn copy_suffixes_are_stripped_but_name_parts_are_kept() {
+        let mut data = AppState::new();
+        data.strip_copy_suffixes = true;
+        for (original, expected) in [
+            ("file (1).pdf", "file.pdf"),
+            ("file(2).pdf", "file.pdf"),
+            ("file - Copy (2).pdf", "file.pdf"),
+            ("file - コピー.pdf", "file.pdf"),
+            ("file のコピー 2.pdf", "file.pdf"),
+            ("file (1) (2).pdf", "file.pdf"),
+            ("Report (2019).pdf", "Report (2019).pdf"),
+            ("Track (01).mp3", "Track (01).mp3"),
+            ("Hard copy.txt", "Hard copy.txt"),
+            ("archive (1).tar.gz", "archive (1).tar.gz"),
+        ] {
+            assert_eq!(new_name(original, &data).0, expected, "{}", original);
+        }
+        data.compound_extensions = true;
+        assert_eq!(new_name("archive (1).tar.gz", &data).0, "archive.tar.gz");
+    }
+
+    #[test]
+    fn macos_copy_suffix_needs_original_in_same_folder() {
+        let mut data = AppState::new();
+        data.strip_copy_suffixes = true;
+        let names = preview_names(&mut data, &["Hard copy.txt", "notes.txt", "notes copy.txt", "notes copy 2.txt", "notes copy 02.txt"]);
+        assert_eq!(names, ["notes.txt", "notes.txt"]);
+        let originals: Vec<&str> = data.preview_files.iter().map(|f| f.original_path.as_str()).collect();
+        assert_eq!(originals, ["/filename-change-test/notes copy.txt", "/filename-change-test/notes copy 2.txt"]);
+    }
+
+    #[test]
+    fn parent_token_expands() {
+        let mut data = AppState::new();
+        data.prefix = "{parent}_".to_string();
+        assert_eq!(new_name("a.txt", &data).0, "parent_a.txt");
+    }
+
+    #[test]
+    fn max_length_truncates_before_extension() {
+        let mut data = AppState::new();
+        data.max_name_length = 8;
+        assert_eq!(new_name("abcdefgh.txt", &data).0, "abcd.txt");
+    }
+
+    #[test]
+    fn exclude_items_toggle_and_remove() {
+        let mut data = AppState::new();
+        data.exclude_pattern = "*.{jpg,png}, re:^tmp, node_modules/".to_string();
+        toggle_exclude_item(&mut data, "re:^tmp");
+        assert_eq!(data.exclude_pattern, "*.{jpg,png}, node_modules/");
+        let chips = exclude_chips(&data.exclude_pattern, &data.disabled_excludes);
+        let kinds: Vec<(&str, bool)> = chips.iter().map(|c| (c.kind.as_str(), c.enabled)).collect();
+        assert_eq!(kinds, vec![("グロブ", true), ("パス", true), ("正規表現", false)]);
+
+        toggle_exclude_item(&mut data, "re:^tmp");
+        assert_eq!(data.exclude_pattern, "*.{jpg,png}, node_modules/, re:^tmp");
+        assert!(data.disabled_excludes.is_empty());
+
+        data.exclude_draft = "re:(".to_string();
+        assert!(!add_exclude_items(&mut data));
+        data.exclude_draft = "!keep, .bak".to_string();
+        assert!(add_exclude_items(&mut data));
+        assert_eq!(data.exclude_pattern, "*.{jpg,png}, node_modules/, re:^tmp, !keep, .bak");
+
+        remove_exclude_item(&mut data, "node_modules/");
+        assert_eq!(data.exclude_pattern, "*.{jpg,png}, re:^tmp, !keep, .bak");
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size(""), Ok(None));
+        assert_eq!(parse_size("500"), Ok(Some(500)));
+        assert_eq!(parse_size("1.5 KB"), Ok(Some(1536)));
+        assert_eq!(parse_size("10m"), Ok(Some(10 << 20)));
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("KB").is_err());
+    }
+}
@@ -1,103 +1,1241 @@
-use crate::state::{AppState, FileEntry};
+use crate::state::{AppState, FileEntry, LinkKind, ReadOnlyHandling, RenameFailure, RenameOutcome, RenameResult, ScanOptions};
 use crate::events::{RENAMING_DONE, RENAMING_PROGRESS};
+use crate::paths::{sensitive_dir_reason, subfolder_name_error, to_extended_path};
+use crate::preview::{renumber_preview, scope_name};
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::Local;
 use druid::{EventCtx, Target};
-use tracing::error;
+use rfd::{MessageButtons, MessageDialog, MessageLevel};
+use crate::history;
+use crate::plan::{can_overwrite, collision_key, conflicts_with_existing_path, detect_collisions, Collisions, PlannedMove};
+use tracing::{debug, error, info, warn};
 
-/// リネーム処理
-pub fn apply_changes(ctx: &mut EventCtx, data: &mut AppState) {
-    if data.conversion_in_progress {
-        return;
+/// 適用方法（その場でリネームするか、別フォルダへ新しい名前でコピーするか）
+#[derive(Clone, Debug)]
+pub enum ApplyMode {
+    Rename,
+    /// サブフォルダ内のファイルも各ファイルの対象フォルダ直下へ新しい名前で移動する（フラット化）。
+    /// パス全体を対象にする場合は、新しい名前が対象フォルダからの相対パスになる。
+    Flatten { remove_empty_dirs: bool },
+    /// 対象フォルダからの相対フォルダ構成を保ったまま `target_root` へコピーする（`flatten` なら直下へ）
+    CopyTo { target_root: PathBuf, flatten: bool },
+    /// 履歴の取り消し: 元のパスをキーに、記録されている移動前のパスへ戻す
+    Restore { destinations: HashMap<String, PathBuf> },
+    /// 元のファイルは残し、`base` で決まる変更後のパスに元を指すリンクを作る
+    Link { kind: LinkKind, base: Box<ApplyMode> },
+}
+
+impl ApplyMode {
+    /// 設定から適用方法を決める。コピー先が対象フォルダと同じならリネームとして扱う。
+    /// リンクを作る設定なら、その変更後のパスにリンクを作る方法にする。
+    pub fn from_state(data: &AppState) -> Result<Self, String> {
+        let base = Self::placement_from_state(data)?;
+        Ok(match data.link_kind {
+            LinkKind::None => base,
+            kind => ApplyMode::Link { kind, base: Box::new(base) },
+        })
+    }
+
+    /// 変更後のパスの決め方（リネーム・フラット化・別フォルダへのコピー）
+    fn placement_from_state(data: &AppState) -> Result<Self, String> {
+        // パス全体を対象にする場合、新しい名前は対象フォルダ（コピー先）からの相対パスなのでフラット化と同じく root につなげる
+        let flatten = (data.flatten && data.include_subdirectories) || data.match_full_path;
+        let rename = if flatten {
+            ApplyMode::Flatten { remove_empty_dirs: data.remove_empty_dirs }
+        } else {
+            ApplyMode::Rename
+        };
+        if !data.copy_to_target {
+            return Ok(rename);
+        }
+        if data.target_dir.trim().is_empty() {
+            return Err("コピー先フォルダを指定してください。".to_string());
+        }
+        let target_root = PathBuf::from(data.target_dir.trim());
+        // 追加フォルダがある場合は、コピー先が対象フォルダと同じでも他のフォルダからはコピーになるためリネームにしない
+        let roots = ScanOptions::from_state(data).roots();
+        let same = roots.len() == 1 && {
+            let source_root = PathBuf::from(&roots[0]);
+            match (source_root.canonicalize(), target_root.canonicalize()) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => source_root == target_root,
+            }
+        };
+        if same {
+            return Ok(rename);
+        }
+        Ok(ApplyMode::CopyTo { target_root, flatten })
+    }
+
+    /// 変更後のパス（リネーム先またはコピー先）
+    pub fn destination(&self, f: &FileEntry) -> PathBuf {
+        let original_path = Path::new(&f.original_path);
+        match self {
+            ApplyMode::Rename => original_path.with_file_name(&f.new_name),
+            ApplyMode::Flatten { .. } => Path::new(&f.root).join(&f.new_name),
+            ApplyMode::CopyTo { target_root, flatten: true } => target_root.join(&f.new_name),
+            ApplyMode::CopyTo { target_root, flatten: false } => {
+                let relative_dir = original_path
+                    .parent()
+                    .and_then(|p| p.strip_prefix(&f.root).ok())
+                    .map(|p| p.to_path_buf())
+                    .unwrap_or_default();
+                target_root.join(relative_dir).join(&f.new_name)
+            }
+            ApplyMode::Restore { destinations } => destinations
+                .get(&f.original_path)
+                .cloned()
+                .unwrap_or_else(|| original_path.with_file_name(&f.new_name)),
+            ApplyMode::Link { base, .. } => base.destination(f),
+        }
     }
 
+    /// 名前が変わらなくても場所が変わる（フラット化でサブフォルダから直下へ移る）か
+    fn relocates(&self, f: &FileEntry) -> bool {
+        let parent = Path::new(&f.original_path).parent();
+        match self {
+            ApplyMode::Flatten { .. } | ApplyMode::CopyTo { flatten: true, .. } => parent != Some(Path::new(&f.root)),
+            ApplyMode::Link { base, .. } => base.relocates(f),
+            _ => false,
+        }
+    }
+
+    fn is_copy(&self) -> bool {
+        matches!(self, ApplyMode::CopyTo { .. })
+    }
+
+    /// 元のファイル自体を移動・リネームするか（コピー・リンクの作成では元はそのまま）
+    fn moves_original(&self) -> bool {
+        !matches!(self, ApplyMode::CopyTo { .. } | ApplyMode::Link { .. })
+    }
+
+    /// 履歴ログの op 列と、完了メッセージの動詞
+    fn operation(&self) -> (&'static str, &'static str) {
+        match self {
+            ApplyMode::CopyTo { .. } => ("copy", "コピー"),
+            ApplyMode::Link { kind: LinkKind::Symbolic, .. } => ("symlink", "シンボリックリンク作成"),
+            ApplyMode::Link { .. } => ("hardlink", "ハードリンク作成"),
+            _ => ("rename", "リネーム"),
+        }
+    }
+}
+
+/// 適用時に同時に処理する数の既定値（CPU のコア数、多すぎると遅いドライブで逆に遅くなるため 8 まで）
+pub fn default_rename_threads() -> usize {
+    num_cpus::get().clamp(1, 8)
+}
+
+/// 実際にファイル名が変わるものを抽出し、変更後のパスで衝突がないか検査する。
+/// 衝突があればステータス表示用のメッセージを Err で返す。
+pub fn plan_renames(data: &AppState, mode: &ApplyMode) -> Result<Vec<FileEntry>, String> {
+    let changed_files = select_changes(data, mode)?;
+    let collisions = find_collisions(data, &changed_files, mode);
+    if collisions.is_empty() {
+        Ok(changed_files)
+    } else {
+        Err(collisions.message())
+    }
+}
+
+
+/// 適用の対象（実際にファイル名が変わり、新しい名前が正しいもの）を抽出する。
+fn select_changes(data: &AppState, mode: &ApplyMode) -> Result<Vec<FileEntry>, String> {
     // 実際にファイル名が変わるものだけを抽出
     let changed_files: Vec<FileEntry> = data
         .files
         .iter()
         .cloned()
         .filter(|f| {
+            if !f.will_apply() {
+                // 適用範囲（N〜M 番目）の外、またはチェックを外した行
+                return false;
+            }
             let original_path = Path::new(&f.original_path);
-            if !original_path.exists() {
+            if !to_extended_path(original_path).exists() {
                 // 一度適用済みなどで元パスがすでに存在しないエントリはスキップ
                 return false;
             }
+            if matches!(mode, ApplyMode::Link { .. })
+                && collision_key(&mode.destination(f).to_string_lossy(), data.case_insensitive_fs)
+                    == collision_key(&f.original_path, data.case_insensitive_fs)
+            {
+                // 元のファイルと同じ場所にはリンクを作れない
+                return false;
+            }
+            if data.match_full_path {
+                // 相対パスが変わらなければ移動しない
+                return scope_name(f, data) != f.new_name;
+            }
             let original_name = original_path
                 .file_name()
                 .map(|s| s.to_string_lossy().to_string())
                 .unwrap_or_default();
-            original_name != f.new_name
+            original_name != f.new_name || mode.relocates(f)
         })
         .collect();
 
-    let total_changed = changed_files.len();
-    if total_changed == 0 {
-        data.status_message = "変更対象のファイルはありません。".to_string();
-        return;
+    if changed_files.is_empty() {
+        return Err("変更対象のファイルはありません。".to_string());
     }
 
-    // 衝突検出
-    let mut new_path_to_sources: HashMap<String, Vec<String>> = HashMap::new();
-    let mut existing_conflicts: Vec<String> = Vec::new();
-    for f in &changed_files {
-        let original_path = Path::new(&f.original_path);
-        let new_path_buf = original_path.with_file_name(&f.new_name);
-        let new_path_norm = new_path_buf.to_string_lossy().to_string().to_ascii_lowercase();
-        new_path_to_sources
-            .entry(new_path_norm.clone())
-            .or_default()
-            .push(f.original_path.clone());
-        if new_path_buf.exists() {
-            let orig_norm = original_path
-                .to_string_lossy()
-                .to_string()
-                .to_ascii_lowercase();
-            if new_path_norm != orig_norm {
-                existing_conflicts.push(new_path_buf.to_string_lossy().to_string());
-            }
-        }
-    }
-    let duplicates: Vec<(String, Vec<String>)> = new_path_to_sources
-        .into_iter()
-        .filter_map(|(k, v)| if v.len() > 1 { Some((k, v)) } else { None })
+    // 新しい名前のサブフォルダ指定が対象フォルダの外を指す・区切りを許していない場合は適用しない
+    let allow_subfolders = data.sort_into_subfolders || data.match_full_path;
+    let invalid: Vec<&FileEntry> = changed_files
+        .iter()
+        .filter(|f| subfolder_name_error(&f.new_name, allow_subfolders).is_some())
         .collect();
-    if !duplicates.is_empty() || !existing_conflicts.is_empty() {
-        let dup_count = duplicates.len();
-        let exist_count = existing_conflicts.len();
-        error!(?duplicates, ?existing_conflicts, "collision_detected");
-        data.status_message = format!(
-            "衝突を検出: 新名の重複 {} 件、既存ファイルとの衝突 {} 件",
-            dup_count, exist_count
+    if let Some(first) = invalid.first() {
+        let reason = subfolder_name_error(&first.new_name, allow_subfolders).unwrap_or_default();
+        error!(count = invalid.len(), new_name = %first.new_name, "invalid_subfolder_name");
+        return Err(format!("新しい名前が不正です {} 件（例 {}: {}）", invalid.len(), first.new_name, reason));
+    }
+    Ok(changed_files)
+}
+
+/// 変更後のパスが互いに重なるもの・一覧外の既存ファイルと重なるものを探す（判定は `plan::detect_collisions`）。
+fn find_collisions(data: &AppState, changed_files: &[FileEntry], mode: &ApplyMode) -> Collisions {
+    let moves: Vec<PlannedMove> = changed_files
+        .iter()
+        .map(|f| {
+            let destination = mode.destination(f);
+            PlannedMove {
+                source: &f.original_path,
+                destination_exists: to_extended_path(&destination).exists(),
+                destination: destination.to_string_lossy().to_string(),
+            }
+        })
+        .collect();
+    let collisions = detect_collisions(&moves, matches!(mode, ApplyMode::Link { .. }), data.case_insensitive_fs);
+    if !collisions.is_empty() {
+        error!(duplicates = ?collisions.duplicates, existing_conflicts = ?collisions.existing, "collision_detected");
+    }
+    collisions
+}
+
+/// 変更後のパスに（自分自身ではない）ファイルが既にあるか。
+/// 一覧に含まれず名前も変わらないファイルや、除外したファイルとの衝突もここで見つかる。
+pub fn conflicts_with_existing(f: &FileEntry, mode: &ApplyMode, case_insensitive_fs: bool) -> bool {
+    let new_path = mode.destination(f);
+    conflicts_with_existing_path(
+        &f.original_path,
+        &new_path.to_string_lossy(),
+        to_extended_path(&new_path).exists(),
+        matches!(mode, ApplyMode::Link { .. }),
+        case_insensitive_fs,
+    )
+}
+
+/// リネーム（コピーモードではコピー）を並列に実行し、1 件終わるごとに完了件数を `on_progress` へ通知する。
+/// 完了メッセージ（成功件数・エラー件数・合計サイズ・所要時間・平均速度）と、ファイルごとの結果・失敗したファイルの一覧を返す。
+/// `threads` はリネームを同時に処理する数（1 なら順に処理する）。
+/// `read_only` は読み取り専用のファイルを移動・リネームするときの扱い（コピー・リンクの作成では使わない）。
+/// `backup` なら元のファイルを動かす前にバックアップのフォルダへコピーし、コピーできなかったファイルは変更しない。
+pub fn execute_renames(
+    changed_files: &[FileEntry],
+    mode: &ApplyMode,
+    threads: usize,
+    read_only: ReadOnlyHandling,
+    backup: bool,
+    on_progress: impl Fn(usize) + Sync,
+) -> RenameOutcome {
+    let counter = AtomicUsize::new(0);
+    let started = Instant::now();
+    // コピー・リンクの作成では元のファイルに触れないため、バックアップしない
+    let backup = backup && mode.moves_original();
+    let backup_stamp = Local::now().format("%Y%m%d_%H%M%S_%3f").to_string();
+    let backup_bytes = AtomicU64::new(0);
+    let backup_dirs: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    // 成功したものはファイルサイズ（バイト）と別ドライブ間の移動だったかを返し、完了メッセージに出す
+    let rename_one = |file: &FileEntry| {
+        let original_path = to_extended_path(Path::new(&file.original_path));
+        let new_path = mode.destination(file);
+        let size = std::fs::metadata(&original_path).map(|m| m.len()).unwrap_or(0);
+        let locked = mode.moves_original() && is_read_only(&original_path);
+        // Unix では前もって判定しないため、権限のエラーで失敗したものを読み取り専用として報告する
+        let denied = Cell::new(false);
+        // 長いパス・UNC パスでも失敗しないよう拡張長プレフィックスを付けて渡す
+        let move_file = || {
+            if locked {
+                transfer_read_only(&original_path, &to_extended_path(&new_path), mode, read_only)
+            } else {
+                transfer(&original_path, &to_extended_path(&new_path), mode).map_err(|e| {
+                    denied.set(!cfg!(windows) && mode.moves_original() && e.kind() == std::io::ErrorKind::PermissionDenied);
+                    describe_rename_error(&e)
+                })
+            }
+        };
+        let backed_up = if backup {
+            let (backup_dir, backup_path) = backup_location(file, &backup_stamp);
+            backup_dirs.lock().unwrap_or_else(|e| e.into_inner()).insert(backup_dir.to_string_lossy().to_string());
+            back_up_file(&original_path, &backup_path).map(|bytes| {
+                backup_bytes.fetch_add(bytes, Ordering::Relaxed);
+            })
+        } else {
+            Ok(())
+        };
+        let result = backed_up.and_then(|()| {
+            if file.overwrite_existing {
+                replace_existing(&to_extended_path(&new_path), move_file)
+            } else {
+                move_file()
+            }
+        });
+        let result = result.map(|crossed| (size, crossed)).map_err(|reason| {
+            error!(path = %file.original_path, err = %reason, "rename_failed");
+            RenameFailure {
+                original_path: file.original_path.clone(),
+                new_name: file.new_name.clone(),
+                reason,
+                read_only: locked || denied.get(),
+            }
+        });
+        let done_count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        on_progress(done_count);
+        result
+    };
+    // ネットワークドライブや HDD では同時に多く処理すると遅くなるため、専用のスレッドプールで数を抑える
+    let results: Vec<Result<(u64, bool), RenameFailure>> = if threads <= 1 {
+        changed_files.iter().map(rename_one).collect()
+    } else {
+        match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => pool.install(|| changed_files.par_iter().map(rename_one).collect()),
+            Err(e) => {
+                error!(threads, err = %e, "rename_pool_build_failed");
+                changed_files.par_iter().map(rename_one).collect()
+            }
+        }
+    };
+
+    let elapsed = started.elapsed();
+    let mut total_bytes = 0u64;
+    let mut cross_device_count = 0usize;
+    let mut failures: Vec<RenameFailure> = Vec::new();
+    let mut file_results: Vec<RenameResult> = Vec::with_capacity(changed_files.len());
+    for (file, result) in changed_files.iter().zip(results) {
+        let error = match result {
+            Ok((size, crossed)) => {
+                total_bytes += size;
+                cross_device_count += crossed as usize;
+                String::new()
+            }
+            Err(failure) => {
+                let reason = failure.reason.clone();
+                failures.push(failure);
+                reason
+            }
+        };
+        file_results.push(RenameResult {
+            original_path: file.original_path.clone(),
+            folder: Path::new(&file.original_path)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            new_path: mode.destination(file).to_string_lossy().to_string(),
+            error,
+        });
+    }
+    let success_count = changed_files.len() - failures.len();
+    if let ApplyMode::Flatten { remove_empty_dirs: true } = mode {
+        remove_emptied_dirs(changed_files, &failures);
+    }
+    // 監査用に適用結果を履歴ログへ追記する
+    let (op, verb) = mode.operation();
+    if let Err(e) = history::append_batch(op, changed_files, |f| mode.destination(f), &failures) {
+        error!(err = %e, "rename_log_write_failed");
+    }
+
+    let secs = elapsed.as_secs_f64();
+    let mut msg = format!(
+        "{} {} 件、エラー {} 件（合計 {}、所要 {:.1} 秒",
+        verb,
+        success_count,
+        failures.len(),
+        format_bytes(total_bytes),
+        secs
+    );
+    if secs > 0.0 && total_bytes > 0 {
+        msg.push_str(&format!("、{}/秒", format_bytes((total_bytes as f64 / secs) as u64)));
+    }
+    if cross_device_count > 0 {
+        msg.push_str(&format!("、うち別ドライブへコピー+削除 {} 件", cross_device_count));
+    }
+    let read_only_failures = failures.iter().filter(|f| f.read_only).count();
+    if read_only_failures > 0 {
+        msg.push_str(&format!("、エラーのうち読み取り専用 {} 件", read_only_failures));
+    }
+    let overwritten = changed_files
+        .iter()
+        .zip(&file_results)
+        .filter(|(f, r)| f.overwrite_existing && r.error.is_empty())
+        .count();
+    if overwritten > 0 {
+        msg.push_str(&format!("、うち既存のファイルを上書き {} 件", overwritten));
+    }
+    let backup_dirs = backup_dirs.into_inner().unwrap_or_else(|e| e.into_inner());
+    if let Some(first) = backup_dirs.iter().next() {
+        msg.push_str(&format!("、バックアップ {}（{}", format_bytes(backup_bytes.into_inner()), first));
+        if backup_dirs.len() > 1 {
+            msg.push_str(&format!(" ほか {} か所", backup_dirs.len() - 1));
+        }
+        msg.push('）');
+    }
+    msg.push('）');
+    RenameOutcome { message: msg, results: file_results, failures }
+}
+
+/// バックアップのフォルダの名前（各対象フォルダの直下に作る。読み込みでは対象にしない）
+pub const BACKUP_DIR_NAME: &str = ".filename-change-backup";
+
+/// バックアップのフォルダ（`<対象フォルダ>/.filename-change-backup/<日時>`）と、そこでのファイルのパス（対象フォルダからの相対パスを保つ）。
+/// 対象フォルダが分からない場合（履歴の取り消し）は、ファイルのあるフォルダに作る。
+fn backup_location(file: &FileEntry, stamp: &str) -> (PathBuf, PathBuf) {
+    let original = Path::new(&file.original_path);
+    let root = if file.root.is_empty() { original.parent().unwrap_or(Path::new("")) } else { Path::new(&file.root) };
+    let relative = original
+        .strip_prefix(root)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| PathBuf::from(original.file_name().unwrap_or_default()));
+    let dir = root.join(BACKUP_DIR_NAME).join(stamp);
+    let path = dir.join(relative);
+    (dir, path)
+}
+
+/// 元のファイルをバックアップのパスへコピーし（フォルダがなければ作成）、コピーしたバイト数を返す。
+fn back_up_file(from: &Path, to: &Path) -> Result<u64, String> {
+    let to = to_extended_path(to);
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("バックアップできないため変更しませんでした: {}", describe_rename_error(&e)))?;
+    }
+    std::fs::copy(from, &to).map_err(|e| {
+        error!(path = %from.display(), backup = %to.display(), err = %e, "backup_failed");
+        format!("バックアップできないため変更しませんでした: {}", describe_rename_error(&e))
+    })
+}
+
+/// バイト数を KB/MB/GB 単位（1024 基準）の表示にする。
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// 1 件をリネーム、またはコピー先フォルダ（なければ作成）へコピーする。
+/// 移動先のフォルダ（サブフォルダへの振り分け先、取り消しではフラット化で削除したフォルダ）がなければ作成する。
+/// 移動先が別のドライブ（マウントポイント）で rename できなかった場合はコピーして元を削除し、true を返す。
+fn transfer(from: &Path, to: &Path, mode: &ApplyMode) -> std::io::Result<bool> {
+    if let Some(parent) = to.parent() {
+        if !parent.exists() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    if mode.is_copy() {
+        return std::fs::copy(from, to).map(|_| false);
+    }
+    if let ApplyMode::Link { kind, .. } = mode {
+        return create_link(from, to, *kind).map(|_| false);
+    }
+    if is_case_only_change(from, to) {
+        return rename_via_temp(from, to).map(|_| false);
+    }
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(false),
+        // rename はリンク自体を移す。コピー+削除ではリンク先の中身の複製に置き換わってしまうため、リンクは別ドライブへ移さない
+        Err(e) if is_cross_device(&e) && is_symlink(from) => {
+            Err(std::io::Error::other("シンボリックリンクは別のドライブへ移動できません（リンク先の中身がコピーされるため）"))
+        }
+        Err(e) if is_cross_device(&e) => {
+            debug!(from = %from.display(), to = %to.display(), "cross_device_move");
+            move_across_devices(from, to)
+                .map(|_| true)
+                .map_err(|e| std::io::Error::other(format!("別のドライブへの移動（コピー+削除）に失敗しました: {}", describe_rename_error(&e))))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// `to` に `from` を指すリンクを作る（元のファイルはそのまま）。
+/// シンボリックリンクは元の絶対パスを指すので、リンクをほかの場所へ移しても元を指し続ける。
+fn create_link(from: &Path, to: &Path, kind: LinkKind) -> std::io::Result<()> {
+    let result = match kind {
+        LinkKind::Symbolic => {
+            // 相対パスのままだとリンクの場所からの相対になるため、絶対パスを指させる
+            let from = std::path::absolute(from)?;
+            let from = from.as_path();
+            #[cfg(windows)]
+            {
+                std::os::windows::fs::symlink_file(from, to)
+            }
+            #[cfg(not(windows))]
+            {
+                std::os::unix::fs::symlink(from, to)
+            }
+        }
+        _ => std::fs::hard_link(from, to),
+    };
+    result.map_err(|e| match describe_link_error(&e, kind) {
+        // 種類で一般的な文言に置き換えられないよう、理由そのものを伝える
+        Some(reason) => std::io::Error::other(reason),
+        None => e,
+    })
+}
+
+/// リンクを作れない理由のうち、ファイルシステムや権限によるものを利用者向けの文言にする。
+fn describe_link_error(e: &std::io::Error, kind: LinkKind) -> Option<String> {
+    if kind == LinkKind::Hard && is_cross_device(e) {
+        return Some("ハードリンクは元のファイルと同じドライブにしか作れません（シンボリックリンクかコピーを使ってください）".to_string());
+    }
+    // Windows の ERROR_PRIVILEGE_NOT_HELD(1314)
+    if cfg!(windows) && kind == LinkKind::Symbolic && e.raw_os_error() == Some(1314) {
+        return Some("シンボリックリンクの作成には管理者権限か開発者モードが必要です".to_string());
+    }
+    // Unix の EPERM(1) はファイルシステムがハードリンクに対応していない場合（FAT など）にも返る。
+    // Windows の ERROR_INVALID_FUNCTION(1) / ERROR_NOT_SUPPORTED(50)
+    let unsupported = e.kind() == std::io::ErrorKind::Unsupported
+        || if cfg!(windows) { matches!(e.raw_os_error(), Some(1) | Some(50)) } else { e.raw_os_error() == Some(1) };
+    if unsupported {
+        let name = if kind == LinkKind::Hard { "ハードリンク" } else { "シンボリックリンク" };
+        return Some(format!("このドライブ（ファイルシステム）は{}に対応していません", name));
+    }
+    None
+}
+
+/// rename が別のドライブ（ファイルシステム）をまたぐために失敗したか。
+/// Unix の EXDEV(18)、Windows の ERROR_NOT_SAME_DEVICE(17)。
+fn is_cross_device(e: &std::io::Error) -> bool {
+    let code = if cfg!(windows) { 17 } else { 18 };
+    e.raw_os_error() == Some(code)
+}
+
+/// シンボリックリンクか（リンク先ではなくリンク自体を調べる）。
+fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).map(|m| m.file_type().is_symlink()).unwrap_or(false)
+}
+
+/// 読み取り専用のファイルを扱いに従って移動する。失敗した場合は理由を返す。
+/// 解除して移動する場合、移動できなければ元のファイルを読み取り専用に戻す。
+fn transfer_read_only(from: &Path, to: &Path, mode: &ApplyMode, handling: ReadOnlyHandling) -> Result<bool, String> {
+    match handling {
+        ReadOnlyHandling::Attempt => {
+            transfer(from, to, mode).map_err(|e| format!("{}（読み取り専用）", describe_rename_error(&e)))
+        }
+        ReadOnlyHandling::Skip => Err("読み取り専用のため変更しませんでした".to_string()),
+        ReadOnlyHandling::Unlock | ReadOnlyHandling::UnlockAndRestore => {
+            set_read_only(from, false).map_err(|e| format!("読み取り専用を解除できません: {}", describe_rename_error(&e)))?;
+            let crossed = match transfer(from, to, mode) {
+                Ok(crossed) => crossed,
+                Err(e) => {
+                    let _ = set_read_only(from, true);
+                    return Err(describe_rename_error(&e));
+                }
+            };
+            // 戻せなくても移動はできているため、失敗にはせずログだけ残す
+            if handling == ReadOnlyHandling::UnlockAndRestore {
+                if let Err(e) = set_read_only(to, true) {
+                    warn!(path = %to.display(), err = %e, "read_only_restore_failed");
+                }
+            }
+            Ok(crossed)
+        }
+    }
+}
+
+/// 読み取り専用のファイルか（Windows の読み取り専用属性）。
+/// Unix ではリネームできるかはファイルの権限ではなくフォルダの書き込み権限で決まるため、常に false。
+/// 変更が権限のエラーで失敗したときに、読み取り専用として報告する。
+pub fn is_read_only(path: &Path) -> bool {
+    cfg!(windows) && std::fs::symlink_metadata(path).map(|m| m.permissions().readonly()).unwrap_or(false)
+}
+
+/// 読み取り専用属性を付ける・外す（読み取り専用と判定するのは Windows だけなので、Windows でだけ使う）。
+fn set_read_only(path: &Path, read_only: bool) -> std::io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    #[allow(clippy::permissions_set_readonly_false)] // Windows では読み取り専用属性を外すだけ
+    permissions.set_readonly(read_only);
+    std::fs::set_permissions(path, permissions)
+}
+
+/// 別のドライブへコピー（更新日時も引き継ぐ）してから元を削除する。
+/// 元を削除できなければコピーを消して、元の状態に戻す。
+fn move_across_devices(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::copy(from, to)?;
+    if let Ok(modified) = std::fs::metadata(from).and_then(|m| m.modified()) {
+        if let Ok(file) = std::fs::File::options().write(true).open(to) {
+            let _ = file.set_modified(modified);
+        }
+    }
+    if let Err(e) = std::fs::remove_file(from) {
+        let _ = std::fs::remove_file(to);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// 同じフォルダ内で大文字小文字だけが異なる名前への変更か（例 `File.txt` → `file.txt`）。
+fn is_case_only_change(from: &Path, to: &Path) -> bool {
+    if from == to || from.parent() != to.parent() {
+        return false;
+    }
+    match (from.file_name(), to.file_name()) {
+        (Some(a), Some(b)) => a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase(),
+        _ => false,
+    }
+}
+
+/// 同じフォルダ内の一時的な名前（例 `a.txt.renaming-1234-0`）。
+fn temp_sibling(path: &Path, tag: &str) -> PathBuf {
+    static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let file_name = path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(
+        "{}.{}-{}-{}",
+        file_name,
+        tag,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// 大小無視のファイルシステムでは大文字小文字だけの変更が無視されたり失敗したりするため、
+/// いったん一時的な名前に変えてから目的の名前にする。2 段目が失敗したら元の名前に戻す。
+fn rename_via_temp(from: &Path, to: &Path) -> std::io::Result<()> {
+    let temp = temp_sibling(from, "renaming");
+    std::fs::rename(from, &temp)?;
+    if let Err(e) = std::fs::rename(&temp, to) {
+        let _ = std::fs::rename(&temp, from);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// 既存の `to` を上書きして移す。既存のファイルはいったん一時的な名前へ退避し、
+/// 移せたら削除する。移せなければ退避したファイルを元の名前に戻す。
+fn replace_existing(to: &Path, move_file: impl FnOnce() -> Result<bool, String>) -> Result<bool, String> {
+    let aside = temp_sibling(to, "replacing");
+    std::fs::rename(to, &aside)
+        .map_err(|e| format!("既存のファイルを置き換えられません: {}", describe_rename_error(&e)))?;
+    match move_file() {
+        Ok(crossed) => {
+            // 移動はできているため、消せなくても失敗にはせずログだけ残す
+            if let Err(e) = std::fs::remove_file(&aside) {
+                warn!(path = %aside.display(), err = %e, "replaced_file_remove_failed");
+            }
+            Ok(crossed)
+        }
+        Err(reason) => {
+            if let Err(e) = std::fs::rename(&aside, to) {
+                error!(path = %aside.display(), err = %e, "replaced_file_restore_failed");
+            }
+            Err(reason)
+        }
+    }
+}
+
+/// フラット化で移動したファイルの元フォルダのうち、空になったものを各ファイルの対象フォルダの手前まで遡って削除する。
+/// 空でないフォルダは `remove_dir` が失敗するのでそのまま残る。
+fn remove_emptied_dirs(moved: &[FileEntry], failures: &[RenameFailure]) {
+    let failed: HashSet<&str> = failures.iter().map(|f| f.original_path.as_str()).collect();
+    let mut dirs: Vec<(PathBuf, &Path)> = moved
+        .iter()
+        .filter(|f| !failed.contains(f.original_path.as_str()))
+        .filter_map(|f| Path::new(&f.original_path).parent().map(|p| (p.to_path_buf(), Path::new(&f.root))))
+        .collect();
+    // 深いフォルダから削除する
+    dirs.sort_by_key(|(d, _)| std::cmp::Reverse(d.components().count()));
+    dirs.dedup();
+    for (dir, root) in dirs {
+        let mut current = dir.as_path();
+        while current != root && current.starts_with(root) {
+            if std::fs::remove_dir(to_extended_path(current)).is_err() {
+                break;
+            }
+            debug!(dir = %current.display(), "removed_empty_dir");
+            match current.parent() {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+    }
+}
+
+/// リネーム失敗の理由を利用者向けの文言にする。
+fn describe_rename_error(e: &std::io::Error) -> String {
+    // Windows の ERROR_SHARING_VIOLATION(32) / ERROR_LOCK_VIOLATION(33)
+    if cfg!(windows) && matches!(e.raw_os_error(), Some(32) | Some(33)) {
+        return "他のプロセスが使用中です".to_string();
+    }
+    match e.kind() {
+        std::io::ErrorKind::PermissionDenied => "アクセスが拒否されました（使用中または権限なし）".to_string(),
+        std::io::ErrorKind::NotFound => "元のファイルが見つかりません".to_string(),
+        std::io::ErrorKind::AlreadyExists => "同名のファイルが既に存在します".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+/// リネーム処理
+pub fn apply_changes(ctx: &mut EventCtx, data: &mut AppState) {
+    if data.conversion_in_progress {
+        return;
+    }
+    if data.search_only {
+        data.set_status("検索のみモードでは適用できません。名前を変えるには「名前の変更」に切り替えてください。");
+        return;
+    }
+    if data.scan_in_progress {
+        data.set_status("ファイル一覧の読み込み中です。完了後に適用してください。");
+        return;
+    }
+
+    let mode = match ApplyMode::from_state(data) {
+        Ok(mode) => mode,
+        Err(msg) => {
+            data.set_status(msg);
+            return;
+        }
+    };
+    let changed_files = match plan_renames(data, &mode) {
+        Ok(files) => files,
+        Err(msg) => match resolve_collisions(data, &mode, msg) {
+            Ok(files) => files,
+            Err(msg) => {
+                data.set_status(msg);
+                return;
+            }
+        },
+    };
+
+    // 危険なフォルダや大量の変更は、確認ダイアログを切っていても必ず確認する
+    if !confirm_risky_apply(data, changed_files.len()) {
+        data.set_status("適用をキャンセルしました。");
+        return;
+    }
+    if data.confirm_before_apply && !confirm_apply(data, &changed_files, &mode) {
+        data.set_status("適用をキャンセルしました。");
+        return;
+    }
+
+    start_worker(ctx, data, changed_files, mode);
+}
+
+/// プレビューの 1 行だけをすぐに適用する（その行のチェック・適用範囲に関係なく、1 件だけのバッチとして）。
+/// 衝突の判定は一括の適用と同じで、既存のファイルや他の行の今の名前と重なる場合は適用しない。
+pub fn apply_row(ctx: &mut EventCtx, data: &mut AppState, path: &str) {
+    if data.conversion_in_progress || data.search_only {
+        return;
+    }
+    if data.scan_in_progress {
+        data.set_status("ファイル一覧の読み込み中です。完了後に適用してください。");
+        return;
+    }
+    let mode = match ApplyMode::from_state(data) {
+        Ok(mode) => mode,
+        Err(msg) => {
+            data.set_status(msg);
+            return;
+        }
+    };
+    let mut single = data.clone();
+    for f in single.files.iter_mut() {
+        let target = f.original_path == path;
+        f.selected = target;
+        f.in_range = target;
+    }
+    let changed_files = match plan_renames(&single, &mode) {
+        Ok(files) => files,
+        Err(msg) => {
+            data.set_status(format!("この行は適用できません: {}", msg));
+            return;
+        }
+    };
+    data.single_row_apply = true;
+    start_worker(ctx, data, changed_files, mode);
+}
+
+/// 衝突で適用できないとき、衝突したファイルだけに連番を付けるか、既存のファイルとの衝突を 1 件ずつ確認するか、
+/// 衝突したファイルを除いて適用するかを尋ねる。
+/// 適用するファイルを返す。中止した場合や、衝突以外の理由で適用できない場合はステータス表示用のメッセージを返す。
+fn resolve_collisions(data: &mut AppState, mode: &ApplyMode, msg: String) -> Result<Vec<FileEntry>, String> {
+    let mut changed_files = select_changes(data, mode)?;
+    let collisions = find_collisions(data, &changed_files, mode);
+    if collisions.is_empty() {
+        return Err(msg);
+    }
+    let description = format!(
+        "{}。\n\n衝突したファイルだけに連番（{}）を付けて適用しますか？",
+        collisions.message(),
+        data.auto_number_template
+    );
+    let number = MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("衝突があります")
+        .set_description(&description)
+        .set_buttons(MessageButtons::OkCancelCustom("連番を付けて適用".to_string(), "ほかの方法".to_string()))
+        .show();
+    if number {
+        // 「重複時に連番を付与」と同じ処理で、プレビューの並び順・チェックを保ったまま付け直す
+        let auto_number = data.auto_number_on_conflict;
+        data.auto_number_on_conflict = true;
+        renumber_preview(data);
+        data.auto_number_on_conflict = auto_number;
+        // 一覧外の既存ファイルとの衝突は連番では解消しない
+        return plan_renames(data, mode).map_err(|e| format!("連番を付けても解消しない衝突があります（{}）", e));
+    }
+    if !collisions.existing.is_empty() && collisions.existing.len() <= INTERACTIVE_CONFLICT_LIMIT {
+        let description = format!(
+            "既存のファイルと衝突する {} 件を 1 件ずつ確認し、スキップ・上書き・別の名前を選びますか？",
+            collisions.existing.len()
         );
+        let each = MessageDialog::new()
+            .set_level(MessageLevel::Warning)
+            .set_title("衝突があります")
+            .set_description(&description)
+            .set_buttons(MessageButtons::OkCancelCustom("1 件ずつ確認".to_string(), "ほかの方法".to_string()))
+            .show();
+        if each {
+            let decisions = ask_each_conflict(data, &mut changed_files, mode);
+            info!(
+                skipped = decisions.skipped,
+                overwritten = decisions.overwritten,
+                renamed = decisions.renamed,
+                "conflicts_resolved"
+            );
+            let summary = decisions.message();
+            if changed_files.is_empty() {
+                return Err(format!("{}。適用するファイルはありません。", summary));
+            }
+            // 新しい名前が互いに重なるものは 1 件ずつの確認では扱わないため、残っていれば除くかを尋ねる
+            let remaining = find_collisions(data, &changed_files, mode);
+            if remaining.is_empty() {
+                data.set_status(summary);
+                return Ok(changed_files);
+            }
+            return skip_collisions(data, changed_files, &remaining, msg);
+        }
+    }
+    skip_collisions(data, changed_files, &collisions, msg)
+}
+
+/// 衝突したファイルを除いて適用するかを尋ね、適用するファイルを返す。
+fn skip_collisions(
+    data: &mut AppState,
+    mut changed_files: Vec<FileEntry>,
+    collisions: &Collisions,
+    msg: String,
+) -> Result<Vec<FileEntry>, String> {
+    let skipped = collisions.sources.len();
+    changed_files.retain(|f| !collisions.sources.contains(&f.original_path));
+    if changed_files.is_empty() {
+        return Err(msg);
+    }
+    let description = format!(
+        "衝突したファイル {} 件を除き、残りの {} 件を適用しますか？",
+        skipped,
+        changed_files.len()
+    );
+    let skip = MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("衝突があります")
+        .set_description(&description)
+        .set_buttons(MessageButtons::OkCancelCustom("除いて適用".to_string(), "中止".to_string()))
+        .show();
+    if !skip {
+        return Err(msg);
+    }
+    data.set_status(format!("衝突したファイル {} 件を除いて適用します", skipped));
+    Ok(changed_files)
+}
+
+/// 既存のファイルとの衝突を 1 件ずつ確認するのは、この件数以下のときだけ（多いとダイアログが続きすぎるため）
+const INTERACTIVE_CONFLICT_LIMIT: usize = 20;
+
+/// 衝突ごとに選んだ扱いの件数
+#[derive(Default)]
+struct ConflictDecisions {
+    skipped: usize,
+    overwritten: usize,
+    renamed: usize,
+}
+
+impl ConflictDecisions {
+    fn message(&self) -> String {
+        format!(
+            "衝突の扱い: 上書き {} 件、別の名前 {} 件、スキップ {} 件",
+            self.overwritten, self.renamed, self.skipped
+        )
+    }
+}
+
+/// 既存のファイルと衝突するファイルごとに、上書き・別の名前・スキップを尋ねて `changed_files` に反映する。
+/// スキップしたものは除き、上書きするものには印を付け、別の名前にしたものは新しい名前に変える。
+fn ask_each_conflict(data: &AppState, changed_files: &mut Vec<FileEntry>, mode: &ApplyMode) -> ConflictDecisions {
+    let mut decisions = ConflictDecisions::default();
+    let conflicting: Vec<String> = changed_files
+        .iter()
+        .filter(|f| conflicts_with_existing(f, mode, data.case_insensitive_fs))
+        .map(|f| f.original_path.clone())
+        .collect();
+    let total = conflicting.len();
+    let mut skip: HashSet<String> = HashSet::new();
+    for (i, path) in conflicting.iter().enumerate() {
+        let Some(index) = changed_files.iter().position(|f| &f.original_path == path) else {
+            continue;
+        };
+        loop {
+            let file = &changed_files[index];
+            let destination = mode.destination(file);
+            let description = format!(
+                "({}/{}) 移動先に同じ名前のファイルが既にあります。\n\n元: {}\n移動先: {}",
+                i + 1,
+                total,
+                file.original_path,
+                destination.display()
+            );
+            // 移動先が今回変更するほかのファイルなら、上書きは選ばせず別の名前かスキップだけを尋ねる
+            let sources = changed_files.iter().map(|f| f.original_path.as_str());
+            let rename_description = if can_overwrite(&destination.to_string_lossy(), sources, data.case_insensitive_fs) {
+                let overwrite = MessageDialog::new()
+                    .set_level(MessageLevel::Warning)
+                    .set_title("既存のファイルとの衝突")
+                    .set_description(&format!("{}\n\n既存のファイルを上書きしますか？", description))
+                    .set_buttons(MessageButtons::OkCancelCustom("上書き".to_string(), "上書きしない".to_string()))
+                    .show();
+                if overwrite && confirm_overwrite(&destination) {
+                    changed_files[index].overwrite_existing = true;
+                    decisions.overwritten += 1;
+                    break;
+                }
+                format!("{}\n\n別の名前で適用しますか？", description)
+            } else {
+                format!(
+                    "{}\n（移動先のファイルも今回の変更の対象のため、上書きできません）\n\n別の名前で適用しますか？",
+                    description
+                )
+            };
+            let rename = MessageDialog::new()
+                .set_level(MessageLevel::Warning)
+                .set_title("既存のファイルとの衝突")
+                .set_description(&rename_description)
+                .set_buttons(MessageButtons::OkCancelCustom("別の名前にする".to_string(), "スキップ".to_string()))
+                .show();
+            let chosen = if rename { pick_new_name(&destination) } else { None };
+            let Some(name) = chosen else {
+                skip.insert(path.clone());
+                decisions.skipped += 1;
+                break;
+            };
+            let new_name = replace_file_name(&file.new_name, &name);
+            let previous = std::mem::replace(&mut changed_files[index].new_name, new_name);
+            // 選んだ名前も既存のファイル・ほかのファイルの新しい名前と重なる場合は、もう一度尋ねる
+            let renamed = &changed_files[index];
+            let new_key = collision_key(&mode.destination(renamed).to_string_lossy(), data.case_insensitive_fs);
+            let taken = conflicts_with_existing(renamed, mode, data.case_insensitive_fs)
+                || changed_files.iter().enumerate().any(|(j, f)| {
+                    j != index && collision_key(&mode.destination(f).to_string_lossy(), data.case_insensitive_fs) == new_key
+                });
+            if !taken {
+                decisions.renamed += 1;
+                break;
+            }
+            MessageDialog::new()
+                .set_level(MessageLevel::Warning)
+                .set_title("既存のファイルとの衝突")
+                .set_description(&format!("「{}」も既に使われています。別の扱いを選んでください。", name))
+                .set_buttons(MessageButtons::Ok)
+                .show();
+            changed_files[index].new_name = previous;
+        }
+    }
+    changed_files.retain(|f| !skip.contains(&f.original_path));
+    decisions
+}
+
+/// 上書きすると既存のファイルが失われることを確認し、「はい」が押された場合のみ true を返す。
+fn confirm_overwrite(destination: &Path) -> bool {
+    let description = format!(
+        "{}\n\nこのファイルは置き換えられ、元に戻せません（適用履歴の取り消しでも戻りません）。\n本当に上書きしますか？",
+        destination.display()
+    );
+    MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("上書きの確認")
+        .set_description(&description)
+        .set_buttons(MessageButtons::YesNo)
+        .show()
+}
+
+/// 保存ダイアログで新しいファイル名を選ばせる。フォルダは変えられないため、選んだ名前だけを返す。
+fn pick_new_name(destination: &Path) -> Option<String> {
+    let mut dialog = rfd::FileDialog::new().set_title("新しい名前を指定（フォルダは変わりません）");
+    if let Some(dir) = destination.parent() {
+        dialog = dialog.set_directory(dir);
+    }
+    if let Some(name) = destination.file_name() {
+        dialog = dialog.set_file_name(&name.to_string_lossy());
+    }
+    let picked = dialog.save_file()?;
+    picked
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .filter(|s| !s.trim().is_empty())
+}
+
+/// 新しい名前の最後の要素（サブフォルダへの振り分け先を除いたファイル名）を `name` に置き換える。
+fn replace_file_name(new_name: &str, name: &str) -> String {
+    match new_name.rfind(std::path::is_separator) {
+        Some(pos) => format!("{}{}", &new_name[..=pos], name),
+        None => name.to_string(),
+    }
+}
+
+/// 適用履歴の指定バッチを取り消す（新しい名前から元の名前へ戻す）。
+pub fn undo_batch(ctx: &mut EventCtx, data: &mut AppState, batch_id: &str) {
+    if data.conversion_in_progress {
+        return;
+    }
+    let history::UndoPlan { entries, destinations, skipped } = match history::plan_undo(batch_id) {
+        Ok(plan) => plan,
+        Err(msg) => {
+            data.set_status(msg);
+            return;
+        }
+    };
+    let mut description = format!("{} 件を元の名前に戻します。", entries.len());
+    if skipped > 0 {
+        description.push_str(&format!("\n（ファイルが見つからない、または元の名前が使われているため {} 件はスキップ）", skipped));
+    }
+    let confirmed = MessageDialog::new()
+        .set_level(MessageLevel::Info)
+        .set_title("履歴から元に戻す")
+        .set_description(&description)
+        .set_buttons(MessageButtons::OkCancel)
+        .show();
+    if !confirmed {
         return;
     }
+    start_worker(ctx, data, entries, ApplyMode::Restore { destinations });
+}
+
+/// 進捗を通知する最短の間隔（およそ 1 フレーム）
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(16);
+
+/// 進捗の通知の間引き。1 件ごとにコマンドを送ると、数万件の適用で UI のイベントキューがあふれるため、
+/// 前回の通知から `PROGRESS_INTERVAL` 経ったか、全体の 1% 以上進んだときだけ通知する（最後の 1 件は必ず通知する）。
+struct ProgressThrottle {
+    total: usize,
+    last: Mutex<(Instant, usize)>, // 最後に通知した時刻と完了件数
+}
+
+impl ProgressThrottle {
+    fn new(total: usize) -> Self {
+        Self { total, last: Mutex::new((Instant::now(), 0)) }
+    }
 
-    data.conversion_total = total_changed;
+    /// 完了件数 `done` を通知すべきか。並列に処理すると件数が前後して届くため、前回より少なければ通知しない。
+    fn should_send(&self, done: usize) -> bool {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let (sent_at, sent_done) = *last;
+        if done <= sent_done {
+            return false;
+        }
+        let step = (self.total / 100).max(1);
+        if done < self.total && done - sent_done < step && sent_at.elapsed() < PROGRESS_INTERVAL {
+            return false;
+        }
+        *last = (Instant::now(), done);
+        true
+    }
+}
+
+/// バックグラウンドでリネームを実行し、進捗と完了をコマンドで通知する。
+fn start_worker(ctx: &mut EventCtx, data: &mut AppState, changed_files: Vec<FileEntry>, mode: ApplyMode) {
+    data.conversion_total = changed_files.len();
     data.conversion_done = 0;
+    data.conversion_started = Some(Instant::now());
     data.conversion_in_progress = true;
+    data.rename_failures.clear();
+    data.rename_results.clear();
+    data.rename_folder_breakdown.clear();
 
+    let threads = data.rename_threads;
+    let read_only = data.read_only_handling;
+    let backup = data.backup_before_apply;
     let event_sink = ctx.get_external_handle();
     std::thread::spawn(move || {
-        let counter = AtomicUsize::new(0);
-        let results: Vec<Result<(), std::io::Error>> = changed_files
-            .par_iter()
-            .map(|file| {
-                let original_path = Path::new(&file.original_path);
-                let new_path = original_path.with_file_name(&file.new_name);
-                let result = std::fs::rename(&original_path, &new_path);
-                let done_count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let throttle = ProgressThrottle::new(changed_files.len());
+        let outcome = execute_renames(&changed_files, &mode, threads, read_only, backup, |done_count| {
+            if throttle.should_send(done_count) {
                 let _ = event_sink.submit_command(RENAMING_PROGRESS, done_count, Target::Global);
-                result
-            })
-            .collect();
-
-        let success_count = results.iter().filter(|r| r.is_ok()).count();
-        let error_count = results.len() - success_count;
-        let msg = format!("リネーム {} 件、エラー {} 件", success_count, error_count);
-        let _ = event_sink.submit_command(RENAMING_DONE, msg, Target::Global);
+            }
+        });
+        let _ = event_sink.submit_command(RENAMING_DONE, outcome, Target::Global);
     });
 }
 
+/// 対象フォルダがルート・ホーム・システムフォルダの場合や、変更件数が多い場合に警告する。
+/// 該当しなければ何も表示せず true、該当すれば「はい」が押された場合のみ true を返す。
+fn confirm_risky_apply(data: &AppState, count: usize) -> bool {
+    let mut warnings = Vec::new();
+    for root in ScanOptions::from_state(data).roots() {
+        if let Some(reason) = sensitive_dir_reason(Path::new(&root)) {
+            warnings.push(format!("対象フォルダは{}: {}", reason, root));
+        }
+    }
+    if exceeds_batch_limit(count, data.large_batch_threshold) {
+        warnings.push(format!("{} 件のファイルを一度に変更します。", count));
+    }
+    if warnings.is_empty() {
+        return true;
+    }
+    let description = format!(
+        "{}\n\nシステムや他のアプリのファイルを変更すると、元に戻せなくなることがあります。\n本当に実行しますか？",
+        warnings.join("\n")
+    );
+    MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("注意")
+        .set_description(&description)
+        .set_buttons(MessageButtons::YesNo)
+        .show()
+}
+
+/// 一度に変更する件数が確認の必要な件数に達しているか（上限 0 なら確認しない）。
+pub fn exceeds_batch_limit(count: usize, threshold: usize) -> bool {
+    threshold > 0 && count >= threshold
+}
 
+/// 適用内容の要約を表示し、OK が押された場合のみ true を返す。
+fn confirm_apply(data: &AppState, changed_files: &[FileEntry], mode: &ApplyMode) -> bool {
+    let in_subdirs = changed_files
+        .iter()
+        .filter(|f| Path::new(&f.original_path).parent() != Some(Path::new(&f.root)))
+        .count();
+    let mut description = format!(
+        "{} 件のファイル名を変更します。\n連番付与: {} 件\nフォルダ: {}",
+        changed_files.len(),
+        data.auto_numbered_count,
+        ScanOptions::from_state(data).roots().join("\n　　　　")
+    );
+    if in_subdirs > 0 {
+        description.push_str(&format!("\n（うちサブフォルダ内 {} 件）", in_subdirs));
+    }
+    let link = match mode {
+        ApplyMode::Link { kind, base } => {
+            let name = if *kind == LinkKind::Hard { "ハードリンク" } else { "シンボリックリンク" };
+            description.push_str(&format!("\n元のファイルは残し、新しい名前の{}を作成します。", name));
+            Some(base.as_ref())
+        }
+        _ => None,
+    };
+    if data.match_full_path {
+        description.push_str("\n対象フォルダからの相対パスごと書き換え、必要なフォルダを作成して移動します。");
+    }
+    if data.backup_before_apply && mode.moves_original() {
+        description.push_str(&format!("\n変更前に元のファイルを対象フォルダの {} へコピーします。", BACKUP_DIR_NAME));
+    }
+    match link.unwrap_or(mode) {
+        ApplyMode::CopyTo { target_root, .. } if link.is_some() => {
+            description.push_str(&format!("\nリンクは {} に作成します。", target_root.display()));
+        }
+        ApplyMode::Flatten { .. } if link.is_some() && !data.match_full_path => {
+            description.push_str("\nサブフォルダ内のファイルのリンクも対象フォルダ直下に作成します。");
+        }
+        ApplyMode::CopyTo { target_root, .. } => {
+            description.push_str(&format!("\n元のファイルは残し、新しい名前で {} へコピーします。", target_root.display()));
+        }
+        ApplyMode::Flatten { remove_empty_dirs, .. } => {
+            if !data.match_full_path {
+                description.push_str("\nサブフォルダ内のファイルを対象フォルダ直下へ移動します（フラット化）。");
+            }
+            if *remove_empty_dirs && link.is_none() {
+                description.push_str("\n空になったサブフォルダは削除します。");
+            }
+        }
+        _ => {}
+    }
+    description.push_str("\n\n実行しますか？");
+    MessageDialog::new()
+        .set_level(MessageLevel::Info)
+        .set_title("変更の確認")
+        .set_description(&description)
+        .set_buttons(MessageButtons::OkCancel)
+        .show()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_only_change_requires_same_folder_and_different_case() {
+        assert!(is_case_only_change(Path::new("/d/File.txt"), Path::new("/d/file.txt")));
+        // ASCII 以外の大文字小文字も同じ名前とみなす
+        assert!(is_case_only_change(Path::new("/d/\u{C4}pfel.txt"), Path::new("/d/\u{E4}pfel.txt")));
+        assert!(is_case_only_change(Path::new("/d/\u{416}.txt"), Path::new("/d/\u{436}.txt")));
+        // 同じパス・別のフォルダ・別の名前は対象外
+        assert!(!is_case_only_change(Path::new("/d/file.txt"), Path::new("/d/file.txt")));
+        assert!(!is_case_only_change(Path::new("/a/File.txt"), Path::new("/b/file.txt")));
+        assert!(!is_case_only_change(Path::new("/d/File.txt"), Path::new("/d/file2.txt")));
+    }
+
+    #[test]
+    fn rename_via_temp_applies_new_case() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let from = dir.path().join("File.txt");
+        let to = dir.path().join("file.txt");
+        std::fs::write(&from, "content").expect("write");
+        rename_via_temp(&from, &to).expect("rename");
+        let names: Vec<String> = std::fs::read_dir(dir.path())
+            .expect("read_dir")
+            .map(|e| e.expect("entry").file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["file.txt"]);
+        assert_eq!(std::fs::read_to_string(&to).expect("read"), "content");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_mode_does_not_make_a_file_read_only_on_unix() {
+        use std::os::unix::fs::PermissionsExt;
+        // 書き込み権限のないファイルも、フォルダに書き込めればリネームできる
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("locked.txt");
+        std::fs::write(&path, "content").expect("write");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444)).expect("chmod");
+        assert!(!is_read_only(&path));
+        let renamed = dir.path().join("renamed.txt");
+        transfer(&path, &renamed, &ApplyMode::Rename).expect("rename");
+        assert!(renamed.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn renaming_a_symlink_moves_the_link_not_its_target() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        let renamed = dir.path().join("renamed.txt");
+        std::fs::write(&target, "content").expect("write");
+        std::os::unix::fs::symlink(&target, &link).expect("symlink");
+
+        assert!(!transfer(&link, &renamed, &ApplyMode::Rename).expect("rename"));
+        assert!(std::fs::symlink_metadata(&link).is_err());
+        assert!(std::fs::symlink_metadata(&renamed).expect("renamed").file_type().is_symlink());
+        assert_eq!(std::fs::read_link(&renamed).expect("read_link"), target);
+        // リンク先はそのまま
+        assert!(!std::fs::symlink_metadata(&target).expect("target").file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).expect("read"), "content");
+    }
+}
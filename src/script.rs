@@ -0,0 +1,129 @@
+// 式（evalexpr）によるファイルごとの新しい名前の算出
+//
+// 上級者向けの任意の処理用。式はファイルごとに評価し、結果の文字列を新しい名前の元にする。
+// evalexpr はファイル・ネットワークへのアクセスやループを持たないため、式から外部には触れない。
+
+use crate::paths::to_extended_path;
+use crate::transform::split_basename_ext;
+use chrono::{DateTime, Local};
+use evalexpr::{
+    build_operator_tree, ContextWithMutableFunctions, ContextWithMutableVariables, EvalexprError, Function,
+    HashMapContext, Node, Value,
+};
+use std::path::Path;
+
+/// 更新日時 `mtime` の書式（`{exif}` の既定の書式と同じ）
+const MTIME_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// コンパイル済みの式
+pub struct NameScript {
+    node: Node,
+}
+
+/// 式から参照できる 1 ファイル分の値
+pub struct ScriptVars {
+    pub name: String,  // 元のファイル名（拡張子を含む）
+    pub index: usize,  // 一覧での番号（1 から）
+    pub size: u64,     // ファイルサイズ（バイト）
+    pub mtime: String, // 更新日時（例 20240703_142501、取得できなければ空）
+    pub dir: String,   // ファイルのあるフォルダの名前
+    pub compound: bool, // `.tar.gz` などを 1 つの拡張子として `ext` に入れる
+}
+
+impl ScriptVars {
+    /// ファイルの情報から値を集める（サイズ・更新日時を読めなければ 0 と空）。
+    pub fn from_file(path: &Path, index: usize, compound: bool) -> Self {
+        let meta = std::fs::metadata(to_extended_path(path)).ok();
+        let mtime = meta
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .map(|t| DateTime::<Local>::from(t).format(MTIME_FORMAT).to_string())
+            .unwrap_or_default();
+        Self {
+            name: path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default(),
+            index,
+            size: meta.map(|m| m.len()).unwrap_or(0),
+            mtime,
+            dir: path
+                .parent()
+                .and_then(|p| p.file_name())
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            compound,
+        }
+    }
+
+    /// 正規表現テスト欄のサンプル名用（ファイルがないためサイズ 0、更新日時・フォルダは空）
+    pub fn sample(name: &str, index: usize, compound: bool) -> Self {
+        Self { name: name.to_string(), index, size: 0, mtime: String::new(), dir: String::new(), compound }
+    }
+}
+
+impl NameScript {
+    pub fn compile(expr: &str) -> Result<Self, String> {
+        if expr.trim().is_empty() {
+            return Err("式が空です".to_string());
+        }
+        build_operator_tree(expr).map(|node| Self { node }).map_err(|e| e.to_string())
+    }
+
+    /// 式を評価して新しい名前を返す。結果が文字列・数値以外や空の場合はエラー。
+    pub fn eval(&self, vars: &ScriptVars) -> Result<String, String> {
+        let mut context = script_context(vars).map_err(|e| e.to_string())?;
+        let value = self.node.eval_with_context_mut(&mut context).map_err(|e| e.to_string())?;
+        let name = match value {
+            Value::String(s) => s,
+            Value::Int(n) => n.to_string(),
+            Value::Float(n) => n.to_string(),
+            other => return Err(format!("式の結果が文字列ではありません: {}", other)),
+        };
+        if name.trim().is_empty() {
+            return Err("式の結果が空です".to_string());
+        }
+        Ok(name)
+    }
+}
+
+/// 変数と追加の関数を設定した評価用の環境を作る。
+fn script_context(vars: &ScriptVars) -> Result<HashMapContext, EvalexprError> {
+    let (stem, ext) = match split_basename_ext(&vars.name, vars.compound) {
+        Some((stem, ext)) => (stem.to_string(), ext.to_string()),
+        None => (vars.name.clone(), String::new()),
+    };
+    let mut context = HashMapContext::new();
+    context.set_value("name".to_string(), Value::from(vars.name.clone()))?;
+    context.set_value("stem".to_string(), Value::from(stem))?;
+    context.set_value("ext".to_string(), Value::from(ext))?;
+    context.set_value("index".to_string(), Value::Int(vars.index as i64))?;
+    context.set_value("size".to_string(), Value::Int(vars.size.min(i64::MAX as u64) as i64))?;
+    context.set_value("mtime".to_string(), Value::from(vars.mtime.clone()))?;
+    context.set_value("dir".to_string(), Value::from(vars.dir.clone()))?;
+    // pad(値, 桁数): 数値を 0 埋めする（例 pad(index, 3) → "007"）
+    context.set_function(
+        "pad".to_string(),
+        Function::new(|argument| {
+            let args = argument.as_fixed_len_tuple(2)?;
+            let width = usize::try_from(args[1].as_int()?).map_err(|_| EvalexprError::OutOfBoundsAccess)?;
+            Ok(Value::from(format!("{:0width$}", args[0].as_int()?, width = width.min(32))))
+        }),
+    )?;
+    // 組み込みの str::substring はバイト位置で切り、日本語の途中で止まるため文字数で数える版に置き換える。
+    // 名前の長さはファイルごとに違うため、範囲外は名前の長さに丸める
+    context.set_function(
+        "str::substring".to_string(),
+        Function::new(|argument| {
+            let args = argument.as_ranged_len_tuple(2..=3)?;
+            let subject = args[0].as_string()?;
+            let len = subject.chars().count();
+            let start = usize::try_from(args[1].as_int()?).map_err(|_| EvalexprError::OutOfBoundsAccess)?;
+            let end = match args.get(2) {
+                Some(end) => usize::try_from(end.as_int()?).map_err(|_| EvalexprError::OutOfBoundsAccess)?,
+                None => len,
+            }
+            .min(len);
+            let start = start.min(end);
+            Ok(Value::from(subject.chars().skip(start).take(end - start).collect::<String>()))
+        }),
+    )?;
+    Ok(context)
+}
@@ -1,53 +1,681 @@
-use druid::im::Vector;
+use druid::im::{HashMap, Vector};
+use crate::input_history::InputSnapshot;
+use crate::lookup::LookupTable;
+use crate::paths::LARGE_BATCH_WARNING_THRESHOLD;
+use crate::rename::default_rename_threads;
+use crate::tags::AudioTags;
 use druid::{Data, Lens};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// 新ファイル名に適用する Unicode 正規化形式
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum UnicodeNormalization {
+    None,
+    Nfc,
+    Nfd,
+}
+
+/// 区切り文字の正規化で置き換える区切り・置き換え先の区切り
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum Separator {
+    Space, // 空白（置き換える側では全角スペースも含む）
+    Underscore,
+    Hyphen,
+}
+
+impl Separator {
+    pub fn as_char(self) -> char {
+        match self {
+            Separator::Space => ' ',
+            Separator::Underscore => '_',
+            Separator::Hyphen => '-',
+        }
+    }
+
+    /// `c` がこの区切りか（空白は全角スペースも含む）
+    pub fn matches(self, c: char) -> bool {
+        c == self.as_char() || (self == Separator::Space && c == '\u{3000}')
+    }
+}
+
+impl std::str::FromStr for Separator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "space" => Ok(Separator::Space),
+            "underscore" => Ok(Separator::Underscore),
+            "hyphen" => Ok(Separator::Hyphen),
+            _ => Err(format!("不明な値です: {}", s)),
+        }
+    }
+}
+
+/// 元のファイルを残して新しい名前のリンクを作る場合の種類
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum LinkKind {
+    None, // リンクは作らない（リネーム・コピー）
+    Hard,
+    Symbolic,
+}
+
+/// 読み取り専用のファイルを移動・リネームするときの扱い
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum ReadOnlyHandling {
+    Attempt,          // そのまま変更を試す（失敗すれば読み取り専用として報告）
+    Skip,             // 変更せずに飛ばす
+    Unlock,           // 読み取り専用を解除して変更する（解除したまま）
+    UnlockAndRestore, // 解除して変更し、変更後のファイルを読み取り専用に戻す
+}
+
+impl ReadOnlyHandling {
+    /// 設定ファイルに保存する値
+    pub fn key(self) -> &'static str {
+        match self {
+            ReadOnlyHandling::Attempt => "attempt",
+            ReadOnlyHandling::Skip => "skip",
+            ReadOnlyHandling::Unlock => "unlock",
+            ReadOnlyHandling::UnlockAndRestore => "unlock_restore",
+        }
+    }
+}
+
+impl std::str::FromStr for ReadOnlyHandling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "attempt" => Ok(ReadOnlyHandling::Attempt),
+            "skip" => Ok(ReadOnlyHandling::Skip),
+            "unlock" => Ok(ReadOnlyHandling::Unlock),
+            "unlock_restore" => Ok(ReadOnlyHandling::UnlockAndRestore),
+            _ => Err(format!("不明な値です: {}", s)),
+        }
+    }
+}
+
+/// プレビューをクリップボードへコピーするときの 1 行の書式
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum CopyFormat {
+    Arrow, // 元のパス → 新しいパス
+    Tab,   // 元のパス<TAB>新しいパス（表計算・スクリプト向け）
+}
+
+/// メインの検索で何番目の一致を置換するか
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum ReplaceCount {
+    All,
+    First,
+    Last,
+    Nth, // replace_nth 番目（1 から）
+}
+
+impl ReplaceCount {
+    /// `total` 件の一致のうち `index` 番目（0 から）を置換するか。
+    pub fn selects(self, nth: usize, index: usize, total: usize) -> bool {
+        match self {
+            ReplaceCount::All => true,
+            ReplaceCount::First => index == 0,
+            ReplaceCount::Last => index + 1 == total,
+            ReplaceCount::Nth => index + 1 == nth,
+        }
+    }
+}
+
+/// メインの検索/置換の後に順に適用する追加の置換ルール
+#[derive(Clone, Data, Lens)]
+pub struct ReplaceRule {
+    pub id: usize,
+    pub search: String,
+    pub replace: String,
+    pub use_regex: bool,
+    pub case_sensitive: bool,
+}
+
+impl ReplaceRule {
+    pub fn new(id: usize) -> Self {
+        Self {
+            id,
+            search: "".to_string(),
+            replace: "".to_string(),
+            use_regex: false,
+            case_sensitive: false,
+        }
+    }
+}
+
+/// ファイル一覧の走査条件（バックグラウンドの走査スレッドへ渡す）
+#[derive(Clone, Debug, PartialEq, Data)]
+pub struct ScanOptions {
+    pub dir: String,
+    pub extra_dirs: Vector<String>,
+    pub include_subdirectories: bool,
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: bool,
+    pub exclude_pattern: String,
+    pub exclude_hidden: bool,
+    pub min_size: String,
+    pub max_size: String,
+    pub excluded_extensions: Vector<String>,
+    pub read_audio_tags: bool,
+}
+
+impl ScanOptions {
+    pub fn from_state(data: &AppState) -> Self {
+        Self {
+            dir: data.selected_dir.clone(),
+            extra_dirs: data.extra_dirs.clone(),
+            include_subdirectories: data.include_subdirectories,
+            max_depth: data.max_depth,
+            follow_symlinks: data.follow_symlinks,
+            exclude_pattern: data.exclude_pattern.clone(),
+            exclude_hidden: data.exclude_hidden,
+            min_size: data.min_size.clone(),
+            max_size: data.max_size.clone(),
+            excluded_extensions: data.excluded_extensions.clone(),
+            read_audio_tags: data.use_audio_tags,
+        }
+    }
+
+    /// 読み込む対象フォルダ（対象フォルダと追加フォルダ。空欄と重複は除く）
+    pub fn roots(&self) -> Vec<String> {
+        let mut roots: Vec<String> = Vec::new();
+        for dir in std::iter::once(&self.dir).chain(self.extra_dirs.iter()) {
+            if !dir.trim().is_empty() && !roots.contains(dir) {
+                roots.push(dir.clone());
+            }
+        }
+        roots
+    }
+}
+
+/// 走査で見つかったファイル（音楽タグは有効時のみ走査スレッドで読み込む）
+#[derive(Clone, Debug)]
+pub struct ScannedFile {
+    pub path: String,
+    pub root: String, // 見つけた対象フォルダ
+    pub audio_tags: Option<Arc<AudioTags>>,
+    pub read_only: bool, // 読み取り専用のファイル
+}
+
+/// 除外欄の 1 項目のチップ（除外欄と OFF にした項目から表示のたびに作る）
+#[derive(Clone, Data, PartialEq)]
+pub struct ExcludeChip {
+    pub raw: String,   // 除外欄に書いた項目（`!` 付きならそのまま）
+    pub kind: String,  // 種類（グロブ / 正規表現 / 名前 / パス、無効なら「無効」）
+    pub enabled: bool, // OFF なら除外欄から外している
+}
+
+/// 読み込んだフォルダにある拡張子ごとの切り替え（拡張子の絞り込み）
+#[derive(Clone, Data, Lens)]
+pub struct ExtensionChip {
+    pub ext: String,    // 小文字の拡張子（拡張子なしは空）
+    pub count: usize,   // その拡張子のファイル数（絞り込みで外したものも数える）
+    pub enabled: bool,  // OFF なら一覧・適用の対象から外す
+}
+
+/// 走査の結果の要約（走査の完了時に UI スレッドへ送る）
+#[derive(Clone, Debug, Default)]
+pub struct ScanSummary {
+    pub exclude_error: Option<String>,        // 除外パターンのエラー
+    pub extensions: BTreeMap<String, usize>,  // 拡張子ごとのファイル数（拡張子の絞り込みで外したものも含む）
+    pub invalid_names: Vec<String>,           // 名前が UTF-8 でないため読み込まなかったファイル（置き換え文字で表示用にしたパス）
+    pub excluded_by: Vec<(String, usize)>,    // 除外欄の項目ごとの除外したファイル数（除外欄に書いた順、サイズの範囲は最後）
+    pub size_filtered: usize,                 // サイズの範囲外で除外したファイル数
+}
+
+impl ScanSummary {
+    /// 読み込み完了のステータス（除外パターンのエラーがあればそれを優先する）
+    pub fn status(&self, loaded: usize) -> String {
+        let mut status = match &self.exclude_error {
+            Some(e) => e.clone(),
+            None => format!("ファイル {} 件を読み込み", loaded),
+        };
+        if self.size_filtered > 0 {
+            status.push_str(&format!("（サイズの範囲外 {} 件を除外）", self.size_filtered));
+        }
+        if let Some(first) = self.invalid_names.first() {
+            status.push_str(&format!(
+                " ※名前が UTF-8 でないファイル {} 件は、正しく名前を変えられないため読み込みません: {}",
+                self.invalid_names.len(),
+                first
+            ));
+        }
+        status
+    }
+
+    /// 除外の内訳（1 行に 1 項目、例 `re:\.tmp$ → 12 件除外`）。除外の項目がなければ空。
+    pub fn exclude_breakdown(&self) -> String {
+        self.excluded_by
+            .iter()
+            .map(|(label, count)| format!("{} → {} 件除外", label, count))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
 
 /// 各ファイルの情報（元のパスと新ファイル名）
 #[derive(Clone, Data, Lens)]
 pub struct FileEntry {
     pub original_path: String,
+    pub root: String, // 読み込んだ対象フォルダ（追加フォルダのファイルならそのフォルダ、履歴の取り消しでは空）
     pub new_name: String,
+    // 連番 {n} を展開する前の新ファイル名（並べ替え時の再採番に使う）
+    pub name_template: String,
     // ハイライト用の情報
     pub search_pattern: String,
     pub replace_pattern: String,
     pub case_sensitive: bool,
+    pub use_regex: bool,
+    pub regex_unicode: bool,
+    pub regex_dot_all: bool,
+    pub whole_match: bool,
+    pub replace_count: ReplaceCount,
+    pub replace_nth: usize,
+    pub search_only: bool, // 検索のみモードの結果（一致をすべてハイライトし、フルパスを表示する）
+    pub fuzzy_max_distance: Option<usize>, // あいまい検索の結果なら許す編集距離（ハイライトも近い部分を示す）
+    pub conflicts_existing: bool, // 変更後のパスに既存のファイルがある（適用時に拒否される）
+    pub name_collision: bool,     // 変更後のパスが今回変更する他のファイルと重なる（適用時に拒否される）
+    pub overwrite_existing: bool, // 衝突の確認で既存のファイルを上書きすると決めた（適用時のみ）
+    pub path_error: String, // 新しい名前のサブフォルダ指定が使えない理由（問題なければ空、適用時に拒否される）
+    pub hash_error: String, // {hash} のために内容を読めなかった理由（トークンは空にした、問題なければ空）
+    pub name_note: String, // 新しい名前を補正した場合の元の名前（末尾の . や空白を除いた場合など、補正していなければ空）
+    pub target_path: String, // 適用後のフルパス（適用時と同じ計算、求められなければ空）
+    pub show_target: bool,   // プレビュー行で詳細（変更の理由と適用後のフルパス）を開いて表示しているか
+    pub in_range: bool, // 適用する範囲（プレビューの N〜M 番目）に含まれる。範囲を限定しなければ常に true
+    pub selected: bool, // プレビューのチェック（外した行は適用しない）
+    pub read_only: bool, // 読み込んだときに読み取り専用だった
+    pub change_reason: String, // 名前を変えた処理（例 検索/置換 → 接尾辞、変わらなければ空）
+    pub auto_numbered: bool,   // 重複時の連番を付けた
+    pub duplicate_group: usize, // 内容が同一のファイルのグループ番号（重複ファイル検出の結果、0 なら重複なし・未検出）
+    pub audio_tags: Option<Arc<AudioTags>>, // 音楽タグ（タグ名付けが有効で、タグを読めた場合のみ）
+    // プレビューでフォルダの先頭行に表示する見出し（対象フォルダからの相対パス、先頭行以外は空）
+    pub group_header: String,
+}
+
+impl FileEntry {
+    /// 適用の対象か（適用範囲内で、チェックが入っている）
+    pub fn will_apply(&self) -> bool {
+        self.in_range && self.selected
+    }
 }
 
+/// リネームに失敗したファイルと理由
+#[derive(Clone, Debug, Data, Lens)]
+pub struct RenameFailure {
+    pub original_path: String,
+    pub new_name: String,
+    pub reason: String,
+    pub read_only: bool, // 読み取り専用のために飛ばした・失敗した
+}
+
+/// 適用した 1 ファイルの結果（適用結果の一覧用）
+#[derive(Clone, Debug, Data, Lens)]
+pub struct RenameResult {
+    pub original_path: String,
+    pub folder: String,   // 元のファイルのあるフォルダ（フォルダごとの集計用）
+    pub new_path: String, // 適用後のフルパス（コピー先・リンクの場所を含む）
+    pub error: String,    // 失敗した理由（成功なら空）
+}
+
+/// 1 回の適用（取り消し）の結果。ワーカーから RENAMING_DONE で UI スレッドへ送る
+#[derive(Clone, Debug)]
+pub struct RenameOutcome {
+    pub message: String,               // 完了メッセージ（件数・合計サイズ・所要時間など）
+    pub results: Vec<RenameResult>,    // ファイルごとの結果（適用した順）
+    pub failures: Vec<RenameFailure>,  // 失敗したファイルと理由
+}
+
+impl RenameOutcome {
+    /// フォルダごとの成功・失敗の件数（フォルダが最初に現れた順）
+    pub fn folder_counts(&self) -> Vec<(String, usize, usize)> {
+        let mut counts: Vec<(String, usize, usize)> = Vec::new();
+        for result in &self.results {
+            let index = match counts.iter().position(|(folder, _, _)| *folder == result.folder) {
+                Some(index) => index,
+                None => {
+                    counts.push((result.folder.clone(), 0, 0));
+                    counts.len() - 1
+                }
+            };
+            if result.error.is_empty() {
+                counts[index].1 += 1;
+            } else {
+                counts[index].2 += 1;
+            }
+        }
+        counts
+    }
+
+    /// フォルダごとの内訳（1 行に 1 フォルダ、例 `C:\photos\2024: 変更 10 件、エラー 1 件`）。
+    /// エラーのあったフォルダを先に並べる。1 つのフォルダだけなら完了メッセージと同じになるため空。
+    pub fn folder_breakdown(&self) -> String {
+        let mut counts = self.folder_counts();
+        if counts.len() <= 1 {
+            return String::new();
+        }
+        counts.sort_by_key(|(_, _, failed)| *failed == 0);
+        counts
+            .iter()
+            .map(|(folder, done, failed)| match failed {
+                0 => format!("{}: 変更 {} 件", folder, done),
+                _ => format!("{}: 変更 {} 件、エラー {} 件", folder, done, failed),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// 適用履歴（ログ）の 1 バッチ分の要約
+#[derive(Clone, Debug, Data, Lens)]
+pub struct LogBatch {
+    pub batch_id: String,
+    pub timestamp: String,
+    pub op: String,
+    pub folder: String,
+    pub count: usize,
+    pub errors: usize,
+}
+
+/// ステータスの履歴に残す最大件数
+const STATUS_LOG_LIMIT: usize = 200;
+
 /// アプリ全体の状態
 #[derive(Clone, Data, Lens)]
 pub struct AppState {
     pub selected_dir: String,
+    pub extra_dirs: Vector<String>, // 同じ条件でまとめて変更する追加の対象フォルダ
     pub files: Vector<FileEntry>,
-    pub preview_files: Vector<FileEntry>, // 変更前と変更後が異なるファイル
+    pub preview_files: Vector<FileEntry>, // 変更前と変更後が異なるファイル（検索のみモードでは検索に一致したファイル）
+    pub pasted_names: HashMap<String, String>, // クリップボードから貼り付けた新しい名前（元のパス → 名前、あれば検索・置換より優先）
+    pub search_only: bool,                // 検索のみモード（一致したファイルを探すだけで、名前は変えない・適用できない）
+    pub fuzzy_search: bool,               // 検索のみモードで、検索語に近い名前も探す（あいまい検索）
+    pub fuzzy_distance: usize,            // あいまい検索で許す編集距離（違う文字の数）
+    pub preview_filter: String,           // プレビュー一覧の表示フィルタ（適用対象には影響しない）
+    pub visible_preview: Vector<FileEntry>, // preview_files のうちフィルタに一致する行（表示用）
+    pub problems_first: bool,             // 表示で問題のある行（衝突・使えない名前など）を先頭に並べる（適用・連番の順は変えない）
     pub search_pattern: String,
     pub replace_pattern: String,
+    pub extra_rules: Vector<ReplaceRule>,
+    pub renumber: bool,          // 連番を振り直す（名前の最後の数字を並び順の番号に置き換える）
+    pub renumber_digits: usize,  // 振り直す番号の桁数（ゼロ埋め、0 ならそのまま）
+    pub renumber_whole: bool,    // 拡張子の前の部分全体を番号にする
+    pub strip_existing_number: bool, // 名前の元の番号（末尾、なければ先頭の数字）を除いてから処理する（番号は {num} で使える）
+    pub sequence_start: usize,   // 連番 {n} の開始番号
+    pub reverse_numbering: bool, // 連番 {n} をプレビューの最後の行から振る（表示の並び順は変えない）
+    pub lookup_table: Option<Arc<LookupTable>>, // 読み込んだ置換表（検索/置換・追加ルールの後に適用）
+    pub strip_copy_suffixes: bool, // " (1)", " copy", " のコピー" などの重複風の接尾辞を除去
+    pub prefix: String, // 拡張子の前の部分の先頭に付ける文字列
+    pub suffix: String, // 拡張子の前の部分の末尾に付ける文字列
+    pub insert_text: String,   // 拡張子の前の部分の途中に挿入する文字列
+    pub insert_position: isize, // 挿入位置（先頭からの文字数、負なら末尾から）
+    pub swap_parts: bool,       // 拡張子の前の部分を区切りで 2 つに分けて前後を入れ替える
+    pub swap_delimiter: String, // 入れ替えの区切り（例 `, `）
+    pub swap_joiner: String,    // 入れ替え後につなぐ文字列（空なら区切りと同じ）
+    pub swap_split_first: bool, // 3 つ以上に分かれる名前も最初の区切りで分けて入れ替える（OFF ならそのまま）
+    pub reformat_date: bool,        // 名前に含まれる日付を新しい書式に書き換える（日付のない名前は変更しない）
+    pub date_input_format: String,  // 名前の中の日付の書式（strftime 形式、例 %Y%m%d）
+    pub date_output_format: String, // 書き換え後の日付の書式（例 %Y-%m-%d）
+    pub use_script: bool,       // 式（script_expr）の結果を新しい名前の元にする
+    pub script_expr: String,    // ファイルごとに評価する式（evalexpr）
+    pub limit_range: bool, // プレビューの range_start〜range_end 番目だけを適用する
+    pub range_start: usize, // 適用範囲の先頭（1 から）
+    pub range_end: usize,   // 適用範囲の末尾（0 なら最後まで）
+    pub session_excluded: Vector<String>, // 右クリックの「この行を除外」で外した元パス（読み直しても戻さない、フォルダを変えると空に）
+    pub selection_anchor: String, // 最後にクリックしたプレビュー行の元パス（Shift+クリックの範囲選択の起点）
     pub exclude_pattern: String,
+    pub disabled_excludes: Vector<String>, // チップで OFF にした除外の項目（除外欄には含めず、ON に戻すと除外欄の末尾に加える）
+    pub exclude_draft: String,             // 除外の項目の追加欄
+    pub invalid_names: Vector<String>, // 名前が UTF-8 でないため読み込まなかったファイル（表示用のパス、読み込むたびに作り直す）
+    pub exclude_breakdown: String,     // 直近の読み込みで除外欄の項目ごとに除外したファイル数（1 行に 1 項目）
+    pub show_exclude_breakdown: bool,  // 除外の内訳を開いて表示しているか
+    pub extension_chips: Vector<ExtensionChip>, // 読み込んだフォルダにある拡張子の一覧（読み込むたびに作り直す）
+    pub excluded_extensions: Vector<String>, // 拡張子の絞り込みで外した拡張子（小文字、フォルダを変えると空に）
+    pub min_size: String, // この大きさ未満のファイルを除外する（例 1KB、空欄なら制限なし）
+    pub max_size: String, // この大きさを超えるファイルを除外する（例 10MB、空欄なら制限なし）
+    pub exclude_hidden: bool, // 隠しファイル（. で始まる名前、Windows の隠し/システム属性）を除外
     pub case_sensitive: bool,
+    pub use_regex: bool, // 検索を正規表現として扱う（置換の $1 などを展開）
+    pub regex_unicode: bool, // 正規表現の \w \d や大小無視を Unicode で判定する（OFF なら ASCII のみ）
+    pub regex_dot_all: bool, // 正規表現の `.` を改行にも一致させる
+    pub whole_match: bool, // 検索が名前全体（パス全体を対象なら相対パス全体）と一致する場合だけ置換する
+    pub replace_count: ReplaceCount, // メインの検索で置換する一致（すべて/最初/最後/N番目）
+    pub replace_nth: usize,          // replace_count が Nth のときの番号（1 から）
     pub include_subdirectories: bool,
+    pub max_depth: Option<usize>, // サブフォルダを含める時に読み込む階層の上限（1 なら直下のみ、2 ならその 1 つ下まで。None なら無制限）
+    pub follow_symlinks: bool, // 走査でシンボリックリンク（ファイル・フォルダ）をたどる（OFF ならリンクは一覧に出さない）
+    pub use_audio_tags: bool, // 音楽ファイルのタグを読み込み、{artist} などのトークンを使えるようにする
+    pub exif_fallback_mtime: bool, // {exif} で EXIF がない場合に更新日時を使う（OFF なら空）
+    pub flatten: bool,           // サブフォルダ内のファイルも対象フォルダ直下へ移動する（サブフォルダを含める時のみ）
+    pub remove_empty_dirs: bool, // フラット化で空になったサブフォルダを削除する
+    pub strip_trailing_dots: bool, // 新しい名前の末尾の . と空白を取り除く（Windows のみ。他の OS では何もしない）
+    pub sort_into_subfolders: bool, // 新しい名前の / をフォルダの区切りとして、サブフォルダ（なければ作成）へ移動する
+    pub match_full_path: bool, // 検索/置換を対象フォルダからの相対パス全体に適用し、書き換えたパスへ移動する
     pub auto_number_on_conflict: bool,
+    pub auto_number_template: String, // 連番の書式（{n} / {n:桁数} が番号）。拡張子の前に付く
+    pub auto_number_start: usize,     // 連番の開始番号
+    pub case_insensitive_fs: bool, // 対象ファイルシステムが大小無視か（重複・衝突判定に使用）
+    pub lowercase_extension: bool,         // 拡張子を小文字にそろえる（.JPG → .jpg）
+    pub normalize_extension_aliases: bool, // 拡張子の別名を統一する（.jpeg → .jpg, .htm → .html など）
+    pub compound_extensions: bool,         // .tar.gz などを 1 つの拡張子として扱う（拡張子の前の部分だけを変える処理の対象）
+    pub fullwidth_to_halfwidth: bool,
+    pub normalize_separators: bool,   // 区切り文字の正規化（separator_from を separator_to にそろえる）
+    pub separator_from: Separator,
+    pub separator_to: Separator,
+    pub separator_collapse: bool,     // 連続した区切りを 1 つにまとめる
+    pub separator_trim: bool,         // 拡張子の前の部分の先頭・末尾の区切りを除く
+    pub strip_diacritics: bool, // アクセント記号だけを除去（かな・漢字は残す）
+    pub transliterate_ascii: bool,
+    pub ascii_placeholder: String, // ASCII に対応しない文字の代替（空なら元の文字を残す）
+    pub unicode_normalization: UnicodeNormalization,
+    pub restrict_chars: bool,           // 許可した文字以外を置き換える（厳しい命名規則向け）
+    pub allowed_chars: String,          // 許可する文字（`A-Za-z0-9._-` のような範囲指定）
+    pub restrict_replacement: String,   // 許可されない文字の置き換え先
+    pub collapse_replacements: bool,    // 連続した置き換えを 1 つにまとめる
+    pub max_name_length: usize, // ファイル名の最大文字数（0 なら制限なし）
+    pub truncate_with_ellipsis: bool,
+    pub copy_to_target: bool, // リネームせず target_dir へ新しい名前でコピーする
+    pub target_dir: String,
+    pub copy_format: CopyFormat, // 「クリップボードへコピー」の書式
+    pub link_kind: LinkKind, // 元を残し、変更後のパスにリンクを作る（リネーム・コピーの代わり）
+    pub confirm_before_apply: bool, // 適用前に確認ダイアログを表示する
+    pub backup_before_apply: bool,  // 元のファイルを動かす前に、対象フォルダの .filename-change-backup へコピーする
+    pub read_only_handling: ReadOnlyHandling, // 読み取り専用のファイルを移動・リネームするときの扱い
+    pub large_batch_threshold: usize, // この件数以上を一度に変更するときは必ず確認する（0 なら件数では確認しない）
+    pub rename_threads: usize, // 適用時に同時に処理するファイルの数（1 なら 1 件ずつ順に処理する）
+    pub auto_numbered_count: usize, // 直近のプレビューで連番を付与した件数
+    pub matched_count: Option<usize>, // 直近のプレビューで検索に一致した件数（検索なしは None）
+    pub hash_pending: Vector<String>, // {hash} の計算が済んでいない大きいファイル（バックグラウンドで計算し、済んだらプレビューを更新し直す）
+    pub script_error: String, // 直近のプレビューで式を評価できなかったファイルの件数と例（なければ空）
+    pub input_undo: Vector<InputSnapshot>, // 入力の変更の取り消し用（変更前の入力、古い順、Ctrl+Z で戻す）
+    pub input_redo: Vector<InputSnapshot>, // 取り消した入力のやり直し用（Ctrl+Y）
+    pub input_last_field: String, // 直前に文字を入力した欄（続けての入力を 1 回の変更にまとめる）
+    pub input_restoring: bool,    // 取り消し/やり直しで入力を戻した直後（その変更は記録しない）
+    pub test_input: String,  // 正規表現テスト用のサンプルファイル名
+    pub test_output: String, // test_input に検索/置換を適用した結果
     pub status_message: String,
+    pub status_log: Vector<String>, // 時刻付きのステータスの履歴（新しい順、STATUS_LOG_LIMIT 件まで）
+    pub scan_in_progress: bool,
+    pub duplicate_check_in_progress: bool,
+    pub duplicate_check_id: u64, // 実行中の重複ファイル検出の ID（中止・再開始した古い結果を捨てるため）
+    pub scan_id: u64,              // 実行中の走査の世代（古い走査の結果を捨てるため）
+    pub scan_options: ScanOptions, // 実行中（または直近）の走査条件
+    pub preview_after_scan: bool,  // 走査完了後にプレビューを更新する
+    pub rescan_needed: bool,       // 読み込み済みの一覧が古い（適用後など）ため、次のプレビューで読み直す
     pub conversion_in_progress: bool,
+    pub single_row_apply: bool, // 実行中の適用がプレビューの 1 行だけの適用（成功すれば完了ダイアログを出さない）
     pub conversion_total: usize,
     pub conversion_done: usize,
+    #[data(eq)]
+    pub conversion_started: Option<Instant>, // 適用（リネーム/取り消し）を開始した時刻（経過時間・残り時間の表示用）
+    pub rename_failures: Vector<RenameFailure>, // 直近の適用で失敗したファイル
+    pub rename_results: Vector<RenameResult>,   // 直近の適用のファイルごとの結果（次の適用まで残す）
+    pub rename_folder_breakdown: String,        // 直近の適用のフォルダごとの件数（1 つのフォルダだけなら空）
+    pub show_history: bool,
+    pub dark_mode: bool,  // ダークテーマ
+    pub auto_reload: bool, // 対象フォルダを監視し、外部での変更を検知したら一覧を読み直す
+    pub split_ratio: f64, // 元のファイル一覧とプレビューの幅の比率（左側の割合）
+    pub history: Vector<LogBatch>, // 適用履歴（新しい順）
 }
 
 impl AppState {
     pub fn new() -> Self {
         Self {
             selected_dir: "".to_string(),
+            extra_dirs: Vector::new(),
             files: Vector::new(),
             preview_files: Vector::new(),
+            pasted_names: HashMap::new(),
+            preview_filter: "".to_string(),
+            problems_first: false,
+            search_only: false,
+            fuzzy_search: false,
+            fuzzy_distance: 1,
+            visible_preview: Vector::new(),
             search_pattern: "".to_string(),
             replace_pattern: "".to_string(),
+            extra_rules: Vector::new(),
+            renumber: false,
+            renumber_digits: 2,
+            renumber_whole: false,
+            strip_existing_number: false,
+            sequence_start: 1,
+            reverse_numbering: false,
+            lookup_table: None,
+            strip_copy_suffixes: false,
+            prefix: "".to_string(),
+            suffix: "".to_string(),
+            insert_text: "".to_string(),
+            insert_position: 0,
+            swap_parts: false,
+            swap_delimiter: " - ".to_string(),
+            swap_joiner: String::new(),
+            swap_split_first: false,
+            reformat_date: false,
+            date_input_format: "%Y%m%d".to_string(),
+            date_output_format: "%Y-%m-%d".to_string(),
+            use_script: false,
+            script_expr: "".to_string(),
+            limit_range: false,
+            range_start: 1,
+            range_end: 0,
+            session_excluded: Vector::new(),
+            selection_anchor: String::new(),
             exclude_pattern: "".to_string(),
+            disabled_excludes: Vector::new(),
+            exclude_draft: String::new(),
+            invalid_names: Vector::new(),
+            exclude_breakdown: String::new(),
+            show_exclude_breakdown: false,
+            extension_chips: Vector::new(),
+            excluded_extensions: Vector::new(),
+            min_size: "".to_string(),
+            max_size: "".to_string(),
+            exclude_hidden: true,
             case_sensitive: false,
+            use_regex: false,
+            regex_unicode: true,
+            regex_dot_all: false,
+            whole_match: false,
+            replace_count: ReplaceCount::All,
+            replace_nth: 1,
             include_subdirectories: false,
+            max_depth: None,
+            follow_symlinks: false,
+            use_audio_tags: false,
+            exif_fallback_mtime: true,
+            flatten: false,
+            remove_empty_dirs: false,
+            strip_trailing_dots: true,
+            sort_into_subfolders: false,
+            match_full_path: false,
             auto_number_on_conflict: false,
+            auto_number_template: " ({n})".to_string(),
+            auto_number_start: 2,
+            case_insensitive_fs: cfg!(any(windows, target_os = "macos")),
+            lowercase_extension: false,
+            normalize_extension_aliases: false,
+            compound_extensions: false,
+            fullwidth_to_halfwidth: false,
+            normalize_separators: false,
+            separator_from: Separator::Space,
+            separator_to: Separator::Underscore,
+            separator_collapse: true,
+            separator_trim: true,
+            strip_diacritics: false,
+            transliterate_ascii: false,
+            ascii_placeholder: "".to_string(),
+            unicode_normalization: UnicodeNormalization::None,
+            restrict_chars: false,
+            allowed_chars: "A-Za-z0-9._-".to_string(),
+            restrict_replacement: "_".to_string(),
+            collapse_replacements: true,
+            max_name_length: 0,
+            truncate_with_ellipsis: false,
+            copy_to_target: false,
+            copy_format: CopyFormat::Arrow,
+            link_kind: LinkKind::None,
+            target_dir: "".to_string(),
+            confirm_before_apply: true,
+            backup_before_apply: false,
+            read_only_handling: ReadOnlyHandling::Attempt,
+            large_batch_threshold: LARGE_BATCH_WARNING_THRESHOLD,
+            rename_threads: default_rename_threads(),
+            auto_numbered_count: 0,
+            matched_count: None,
+            hash_pending: Vector::new(),
+            script_error: String::new(),
+            input_undo: Vector::new(),
+            input_redo: Vector::new(),
+            input_last_field: String::new(),
+            input_restoring: false,
+            test_input: "".to_string(),
+            test_output: "".to_string(),
             status_message: "準備完了".to_string(),
+            status_log: Vector::new(),
+            scan_in_progress: false,
+            duplicate_check_in_progress: false,
+            duplicate_check_id: 0,
+            scan_id: 0,
+            scan_options: ScanOptions {
+                dir: "".to_string(),
+                extra_dirs: Vector::new(),
+                include_subdirectories: false,
+                max_depth: None,
+                follow_symlinks: false,
+                exclude_pattern: "".to_string(),
+                exclude_hidden: true,
+                min_size: "".to_string(),
+                max_size: "".to_string(),
+                excluded_extensions: Vector::new(),
+                read_audio_tags: false,
+            },
+            preview_after_scan: false,
+            rescan_needed: true,
             conversion_in_progress: false,
+            single_row_apply: false,
             conversion_total: 0,
             conversion_done: 0,
+            conversion_started: None,
+            rename_failures: Vector::new(),
+            rename_results: Vector::new(),
+            rename_folder_breakdown: String::new(),
+            show_history: false,
+            dark_mode: true,
+            auto_reload: false,
+            split_ratio: 0.5,
+            history: Vector::new(),
+        }
+    }
+    /// ステータスを表示し、時刻付きで履歴にも残す（読み込み中の件数など途中経過は直接 status_message に書く）。
+    pub fn set_status(&mut self, message: impl Into<String>) {
+        self.status_message = message.into();
+        let line = format!("{} {}", chrono::Local::now().format("%H:%M:%S"), self.status_message);
+        self.status_log.push_front(line);
+        if self.status_log.len() > STATUS_LOG_LIMIT {
+            self.status_log.truncate(STATUS_LOG_LIMIT);
         }
     }
 }
-
-
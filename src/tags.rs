@@ -0,0 +1,87 @@
+// 音楽ファイルの埋め込みタグ（アーティスト・アルバム・曲番号・タイトル）による名前付け
+
+use crate::transform::replace_forbidden_chars;
+use lofty::prelude::*;
+use std::path::Path;
+
+/// タグを読み込む拡張子（それ以外のファイルは開かない）
+const AUDIO_EXTENSIONS: [&str; 9] = ["mp3", "flac", "m4a", "ogg", "opus", "wav", "aiff", "ape", "wv"];
+
+/// 名前に使えるタグの値
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioTags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .map(|e| AUDIO_EXTENSIONS.contains(&e.as_str()))
+        .unwrap_or(false)
+}
+
+/// 対応する拡張子のファイルだけタグを読む。タグがない・読めない場合は None。
+pub fn read_audio_tags(path: &Path) -> Option<AudioTags> {
+    if !is_audio_file(path) {
+        return None;
+    }
+    let tagged = lofty::read_from_path(path).ok()?;
+    let tag = tagged.primary_tag().or_else(|| tagged.first_tag())?;
+    let tags = AudioTags {
+        artist: tag.artist().map(|s| s.to_string()),
+        album: tag.album().map(|s| s.to_string()),
+        title: tag.title().map(|s| s.to_string()),
+        track: tag.track(),
+    };
+    if tags == AudioTags::default() {
+        return None;
+    }
+    Some(tags)
+}
+
+/// `{artist}` / `{album}` / `{title}` / `{track}` のいずれかを含むか。
+pub fn has_tag_tokens(text: &str) -> bool {
+    ["{artist}", "{album}", "{title}", "{track"].iter().any(|t| text.contains(t))
+}
+
+/// タグのトークンを値に置き換える。`{track:2}` は 2 桁ゼロ埋め。値のないタグは空文字になる。
+/// ファイル名に使えない文字（`\ / : * ? " < > |`）は `_` に置き換える。
+pub fn expand_tag_tokens(name: &str, tags: &AudioTags) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let token = &after[..end];
+        let (key, width) = match token.split_once(':') {
+            Some((k, w)) => (k, w.parse::<usize>().ok()),
+            None => (token, None),
+        };
+        let value = match key {
+            "artist" => tags.artist.clone().unwrap_or_default(),
+            "album" => tags.album.clone().unwrap_or_default(),
+            "title" => tags.title.clone().unwrap_or_default(),
+            "track" => tags
+                .track
+                .map(|n| format!("{:0width$}", n, width = width.unwrap_or(0)))
+                .unwrap_or_default(),
+            _ => {
+                // 対象外のトークン（{n} など）はそのまま残す
+                out.push('{');
+                rest = after;
+                continue;
+            }
+        };
+        out.push_str(&replace_forbidden_chars(value.trim()));
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,108 @@
+// 配色（ライト/ダーク）。AppState::dark_mode に応じて druid::theme の色とアプリ独自の色を切り替える
+
+use crate::config::Settings;
+use druid::{theme, Color, Env, Key};
+use tracing::warn;
+
+/// 検索一致・差分ハイライトの文字色
+pub const HIGHLIGHT_TEXT_COLOR: Key<Color> = Key::new("filename-change.highlight-text-color");
+/// 検索一致・差分ハイライトの背景色
+pub const HIGHLIGHT_BACKGROUND_COLOR: Key<Color> = Key::new("filename-change.highlight-background-color");
+/// 進捗バーの塗りつぶし色
+pub const PROGRESS_FILL_COLOR: Key<Color> = Key::new("filename-change.progress-fill-color");
+/// 進捗バー上の文字色
+pub const PROGRESS_TEXT_COLOR: Key<Color> = Key::new("filename-change.progress-text-color");
+
+/// 設定ファイルで上書きできる色と、その設定キー（`dark.` / `light.` を前に付ける）
+const CUSTOMIZABLE_COLORS: [(&str, Key<Color>); 4] = [
+    ("highlight_text_color", HIGHLIGHT_TEXT_COLOR),
+    ("highlight_background_color", HIGHLIGHT_BACKGROUND_COLOR),
+    ("progress_fill_color", PROGRESS_FILL_COLOR),
+    ("progress_text_color", PROGRESS_TEXT_COLOR),
+];
+
+/// 設定ファイルで指定された色（例 `dark.highlight_background_color=#005FAF`）
+#[derive(Clone, Default)]
+pub struct ColorOverrides {
+    dark: Vec<(Key<Color>, Color)>,
+    light: Vec<(Key<Color>, Color)>,
+}
+
+impl ColorOverrides {
+    /// 設定から色の上書きを読み込む。`#RRGGBB` / `#RRGGBBAA` として読めない値は無視する。
+    pub fn load(settings: &Settings) -> Self {
+        let read = |prefix: &str| -> Vec<(Key<Color>, Color)> {
+            CUSTOMIZABLE_COLORS
+                .iter()
+                .filter_map(|(name, key)| {
+                    let setting = format!("{}.{}", prefix, name);
+                    let value: String = settings.get(&setting)?;
+                    match Color::from_hex_str(&value) {
+                        Ok(color) => Some((*key, color)),
+                        Err(_) => {
+                            warn!(setting, value, "invalid_color_setting");
+                            None
+                        }
+                    }
+                })
+                .collect()
+        };
+        Self { dark: read("dark"), light: read("light") }
+    }
+}
+
+/// ダーク/ライトの配色を Env に設定し、設定ファイルの色の上書きを反映する（ルートの EnvScope から呼ぶ）。
+pub fn apply_theme(env: &mut Env, dark: bool, overrides: &ColorOverrides) {
+    apply_palette(env, dark);
+    let colors = if dark { &overrides.dark } else { &overrides.light };
+    for (key, color) in colors {
+        env.set(*key, *color);
+    }
+}
+
+fn apply_palette(env: &mut Env, dark: bool) {
+    if dark {
+        env.set(theme::WINDOW_BACKGROUND_COLOR, Color::rgb8(0x29, 0x29, 0x2C));
+        env.set(theme::BACKGROUND_LIGHT, Color::rgb8(0x3A, 0x3A, 0x3A));
+        env.set(theme::BACKGROUND_DARK, Color::rgb8(0x31, 0x31, 0x2D));
+        env.set(theme::TEXT_COLOR, Color::rgb8(0xF0, 0xF0, 0xEA));
+        env.set(theme::DISABLED_TEXT_COLOR, Color::rgb8(0xA0, 0xA0, 0x9B));
+        env.set(theme::PLACEHOLDER_COLOR, Color::rgb8(0x80, 0x80, 0x80));
+        env.set(theme::BUTTON_LIGHT, Color::rgb8(0x5C, 0x5C, 0x5C));
+        env.set(theme::BUTTON_DARK, Color::rgb8(0x21, 0x21, 0x21));
+        env.set(theme::BORDER_LIGHT, Color::rgb8(0xA1, 0xA1, 0xA1));
+        env.set(theme::BORDER_DARK, Color::rgb8(0x3A, 0x3A, 0x3A));
+        env.set(theme::FOREGROUND_LIGHT, Color::rgb8(0xF9, 0xF9, 0xF9));
+        env.set(theme::FOREGROUND_DARK, Color::rgb8(0xBF, 0xBF, 0xBF));
+        env.set(theme::PRIMARY_LIGHT, Color::rgb8(0x5C, 0xC4, 0xFF));
+        env.set(theme::PRIMARY_DARK, Color::rgb8(0x00, 0x8D, 0xDD));
+        env.set(theme::SELECTED_TEXT_BACKGROUND_COLOR, Color::rgb8(0x43, 0x70, 0xA8));
+        env.set(theme::CURSOR_COLOR, Color::WHITE);
+        // 暗い背景では明るい黄色が眩しいため、落ち着いた色に白い文字を重ねる
+        env.set(HIGHLIGHT_TEXT_COLOR, Color::WHITE);
+        env.set(HIGHLIGHT_BACKGROUND_COLOR, Color::rgb8(0x8A, 0x6D, 0x00));
+        env.set(PROGRESS_FILL_COLOR, Color::rgb8(0x2E, 0x8B, 0x57));
+        env.set(PROGRESS_TEXT_COLOR, Color::WHITE);
+    } else {
+        env.set(theme::WINDOW_BACKGROUND_COLOR, Color::rgb8(0xF3, 0xF3, 0xF3));
+        env.set(theme::BACKGROUND_LIGHT, Color::rgb8(0xFF, 0xFF, 0xFF));
+        env.set(theme::BACKGROUND_DARK, Color::rgb8(0xE6, 0xE6, 0xE6));
+        env.set(theme::TEXT_COLOR, Color::rgb8(0x1E, 0x1E, 0x1E));
+        env.set(theme::DISABLED_TEXT_COLOR, Color::rgb8(0x8C, 0x8C, 0x8C));
+        env.set(theme::PLACEHOLDER_COLOR, Color::rgb8(0x8C, 0x8C, 0x8C));
+        env.set(theme::BUTTON_LIGHT, Color::rgb8(0xFD, 0xFD, 0xFD));
+        env.set(theme::BUTTON_DARK, Color::rgb8(0xE1, 0xE1, 0xE1));
+        env.set(theme::BORDER_LIGHT, Color::rgb8(0x8A, 0x8A, 0x8A));
+        env.set(theme::BORDER_DARK, Color::rgb8(0xB4, 0xB4, 0xB4));
+        env.set(theme::FOREGROUND_LIGHT, Color::rgb8(0x00, 0x00, 0x00));
+        env.set(theme::FOREGROUND_DARK, Color::rgb8(0x33, 0x33, 0x33));
+        env.set(theme::PRIMARY_LIGHT, Color::rgb8(0x3C, 0x8C, 0xE6));
+        env.set(theme::PRIMARY_DARK, Color::rgb8(0x00, 0x64, 0xC8));
+        env.set(theme::SELECTED_TEXT_BACKGROUND_COLOR, Color::rgb8(0xAD, 0xD6, 0xFF));
+        env.set(theme::CURSOR_COLOR, Color::BLACK);
+        env.set(HIGHLIGHT_TEXT_COLOR, Color::BLACK);
+        env.set(HIGHLIGHT_BACKGROUND_COLOR, Color::rgb8(0xFF, 0xFF, 0x00));
+        env.set(PROGRESS_FILL_COLOR, Color::rgb8(0x00, 0x80, 0x00));
+        env.set(PROGRESS_TEXT_COLOR, Color::WHITE);
+    }
+}
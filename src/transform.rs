@@ -0,0 +1,430 @@
+// 新ファイル名に対する後処理（ASCII化・全角→半角など）
+
+use crate::state::{Separator, UnicodeNormalization};
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization as _;
+
+/// 2 つ合わせて 1 つの拡張子として扱える拡張子（小文字）
+pub const COMPOUND_EXTENSIONS: [&str; 4] = ["tar.gz", "tar.bz2", "tar.xz", "tar.zst"];
+
+/// ファイル名を拡張子の前の部分と拡張子（区切りの `.` を含まない）に分ける。拡張子がなければ None。
+/// `.gitignore` のような `.` で始まるだけの名前や、`.` で終わる名前は拡張子のない名前として扱う。
+/// `compound` なら `archive.tar.gz` の `tar.gz` のような 2 つの部分からなる拡張子をまとめて 1 つの拡張子とする。
+pub fn split_basename_ext(name: &str, compound: bool) -> Option<(&str, &str)> {
+    if compound {
+        // ASCII の小文字化はバイト数を変えないため、小文字の名前での位置を元の名前にも使える
+        let lower = name.to_ascii_lowercase();
+        for ext in COMPOUND_EXTENSIONS {
+            let dot = lower.len().saturating_sub(ext.len() + 1);
+            if dot > 0 && lower.ends_with(ext) && lower.as_bytes()[dot] == b'.' {
+                return Some((&name[..dot], &name[dot + 1..]));
+            }
+        }
+    }
+    name.rsplit_once('.').filter(|(base, ext)| !base.is_empty() && !ext.is_empty())
+}
+
+/// 全角英数字・記号（U+FF01〜U+FF5E）と全角スペースを半角に変換する。
+pub fn fullwidth_to_halfwidth(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            _ => c,
+        })
+        .collect()
+}
+
+/// ファイル名に使えない文字（`\ / : * ? " < > |` と制御文字）を `_` に置き換える。
+/// タグや日時など、外部の値を名前に埋め込むときに使う。
+pub fn replace_forbidden_chars(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if matches!(c, '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control() { '_' } else { c })
+        .collect()
+}
+
+/// 各フォルダ名・ファイル名の末尾の `.` と空白を取り除く（Windows は黙って取り除くため、前もってそろえる）。
+/// すべて `.` や空白の部分（`..` など）は空にせずそのまま残す。
+pub fn strip_trailing_dots_spaces(name: &str) -> String {
+    name.split_inclusive(['/', '\\'])
+        .map(|part| {
+            let (body, separator) = match part.char_indices().last() {
+                Some((i, c)) if c == '/' || c == '\\' => (&part[..i], &part[i..]),
+                _ => (part, ""),
+            };
+            let trimmed = body.trim_end_matches(['.', ' ']);
+            if trimmed.is_empty() {
+                part.to_string()
+            } else {
+                format!("{}{}", trimmed, separator)
+            }
+        })
+        .collect()
+}
+
+/// ラテン文字などのダイアクリティカルマーク（アクセント記号）だけを取り除く（例 `café` → `cafe`）。
+/// 1 文字ずつ NFD で分解し、結合用ダイアクリティカルマークを含む文字だけマークを除いて再合成する。
+/// マークを含まない文字は元のまま残すため、NFD の名前や CJK 互換漢字（U+FA30 など）は変わらない。
+/// かなの濁点・半濁点（U+3099/U+309A）や絵文字の結合文字は対象外のため、かな・漢字・絵文字はそのまま残る。
+pub fn strip_diacritics(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if is_diacritical_mark(c) {
+            // NFD の名前で独立している結合文字
+            continue;
+        }
+        let decomposed: String = c.to_string().nfd().collect();
+        if decomposed.chars().any(is_diacritical_mark) {
+            let base: String = decomposed.chars().filter(|c| !is_diacritical_mark(*c)).collect();
+            out.extend(base.nfc());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn is_diacritical_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'   // Combining Diacritical Marks
+            | '\u{1AB0}'..='\u{1AFF}' // Combining Diacritical Marks Extended
+            | '\u{1DC0}'..='\u{1DFF}' // Combining Diacritical Marks Supplement
+            | '\u{FE20}'..='\u{FE2F}' // Combining Half Marks
+    )
+}
+
+/// 新ファイル名を ASCII に音訳する。拡張子の区切り `.` は保持する。
+/// ASCII に対応する文字がない場合は `placeholder` に置き換え、空なら元の文字を残す。
+pub fn transliterate_to_ascii(name: &str, placeholder: &str, compound: bool) -> String {
+    match split_basename_ext(name, compound) {
+        Some((base, ext)) => format!(
+            "{}.{}",
+            transliterate_part(base, placeholder),
+            transliterate_part(ext, placeholder)
+        ),
+        _ => transliterate_part(name, placeholder),
+    }
+}
+
+fn transliterate_part(part: &str, placeholder: &str) -> String {
+    let mut out = String::with_capacity(part.len());
+    for c in part.chars() {
+        if c.is_ascii() {
+            out.push(c);
+            continue;
+        }
+        // 漢字などは "Bei " のように末尾空白付きで返るため trim する
+        match deunicode::deunicode_char(c).map(|s| s.trim()) {
+            Some(s) if !s.is_empty() && s != "[?]" => out.push_str(s),
+            _ if placeholder.is_empty() => out.push(c),
+            _ => out.push_str(placeholder),
+        }
+    }
+    out
+}
+
+/// 許可する文字の指定（例 `A-Za-z0-9._-`）を文字範囲の一覧にする。
+/// `a-z` のような `-` を挟んだ 2 文字は範囲、先頭・末尾の `-` はそのまま `-` を表す。
+pub fn parse_allowed_chars(spec: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if i + 2 < chars.len() && chars[i + 1] == '-' {
+            let (lo, hi) = (chars[i], chars[i + 2]);
+            ranges.push(if lo <= hi { (lo, hi) } else { (hi, lo) });
+            i += 3;
+        } else {
+            ranges.push((chars[i], chars[i]));
+            i += 1;
+        }
+    }
+    ranges
+}
+
+/// 許可されていない文字を `replacement` に置き換える。拡張子の区切り `.` は許可の有無にかかわらず残す。
+/// `collapse` なら置き換えが連続した箇所を 1 つにまとめる。
+pub fn restrict_to_allowed(
+    name: &str,
+    allowed: &[(char, char)],
+    replacement: &str,
+    collapse: bool,
+    compound: bool,
+) -> String {
+    let restrict = |part: &str| -> String {
+        let mut out = String::with_capacity(part.len());
+        let mut replaced_last = false;
+        for c in part.chars() {
+            if allowed.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) {
+                out.push(c);
+                replaced_last = false;
+            } else if !(collapse && replaced_last) {
+                out.push_str(replacement);
+                replaced_last = true;
+            }
+        }
+        out
+    };
+    match split_basename_ext(name, compound) {
+        Some((base, ext)) => format!("{}.{}", restrict(base), restrict(ext)),
+        _ => restrict(name),
+    }
+}
+
+/// 拡張子の前の部分の `position` 文字目の後に `text` を挿入する（位置は文字数で数える）。
+/// 負の位置は末尾から数え（`-1` なら最後の 1 文字の前）、範囲外は先頭・末尾に丸める。
+pub fn insert_at_char(name: &str, position: isize, text: &str, compound: bool) -> String {
+    let insert = |base: &str| -> String {
+        let len = base.chars().count() as isize;
+        let index = if position < 0 { len + position } else { position }.clamp(0, len) as usize;
+        let offset = base.char_indices().nth(index).map(|(i, _)| i).unwrap_or(base.len());
+        format!("{}{}{}", &base[..offset], text, &base[offset..])
+    };
+    match split_basename_ext(name, compound) {
+        Some((base, ext)) => format!("{}.{}", insert(base), ext),
+        _ => insert(name),
+    }
+}
+
+/// 拡張子の前の部分を区切り `delimiter` で 2 つに分け、前後を入れ替えて `joiner`（空なら `delimiter`）でつなぐ
+/// （例 `Lastname, Firstname` を `,` と ` ` で → `Firstname Lastname`）。分けた部分の前後の空白は除く。
+/// 区切りがない名前はそのまま。3 つ以上に分かれる名前は、`split_first` なら最初の区切りで分け、そうでなければそのまま。
+pub fn swap_parts(name: &str, delimiter: &str, joiner: &str, split_first: bool, compound: bool) -> String {
+    if delimiter.is_empty() {
+        return name.to_string();
+    }
+    let joiner = if joiner.is_empty() { delimiter } else { joiner };
+    let swap = |base: &str| -> String {
+        if !split_first && base.matches(delimiter).count() != 1 {
+            return base.to_string();
+        }
+        match base.split_once(delimiter) {
+            Some((left, right)) if !left.trim().is_empty() && !right.trim().is_empty() => {
+                format!("{}{}{}", right.trim(), joiner, left.trim())
+            }
+            _ => base.to_string(),
+        }
+    };
+    match split_basename_ext(name, compound) {
+        Some((base, ext)) => format!("{}.{}", swap(base), ext),
+        _ => swap(name),
+    }
+}
+
+/// 名前にある元の番号を取り出す。拡張子の前の部分の末尾の数字を優先し、なければ先頭の数字を番号とする
+/// （例 `song 5.mp3` → `5`、`05 song.mp3` → `05`）。番号と、番号の隣の区切り（空白・`_`・`-`）を除いた名前も返す
+/// （`song.mp3`）。フォルダを含む名前（パス全体を対象）では最後の部分だけを見る。
+/// 番号のない名前は None。番号を除くと空になる名前（`2024.txt`）は、除いた名前の代わりに元の名前を返す。
+pub fn split_existing_number(name: &str, compound: bool) -> Option<(String, String)> {
+    let (dir, file) = match name.rsplit_once('/') {
+        Some((dir, file)) => (format!("{}/", dir), file),
+        None => (String::new(), name),
+    };
+    let (base, ext) = match split_basename_ext(file, compound) {
+        Some((base, ext)) => (base, format!(".{}", ext)),
+        _ => (file, String::new()),
+    };
+    const SEPARATORS: [char; 3] = [' ', '_', '-'];
+    let trailing = base.len() - base.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (number, rest) = if trailing > 0 {
+        let start = base.len() - trailing;
+        (&base[start..], base[..start].trim_end_matches(SEPARATORS).to_string())
+    } else {
+        let leading = base.len() - base.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if leading == 0 {
+            return None;
+        }
+        (&base[..leading], base[leading..].trim_start_matches(SEPARATORS).to_string())
+    };
+    if rest.trim().is_empty() {
+        return Some((number.to_string(), name.to_string()));
+    }
+    Some((number.to_string(), format!("{}{}{}", dir, rest, ext)))
+}
+
+/// 拡張子の別名と統一先（左が別名、大文字小文字は区別しない）
+const EXTENSION_ALIASES: [(&str, &str); 6] = [
+    ("jpeg", "jpg"),
+    ("jpe", "jpg"),
+    ("htm", "html"),
+    ("tif", "tiff"),
+    ("yml", "yaml"),
+    ("mpeg", "mpg"),
+];
+
+/// 拡張子だけを正規化する（拡張子前の部分は変更しない）。
+/// `aliases` なら `jpeg` → `jpg` のような別名を統一し（元が大文字だけなら大文字で `JPG`）、
+/// `lowercase` なら小文字にそろえる。
+pub fn normalize_extension(name: &str, aliases: bool, lowercase: bool, compound: bool) -> String {
+    let Some((base, ext)) = split_basename_ext(name, compound) else {
+        return name.to_string();
+    };
+    let mut ext = ext.to_string();
+    if aliases {
+        let lower = ext.to_lowercase();
+        if let Some((_, canonical)) = EXTENSION_ALIASES.iter().find(|(alias, _)| *alias == lower) {
+            let all_upper = ext.chars().all(|c| !c.is_lowercase());
+            ext = if all_upper { canonical.to_uppercase() } else { canonical.to_string() };
+        }
+    }
+    if lowercase {
+        ext = ext.to_lowercase();
+    }
+    format!("{}.{}", base, ext)
+}
+
+/// 拡張子の前の部分の区切り文字 `from` を `to` にそろえる（空白には全角スペースも含む）。拡張子は変えない。
+/// `collapse` なら連続した区切りを 1 つにまとめ、`trim` なら先頭・末尾の区切りを除く（除くと空になる名前は除かない）。
+/// フォルダを含む名前（パス全体を対象）では最後の部分だけを変える。
+pub fn normalize_separators(
+    name: &str,
+    from: Separator,
+    to: Separator,
+    collapse: bool,
+    trim: bool,
+    compound: bool,
+) -> String {
+    let (dir, file) = match name.rsplit_once('/') {
+        Some((dir, file)) => (format!("{}/", dir), file),
+        None => (String::new(), name),
+    };
+    let (base, ext) = match split_basename_ext(file, compound) {
+        Some((base, ext)) => (base, format!(".{}", ext)),
+        None => (file, String::new()),
+    };
+    let target = to.as_char();
+    let mut out = String::with_capacity(base.len());
+    for c in base.chars() {
+        let c = if from.matches(c) { target } else { c };
+        if collapse && c == target && out.ends_with(target) {
+            continue;
+        }
+        out.push(c);
+    }
+    if trim && !out.trim_matches(target).is_empty() {
+        out = out.trim_matches(target).to_string();
+    }
+    format!("{}{}{}", dir, out, ext)
+}
+
+/// 新ファイル名を指定の Unicode 正規化形式（NFC/NFD）に揃える。
+pub fn normalize_unicode(name: &str, form: UnicodeNormalization) -> String {
+    match form {
+        UnicodeNormalization::None => name.to_string(),
+        UnicodeNormalization::Nfc => name.nfc().collect(),
+        UnicodeNormalization::Nfd => name.nfd().collect(),
+    }
+}
+
+/// ファイル名を最大 `max_chars` 文字（バイトではなく文字数）に収める。
+/// 拡張子と区切りの `.` は常に残し、足りない分は拡張子前の部分を切り詰める。
+pub fn truncate_name(name: &str, max_chars: usize, ellipsis: bool, compound: bool) -> String {
+    match split_basename_ext(name, compound) {
+        Some((base, ext)) => truncate_base(base, &format!(".{}", ext), max_chars, ellipsis),
+        _ => truncate_base(name, "", max_chars, ellipsis),
+    }
+}
+
+/// `base + tail` が `max_chars` 文字に収まるよう `base` 側だけを切り詰める。
+/// `tail`（拡張子や連番）は切らず、`base` は最低 1 文字残す。
+pub fn truncate_base(base: &str, tail: &str, max_chars: usize, ellipsis: bool) -> String {
+    let base_len = base.chars().count();
+    let tail_len = tail.chars().count();
+    if max_chars == 0 || base_len + tail_len <= max_chars {
+        return format!("{}{}", base, tail);
+    }
+    let budget = max_chars.saturating_sub(tail_len).max(1);
+    let truncated: String = if ellipsis && budget >= 2 {
+        let kept: String = base.chars().take(budget - 1).collect();
+        format!("{}…", kept)
+    } else {
+        base.chars().take(budget).collect()
+    };
+    format!("{}{}", truncated, tail)
+}
+
+/// 新ファイル名中の連番トークン `{n}` / `{n:桁数}` / `{n:auto}` を `n` で置き換える。
+/// `{n:auto}` は最後の番号 `last` の桁数に合わせてゼロ埋めする（例 100 件なら 001〜100）。
+pub fn expand_sequence(name: &str, n: usize, last: usize) -> String {
+    expand_counter(name, 'n', n, last)
+}
+
+/// `{<token>}` / `{<token>:桁数}` / `{<token>:auto}` を番号 `value`（桁数に満たなければゼロ埋め）で置き換える。
+/// `auto` の桁数は `last` の桁数。
+pub fn expand_counter(text: &str, token: char, value: usize, last: usize) -> String {
+    let open = format!("{{{}", token);
+    if !text.contains(&open) {
+        return text.to_string();
+    }
+    let pattern = format!(r"\{{{}(?::(\d+|auto))?\}}", regex::escape(&token.to_string()));
+    let Ok(re) = Regex::new(&pattern) else {
+        return text.to_string();
+    };
+    re.replace_all(text, |caps: &regex::Captures| {
+        let width = match caps.get(1).map(|m| m.as_str()) {
+            Some("auto") => last.max(value).to_string().len(),
+            Some(digits) => digits.parse::<usize>().unwrap_or(0),
+            None => 0,
+        };
+        format!("{:0width$}", value, width = width)
+    })
+    .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_basename_and_extension() {
+        assert_eq!(split_basename_ext("photo.jpg", false), Some(("photo", "jpg")));
+        // 最後の `.` で分ける
+        assert_eq!(split_basename_ext("a.b.c", false), Some(("a.b", "c")));
+        assert_eq!(split_basename_ext("a.b.c", true), Some(("a.b", "c")));
+    }
+
+    #[test]
+    fn names_without_extension() {
+        for compound in [false, true] {
+            assert_eq!(split_basename_ext(".gitignore", compound), None);
+            assert_eq!(split_basename_ext("noext", compound), None);
+            assert_eq!(split_basename_ext("trailing.", compound), None);
+            assert_eq!(split_basename_ext("", compound), None);
+        }
+    }
+
+    #[test]
+    fn compound_extensions_only_when_enabled() {
+        assert_eq!(split_basename_ext("a.tar.gz", false), Some(("a.tar", "gz")));
+        assert_eq!(split_basename_ext("a.tar.gz", true), Some(("a", "tar.gz")));
+        // 大文字でもまとめ、元の大文字小文字のまま返す
+        assert_eq!(split_basename_ext("Backup.TAR.ZST", true), Some(("Backup", "TAR.ZST")));
+        // `.tar.gz` だけの名前は拡張子の前の部分がないため、最後の `.` で分ける
+        assert_eq!(split_basename_ext(".tar.gz", true), Some((".tar", "gz")));
+    }
+
+    #[test]
+    fn strips_only_diacritics() {
+        assert_eq!(strip_diacritics("caf\u{E9} na\u{EF}ve.txt"), "cafe naive.txt");
+        assert_eq!(strip_diacritics("Ti\u{1EBF}ng Vi\u{1EC7}t"), "Tieng Viet");
+        // NFD で書かれたアクセントも取り除き、それ以外の文字は分解したまま残す
+        assert_eq!(strip_diacritics("cafe\u{301} \u{304B}\u{3099}"), "cafe \u{304B}\u{3099}");
+    }
+
+    #[test]
+    fn leaves_names_without_diacritics_untouched() {
+        // NFD のかな・CJK 互換漢字・絵文字は正規化しない
+        for name in ["\u{304B}\u{3099}\u{304F}.txt", "\u{FA30}.txt", "\u{304C}.txt", "\u{1F468}\u{200D}\u{1F469}.png"] {
+            assert_eq!(strip_diacritics(name), name);
+        }
+    }
+
+    #[test]
+    fn sequence_tokens_expand() {
+        assert_eq!(expand_sequence("a_{n}.txt", 3, 3), "a_3.txt");
+        assert_eq!(expand_sequence("a_{n:3}.txt", 3, 3), "a_003.txt");
+        assert_eq!(expand_sequence("a_{n:auto}.txt", 7, 100), "a_007.txt");
+    }
+}
@@ -1,17 +1,34 @@
+use crate::config::{open_in_file_manager, Settings};
 use crate::controller::AppController;
-use crate::preview::{load_files, update_preview};
+use crate::history::{list_batches, log_path};
+use crate::lookup::LookupTable;
+use crate::events::{
+    APPLY_ROW, FILES_CHANGED, MOVE_PREVIEW_ROW, PREVIEW_REQUEST, REMOVE_EXCLUDE_ITEM, REMOVE_EXTRA_DIR, REMOVE_RULE, REVEAL_FILE, TOGGLE_EXCLUDE_ITEM,
+    TOGGLE_EXTENSION, TOGGLE_TARGET_DETAIL, UNDO_BATCH,
+};
+use crate::preview::{
+    add_exclude_items, copy_original_names, copy_preview_to_clipboard, describe_excludes, describe_size_filter, exclude_chips, paste_new_names,
+    select_all_visible, start_scan,
+};
+use crate::duplicates::{cancel_duplicate_check, deselect_duplicates, start_duplicate_check};
+use crate::name_date::describe_date_formats;
 use crate::rename::apply_changes;
-use crate::state::{AppState, FileEntry};
-use crate::widgets::{HighlightedLabel, ProgressBar};
-use druid::widget::{Button, Checkbox, Flex, Label, List, Scroll, TextBox};
+use crate::theme::{apply_theme, ColorOverrides};
+use crate::state::{AppState, CopyFormat, ExcludeChip, ExtensionChip, FileEntry, LinkKind, LogBatch, ReadOnlyHandling, RenameFailure, RenameResult, ReplaceCount, ReplaceRule, Separator, UnicodeNormalization};
+use crate::widgets::{HighlightedLabel, ProgressBar, RowMenuController, RowSelectController, SplitPanel};
+use druid::widget::{Button, Checkbox, Either, Flex, Label, List, RadioGroup, Scroll, SizedBox, TextBox};
 use druid::widget::CrossAxisAlignment;
 use druid::widget::LineBreaking;
-use druid::{Env, TextAlignment, Widget, WidgetExt};
+use druid::text::ParseFormatter;
+use druid::{theme, Env, LensExt, TextAlignment, Widget, WidgetExt};
 use druid::piet::Color;
 use std::path::Path;
+use std::sync::Arc;
 
 pub fn build_ui() -> impl Widget<AppState> {
     const LABEL_WIDTH: f64 = 120.0;
+    // 適用結果に表示するフォルダごとの件数の最大数
+    const MAX_FOLDERS_IN_PANEL: usize = 20;
 
     // 左上側: ディレクトリ／検索／置換／除外設定
     let directory_row = Flex::row()
@@ -19,12 +36,41 @@ pub fn build_ui() -> impl Widget<AppState> {
         .with_spacer(5.0)
         .with_flex_child(TextBox::new().lens(AppState::selected_dir).fix_height(30.0), 1.0)
         .with_spacer(5.0)
-        .with_child(Button::new("参照").on_click(|_ctx, data: &mut AppState, _env| {
+        .with_child(Button::new("参照").on_click(|ctx, data: &mut AppState, _env| {
             if let Some(path) = rfd::FileDialog::new().pick_folder() {
                 data.selected_dir = path.to_string_lossy().to_string();
-                load_files(data);
+                start_scan(data, ctx.get_external_handle(), false);
             }
-        }));
+        }))
+        .with_spacer(5.0)
+        .with_child(Button::new("フォルダを追加").on_click(|_ctx, data: &mut AppState, _env| {
+            if let Some(paths) = rfd::FileDialog::new().pick_folders() {
+                for path in paths {
+                    let path = path.to_string_lossy().to_string();
+                    if path != data.selected_dir && !data.extra_dirs.contains(&path) {
+                        data.extra_dirs.push_back(path);
+                    }
+                }
+            }
+        }))
+        .with_spacer(5.0)
+        .with_child(Button::new("再読み込み").on_click(|ctx, _data: &mut AppState, _env| {
+            ctx.submit_command(FILES_CHANGED);
+        }))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("自動").lens(AppState::auto_reload));
+
+    // 追加の対象フォルダ（同じ条件でまとめて変更する）
+    let extra_dirs_list = List::new(|| {
+        Flex::row()
+            .with_spacer(LABEL_WIDTH + 5.0)
+            .with_flex_child(Label::dynamic(|dir: &String, _env| format!("＋ {}", dir)).expand_width(), 1.0)
+            .with_spacer(5.0)
+            .with_child(Button::new("外す").on_click(|ctx, dir: &mut String, _env| {
+                ctx.submit_command(REMOVE_EXTRA_DIR.with(dir.clone()));
+            }))
+    })
+    .lens(AppState::extra_dirs);
 
     let search_row = Flex::row()
         .with_child(Label::new("検索:").fix_width(LABEL_WIDTH))
@@ -36,58 +82,911 @@ pub fn build_ui() -> impl Widget<AppState> {
         .with_spacer(5.0)
         .with_flex_child(TextBox::new().lens(AppState::replace_pattern).fix_height(30.0), 1.0);
 
+    let rules_list = List::new(|| {
+        Flex::row()
+            .with_spacer(LABEL_WIDTH + 5.0)
+            .with_flex_child(TextBox::new().with_placeholder("検索").lens(ReplaceRule::search), 1.0)
+            .with_child(Label::new("→"))
+            .with_flex_child(TextBox::new().with_placeholder("置換").lens(ReplaceRule::replace), 1.0)
+            .with_spacer(5.0)
+            .with_child(Checkbox::new("正規表現").lens(ReplaceRule::use_regex))
+            .with_spacer(5.0)
+            .with_child(Checkbox::new("大小区別").lens(ReplaceRule::case_sensitive))
+            .with_spacer(5.0)
+            .with_child(Button::new("削除").on_click(|ctx, rule: &mut ReplaceRule, _env| {
+                ctx.submit_command(REMOVE_RULE.with(rule.id));
+            }))
+    })
+    .with_spacing(4.0)
+    .lens(AppState::extra_rules);
+
+    let add_rule_row = Flex::row()
+        .with_spacer(LABEL_WIDTH + 5.0)
+        .with_child(Button::new("ルールを追加").on_click(|_ctx, data: &mut AppState, _env| {
+            let next_id = data.extra_rules.iter().map(|r| r.id + 1).max().unwrap_or(0);
+            data.extra_rules.push_back(ReplaceRule::new(next_id));
+        }));
+
+    // 置換表（旧→新の対応表）: 多数の置換を 1 ファイルで管理する
+    let lookup_row = Flex::row()
+        .with_child(Label::new("置換表:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_child(Button::new("読み込み").on_click(|_ctx, data: &mut AppState, _env| {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("CSV / TSV", &["csv", "tsv", "txt"])
+                .pick_file()
+            {
+                match LookupTable::load(&path) {
+                    Ok(table) => {
+                        data.set_status(table.summary());
+                        data.lookup_table = Some(Arc::new(table));
+                    }
+                    Err(e) => data.set_status(e),
+                }
+            }
+        }))
+        .with_spacer(5.0)
+        .with_child(
+            Button::new("解除")
+                .on_click(|_ctx, data: &mut AppState, _env| data.lookup_table = None)
+                .disabled_if(|data: &AppState, _| data.lookup_table.is_none()),
+        )
+        .with_spacer(10.0)
+        .with_child(Label::new(|data: &AppState, _env: &Env| match &data.lookup_table {
+            Some(table) => format!("{}（{} 件）", table.source, table.len()),
+            None => "なし".to_string(),
+        }));
+
+    let copy_row = Flex::row()
+        .with_child(Checkbox::new("別フォルダにコピー").lens(AppState::copy_to_target).fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("コピー先フォルダ")
+                .lens(AppState::target_dir)
+                .fix_height(30.0),
+            1.0,
+        )
+        .with_spacer(5.0)
+        .with_child(Button::new("参照").on_click(|_ctx, data: &mut AppState, _env| {
+            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                data.target_dir = path.to_string_lossy().to_string();
+                data.copy_to_target = true;
+            }
+        }));
+
+    // 元のファイルを残し、新しい名前のリンクを作る（コピー先の指定があればそこに作る）
+    let link_row = Flex::row()
+        .with_child(Label::new("元を残してリンク:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_child(
+            RadioGroup::row(vec![
+                ("しない", LinkKind::None),
+                ("ハードリンク", LinkKind::Hard),
+                ("シンボリックリンク", LinkKind::Symbolic),
+            ])
+            .lens(AppState::link_kind),
+        );
+
+    let prefix_suffix_row = Flex::row()
+        .with_child(Label::new("接頭辞:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_flex_child(TextBox::new().lens(AppState::prefix).fix_height(30.0), 1.0)
+        .with_spacer(5.0)
+        .with_child(Label::new("接尾辞:"))
+        .with_spacer(5.0)
+        .with_flex_child(TextBox::new().lens(AppState::suffix).fix_height(30.0), 1.0);
+
+    // 拡張子の前の部分の途中（N 文字目の後）に挿入する
+    let insert_row = Flex::row()
+        .with_child(Label::new("挿入位置:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::insert_position)
+                .fix_width(50.0),
+        )
+        .with_spacer(5.0)
+        .with_child(Label::new("文字目の後に:"))
+        .with_spacer(5.0)
+        .with_flex_child(TextBox::new().lens(AppState::insert_text).fix_height(30.0), 1.0);
+
+    // 区切りで 2 つに分けて前後を入れ替える（例 `Lastname, Firstname` → `Firstname Lastname`）
+    let swap_row = Flex::row()
+        .with_child(Label::new("入れ替え:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("区切りで入れ替え").lens(AppState::swap_parts))
+        .with_spacer(5.0)
+        .with_child(
+            Flex::row()
+                .with_child(Label::new("区切り:"))
+                .with_spacer(5.0)
+                .with_child(TextBox::new().lens(AppState::swap_delimiter).fix_width(60.0))
+                .with_spacer(5.0)
+                .with_child(Label::new("つなぎ:"))
+                .with_spacer(5.0)
+                .with_child(TextBox::new().with_placeholder("区切りと同じ").lens(AppState::swap_joiner).fix_width(90.0))
+                .with_spacer(5.0)
+                .with_child(Checkbox::new("3 つ以上は最初の区切りで分ける").lens(AppState::swap_split_first))
+                .disabled_if(|data: &AppState, _| !data.swap_parts),
+        );
+
+    // 名前の日付の書き換え（例 元 %Y%m%d → 新 %Y-%m-%d で `20240105_a.txt` → `2024-01-05_a.txt`）
+    let date_row = Flex::row()
+        .with_child(Label::new("日付:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("名前の日付を書き換える").lens(AppState::reformat_date))
+        .with_spacer(5.0)
+        .with_child(
+            Flex::row()
+                .with_child(Label::new("元の書式:"))
+                .with_spacer(5.0)
+                .with_child(TextBox::new().with_placeholder("%Y%m%d").lens(AppState::date_input_format).fix_width(110.0))
+                .with_spacer(5.0)
+                .with_child(Label::new("新しい書式:"))
+                .with_spacer(5.0)
+                .with_child(TextBox::new().with_placeholder("%Y-%m-%d").lens(AppState::date_output_format).fix_width(110.0))
+                .disabled_if(|data: &AppState, _| !data.reformat_date),
+        )
+        .with_spacer(10.0)
+        .with_flex_child(
+            Label::new(|data: &AppState, _env: &Env| {
+                if data.reformat_date {
+                    describe_date_formats(&data.date_input_format, &data.date_output_format)
+                } else {
+                    String::new()
+                }
+            })
+            .with_text_color(Color::rgb8(200, 0, 0))
+            .with_text_size(10.0)
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .expand_width(),
+            1.0,
+        );
+
+    // 式: ファイルごとに評価し、結果を新しい名前の元にする（検索/置換などはその結果に適用）
+    let script_row = Flex::row()
+        .with_child(Label::new("式:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("式で名前を決める").lens(AppState::use_script))
+        .with_spacer(5.0)
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("例 stem + \"_\" + pad(index, 3) + \".\" + ext")
+                .lens(AppState::script_expr)
+                .disabled_if(|data: &AppState, _| !data.use_script)
+                .fix_height(30.0),
+            1.0,
+        );
+
     let exclude_row = Flex::row()
         .with_child(Label::new("除外:").fix_width(LABEL_WIDTH))
         .with_spacer(5.0)
-        .with_flex_child(TextBox::new().lens(AppState::exclude_pattern).fix_height(30.0), 1.0);
+        .with_flex_child(TextBox::new().lens(AppState::exclude_pattern).fix_height(30.0), 1.0)
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("隠しファイルを除外").lens(AppState::exclude_hidden));
+
+    // ファイルサイズの範囲（範囲外のファイルは除外する）
+    let size_row = Flex::row()
+        .with_child(Label::new("サイズ:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_child(TextBox::new().with_placeholder("最小 例 1KB").lens(AppState::min_size).fix_width(110.0))
+        .with_spacer(5.0)
+        .with_child(Label::new("〜"))
+        .with_spacer(5.0)
+        .with_child(TextBox::new().with_placeholder("最大 例 10MB").lens(AppState::max_size).fix_width(110.0))
+        .with_spacer(10.0)
+        .with_flex_child(
+            Label::new(|data: &AppState, _env: &Env| describe_size_filter(&data.min_size, &data.max_size))
+                .with_text_color(Color::rgb8(200, 0, 0))
+                .with_text_size(10.0)
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .expand_width(),
+            1.0,
+        );
+
+    // 読み込んだフォルダにある拡張子の切り替え（OFF にした拡張子のファイルは一覧・適用の対象から外す）
+    let extension_chip_row = Flex::row()
+        .with_child(Label::new("拡張子:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_flex_child(
+            Scroll::new(
+                List::new(|| {
+                    Button::new(|chip: &ExtensionChip, _env: &Env| {
+                        let ext = if chip.ext.is_empty() { "（拡張子なし）" } else { chip.ext.as_str() };
+                        format!("{} {} ({})", if chip.enabled { "☑" } else { "☐" }, ext, chip.count)
+                    })
+                    .on_click(|ctx, chip: &mut ExtensionChip, _env| {
+                        ctx.submit_command(TOGGLE_EXTENSION.with(chip.ext.clone()));
+                    })
+                    .padding((0.0, 0.0, 5.0, 0.0))
+                })
+                .horizontal(),
+            )
+            .horizontal()
+            .lens(AppState::extension_chips),
+            1.0,
+        );
+
+    // 除外欄の各項目をどう解釈したかと、無効な項目を入力中に表示する（読み込みのステータスで消えない）
+    let exclude_feedback = Flex::column()
+        .with_child(Either::new(
+            |pattern: &String, _env| !describe_excludes(pattern).0.is_empty(),
+            Label::new(|pattern: &String, _env: &Env| describe_excludes(pattern).0)
+                .with_text_color(Color::grey(0.6))
+                .with_text_size(10.0)
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .expand_width(),
+            SizedBox::empty(),
+        ))
+        .with_child(Either::new(
+            |pattern: &String, _env| !describe_excludes(pattern).1.is_empty(),
+            Label::new(|pattern: &String, _env: &Env| describe_excludes(pattern).1)
+                .with_text_color(Color::rgb8(200, 0, 0))
+                .with_text_size(10.0)
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .expand_width(),
+            SizedBox::empty(),
+        ))
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .padding((LABEL_WIDTH + 5.0, 0.0, 0.0, 0.0))
+        .lens(AppState::exclude_pattern);
+
+    // 除外欄の項目ごとのチップ（種類を表示し、クリックで ON/OFF、× で削除）と項目の追加欄
+    let exclude_chip_row = Flex::row()
+        .with_spacer(LABEL_WIDTH + 5.0)
+        .with_flex_child(
+            Scroll::new(
+                List::new(|| {
+                    Flex::row()
+                        .with_child(
+                            Button::new(|chip: &ExcludeChip, _env: &Env| {
+                                format!("{} {}: {}", if chip.enabled { "☑" } else { "☐" }, chip.kind, chip.raw)
+                            })
+                            .on_click(|ctx, chip: &mut ExcludeChip, _env| {
+                                ctx.submit_command(TOGGLE_EXCLUDE_ITEM.with(chip.raw.clone()));
+                            }),
+                        )
+                        .with_child(Button::new("×").on_click(|ctx, chip: &mut ExcludeChip, _env| {
+                            ctx.submit_command(REMOVE_EXCLUDE_ITEM.with(chip.raw.clone()));
+                        }))
+                        .padding((0.0, 0.0, 8.0, 0.0))
+                })
+                .horizontal(),
+            )
+            .horizontal()
+            // チップは除外欄から作るため、書き戻しはしない（切り替え・削除はコマンドで除外欄を書き換える）
+            .lens(druid::lens::Map::new(
+                |data: &AppState| exclude_chips(&data.exclude_pattern, &data.disabled_excludes),
+                |_: &mut AppState, _: druid::im::Vector<ExcludeChip>| {},
+            )),
+            1.0,
+        )
+        .with_spacer(5.0)
+        .with_child(TextBox::new().with_placeholder("項目を追加 例 *.tmp").lens(AppState::exclude_draft).fix_width(140.0))
+        .with_spacer(5.0)
+        .with_child(Button::new("追加").on_click(|ctx, data: &mut AppState, _env| {
+            if add_exclude_items(data) {
+                ctx.submit_command(PREVIEW_REQUEST.with(()));
+            }
+        }));
+
+    // 直近の読み込みで、除外欄の項目ごとに何件除外したか（開いたときだけ表示）
+    let exclude_breakdown = Either::new(
+        |data: &AppState, _env| data.exclude_breakdown.is_empty(),
+        SizedBox::empty(),
+        Flex::column()
+            .with_child(
+                Label::dynamic(|data: &AppState, _env| {
+                    if data.show_exclude_breakdown { "▾ 除外の内訳".to_string() } else { "▸ 除外の内訳".to_string() }
+                })
+                .with_text_color(Color::rgb8(80, 120, 200))
+                .with_text_size(10.0)
+                .on_click(|_ctx, data: &mut AppState, _env| {
+                    data.show_exclude_breakdown = !data.show_exclude_breakdown;
+                }),
+            )
+            .with_child(Either::new(
+                |data: &AppState, _env| data.show_exclude_breakdown,
+                Label::new(|data: &AppState, _env: &Env| data.exclude_breakdown.clone())
+                    .with_text_color(Color::grey(0.6))
+                    .with_text_size(10.0)
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .expand_width(),
+                SizedBox::empty(),
+            ))
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .padding((LABEL_WIDTH + 5.0, 0.0, 0.0, 0.0)),
+    );
+
+    let test_row = Flex::row()
+        .with_child(Label::new("テスト:").fix_width(LABEL_WIDTH))
+        .with_spacer(5.0)
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("サンプルのファイル名")
+                .lens(AppState::test_input)
+                .fix_height(30.0),
+            1.0,
+        )
+        .with_spacer(5.0)
+        .with_flex_child(
+            Label::new(|data: &String, _env: &Env| format!("→ {}", data))
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .lens(AppState::test_output),
+            1.0,
+        );
+
+    // 名前を変えずにファイルを探すだけの検索のみモード（切り替えは明示的に行う）
+    let mode_row = Flex::row()
+        .with_child(Label::new("モード:"))
+        .with_spacer(5.0)
+        .with_child(
+            RadioGroup::row(vec![("名前の変更", false), ("検索のみ（名前は変えない）", true)])
+                .lens(AppState::search_only),
+        )
+        .with_spacer(10.0)
+        .with_child(
+            Checkbox::new("あいまい検索")
+                .lens(AppState::fuzzy_search)
+                .disabled_if(|data: &AppState, _| !data.search_only),
+        )
+        .with_spacer(5.0)
+        .with_child(Label::new("違う文字数:"))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::fuzzy_distance)
+                .fix_width(40.0)
+                .disabled_if(|data: &AppState, _| !data.search_only || !data.fuzzy_search),
+        );
 
     let left_col = Flex::column()
         .with_child(directory_row)
+        .with_child(extra_dirs_list)
+        .with_spacer(8.0)
+        .with_child(mode_row)
         .with_spacer(8.0)
         .with_child(search_row)
         .with_spacer(8.0)
         .with_child(replace_row)
+        .with_spacer(4.0)
+        .with_child(rules_list)
+        .with_spacer(4.0)
+        .with_child(add_rule_row)
+        .with_spacer(4.0)
+        .with_child(lookup_row)
+        .with_spacer(8.0)
+        .with_child(prefix_suffix_row)
+        .with_spacer(4.0)
+        .with_child(insert_row)
+        .with_spacer(4.0)
+        .with_child(swap_row)
+        .with_spacer(4.0)
+        .with_child(date_row)
+        .with_spacer(4.0)
+        .with_child(script_row)
+        .with_spacer(8.0)
+        .with_child(exclude_row)
+        .with_child(exclude_feedback)
+        .with_child(exclude_chip_row.padding((0.0, 2.0, 0.0, 2.0)))
+        .with_child(size_row)
+        .with_child(exclude_breakdown)
+        .with_child(Either::new(
+            |data: &AppState, _env| data.extension_chips.is_empty(),
+            SizedBox::empty(),
+            extension_chip_row.padding((0.0, 4.0, 0.0, 0.0)),
+        ))
+        .with_spacer(8.0)
+        .with_child(copy_row)
+        .with_spacer(8.0)
+        .with_child(link_row)
         .with_spacer(8.0)
-        .with_child(exclude_row);
+        .with_child(test_row);
 
     let checkbox_row_top = Flex::row()
         .with_child(Checkbox::new("大文字小文字を区別").lens(AppState::case_sensitive))
         .with_spacer(10.0)
-        .with_child(Checkbox::new("サブフォルダを含める").lens(AppState::include_subdirectories));
+        .with_child(Checkbox::new("正規表現").lens(AppState::use_regex))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("完全一致").lens(AppState::whole_match))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("Unicode").lens(AppState::regex_unicode).disabled_if(|data: &AppState, _| !data.use_regex))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new(". を改行に一致").lens(AppState::regex_dot_all).disabled_if(|data: &AppState, _| !data.use_regex))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("サブフォルダを含める").lens(AppState::include_subdirectories))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("シンボリックリンクをたどる").lens(AppState::follow_symlinks))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("音楽タグを使う").lens(AppState::use_audio_tags));
+
+    // 階層の上限: サブフォルダをどこまで読み込むか
+    // フラット化: サブフォルダ内のファイルを対象フォルダ直下へ集める（置換・接頭辞の {dir} でフォルダ名を付けられる）
+    let flatten_row = Flex::row()
+        .with_child(Label::new("階層の上限:"))
+        .with_spacer(5.0)
+        .with_child(
+            // 0 は無制限（None）
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::max_depth.map(|depth| depth.unwrap_or(0), |depth, value: usize| *depth = (value > 0).then_some(value)))
+                .fix_width(40.0),
+        )
+        .with_spacer(5.0)
+        .with_child(Label::new("(1 で直下のみ、0 で無制限)").with_text_color(Color::grey(0.6)))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("フラット化（直下へ移動）").lens(AppState::flatten))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("空になったフォルダを削除").lens(AppState::remove_empty_dirs))
+        .disabled_if(|data: &AppState, _| !data.include_subdirectories);
+
+    // 振り分け: 置換結果の / をフォルダの区切りとして、サブフォルダへ移動する（例 Replace `${1}/$0` で年ごとのフォルダへ）
+    let sort_row = Flex::row()
+        .with_child(Checkbox::new("/ でサブフォルダへ振り分け").lens(AppState::sort_into_subfolders))
+        .with_spacer(10.0)
+        // パス全体: 対象フォルダからの相対パス（例 2023/旅行/IMG_0001.jpg）に検索/置換を適用する
+        .with_child(Checkbox::new("パス全体を対象").lens(AppState::match_full_path));
 
     let checkbox_row_bottom = Flex::row()
-        .with_child(Checkbox::new("重複時に連番を付与").lens(AppState::auto_number_on_conflict));
+        .with_child(Checkbox::new("大小無視のファイルシステム").lens(AppState::case_insensitive_fs))
+        .with_spacer(10.0)
+        // Windows は名前の末尾の . と空白を黙って取り除くため、前もってそろえる（他の OS では使わない）
+        .with_child(
+            Checkbox::new("末尾の . ・空白を除去")
+                .lens(AppState::strip_trailing_dots)
+                .disabled_if(|_, _| !cfg!(windows)),
+        )
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("確認ダイアログを表示").lens(AppState::confirm_before_apply))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("適用前にバックアップ").lens(AppState::backup_before_apply))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("EXIFがなければ更新日時").lens(AppState::exif_fallback_mtime));
+
+    let auto_number_row = Flex::row()
+        .with_child(Checkbox::new("重複時に連番を付与").lens(AppState::auto_number_on_conflict))
+        .with_spacer(5.0)
+        .with_child(Label::new("書式:"))
+        .with_spacer(5.0)
+        .with_child(TextBox::new().lens(AppState::auto_number_template).fix_width(70.0))
+        .with_spacer(5.0)
+        .with_child(Label::new("開始:"))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::auto_number_start)
+                .fix_width(40.0),
+        );
+
+    let transform_row = Flex::row()
+        .with_child(Checkbox::new("コピー接尾辞を除去").lens(AppState::strip_copy_suffixes))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("全角→半角").lens(AppState::fullwidth_to_halfwidth))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("アクセント除去").lens(AppState::strip_diacritics))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("ASCII化").lens(AppState::transliterate_ascii))
+        .with_spacer(5.0)
+        .with_child(Label::new("代替文字:"))
+        .with_spacer(5.0)
+        .with_child(TextBox::new().lens(AppState::ascii_placeholder).fix_width(40.0));
+
+    // 区切り文字の正規化: 空白・_・- のどれかを別の区切りにそろえる（拡張子は変えない）
+    let separators = || {
+        vec![("空白", Separator::Space), ("_", Separator::Underscore), ("-", Separator::Hyphen)]
+    };
+    let separator_row = Flex::row()
+        .with_child(Checkbox::new("区切り文字の正規化:").lens(AppState::normalize_separators))
+        .with_spacer(5.0)
+        .with_child(
+            Flex::row()
+                .with_child(RadioGroup::row(separators()).lens(AppState::separator_from))
+                .with_child(Label::new("→"))
+                .with_child(RadioGroup::row(separators()).lens(AppState::separator_to))
+                .with_spacer(10.0)
+                .with_child(Checkbox::new("連続をまとめる").lens(AppState::separator_collapse))
+                .with_spacer(10.0)
+                .with_child(Checkbox::new("両端を除く").lens(AppState::separator_trim))
+                .disabled_if(|data: &AppState, _| !data.normalize_separators),
+        );
+
+    // 連番の振り直し: 元の番号（ep01, ep02, ep04…）を無視して並び順で 01..N にする
+    let renumber_row = Flex::row()
+        .with_child(Checkbox::new("連番を振り直す").lens(AppState::renumber))
+        .with_spacer(5.0)
+        .with_child(Label::new("桁数:"))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::renumber_digits)
+                .fix_width(40.0),
+        )
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("名前全体を番号にする").lens(AppState::renumber_whole))
+        .with_spacer(10.0)
+        .with_child(Label::new("連番 {n} の開始:"))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::sequence_start)
+                .fix_width(50.0),
+        )
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("逆順で番号付け").lens(AppState::reverse_numbering));
+
+    // 元の番号（末尾、なければ先頭の数字）: 名前から除く・{num} で別の位置に使う（例 接頭辞 `Track {num:2} - `）
+    let existing_number_row = Flex::row()
+        .with_child(Checkbox::new("元の番号を名前から除く").lens(AppState::strip_existing_number))
+        .with_spacer(10.0)
+        .with_child(
+            Label::new("{num} / {num:2} で元の番号を使えます（末尾の数字を優先、なければ先頭）")
+                .with_text_color(Color::grey(0.6)),
+        );
+
+    // 拡張子の正規化（拡張子前の部分は変えない）
+    let extension_row = Flex::row()
+        .with_child(Label::new("拡張子を正規化:"))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("小文字にする").lens(AppState::lowercase_extension))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("別名を統一（jpeg→jpg, htm→html など）").lens(AppState::normalize_extension_aliases))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new(".tar.gz などを 1 つの拡張子とする").lens(AppState::compound_extensions));
+
+    // 許可した文字だけに制限する（ASCII化と違い、許可外の文字は音訳せずに置き換える）
+    let restrict_row = Flex::row()
+        .with_child(Checkbox::new("許可文字のみ:").lens(AppState::restrict_chars))
+        .with_spacer(5.0)
+        .with_child(TextBox::new().lens(AppState::allowed_chars).fix_width(140.0))
+        .with_spacer(5.0)
+        .with_child(Label::new("置換:"))
+        .with_spacer(5.0)
+        .with_child(TextBox::new().lens(AppState::restrict_replacement).fix_width(40.0))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("連続はまとめる").lens(AppState::collapse_replacements));
+
+    let replace_count_row = Flex::row()
+        .with_child(Label::new("置換する一致:"))
+        .with_spacer(5.0)
+        .with_child(
+            RadioGroup::row(vec![
+                ("すべて", ReplaceCount::All),
+                ("最初", ReplaceCount::First),
+                ("最後", ReplaceCount::Last),
+                ("N番目", ReplaceCount::Nth),
+            ])
+            .lens(AppState::replace_count),
+        )
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::replace_nth)
+                .fix_width(40.0),
+        );
+
+    let normalization_row = Flex::row()
+        .with_child(Label::new("Unicode正規化:"))
+        .with_spacer(5.0)
+        .with_child(
+            RadioGroup::row(vec![
+                ("なし", UnicodeNormalization::None),
+                ("NFC", UnicodeNormalization::Nfc),
+                ("NFD", UnicodeNormalization::Nfd),
+            ])
+            .lens(AppState::unicode_normalization),
+        );
+
+    let max_length_row = Flex::row()
+        .with_child(Label::new("最大文字数:"))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::max_name_length)
+                .fix_width(60.0),
+        )
+        .with_spacer(5.0)
+        .with_child(Label::new("(0 で無制限)").with_text_color(Color::grey(0.6)))
+        .with_spacer(10.0)
+        .with_child(Checkbox::new("省略記号…を付ける").lens(AppState::truncate_with_ellipsis));
+
+    // 大量の変更を分けて適用する: プレビューの N〜M 番目だけを適用する
+    let range_row = Flex::row()
+        .with_child(Checkbox::new("範囲を限定:").lens(AppState::limit_range))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::range_start)
+                .fix_width(50.0),
+        )
+        .with_spacer(5.0)
+        .with_child(Label::new("〜"))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::range_end)
+                .fix_width(50.0),
+        )
+        .with_spacer(5.0)
+        .with_child(Label::new("番目 (終了 0 で最後まで)").with_text_color(Color::grey(0.6)));
+
+    // 遅いドライブ（ネットワーク・HDD）向けに、適用時に同時に処理する数を抑える
+    let threads_row = Flex::row()
+        .with_child(Label::new("同時に処理する数:"))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::rename_threads)
+                .fix_width(50.0),
+        )
+        .with_spacer(5.0)
+        .with_child(Label::new("(1 で 1 件ずつ。ネットワークドライブでは小さめに)").with_text_color(Color::grey(0.6)))
+        .with_spacer(15.0)
+        .with_child(Label::new("確認する件数:"))
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .update_data_while_editing(true)
+                .lens(AppState::large_batch_threshold)
+                .fix_width(60.0),
+        )
+        .with_spacer(5.0)
+        .with_child(Label::new("件以上 (0 で確認しない)").with_text_color(Color::grey(0.6)));
+
+    // 読み取り専用のファイルの移動・リネーム（コピー・リンクの作成では元に触れないため使わない）
+    let read_only_row = Flex::row()
+        .with_child(Label::new("読み取り専用のファイル:"))
+        .with_spacer(5.0)
+        .with_child(
+            RadioGroup::row(vec![
+                ("そのまま試す", ReadOnlyHandling::Attempt),
+                ("飛ばす", ReadOnlyHandling::Skip),
+                ("解除して変更", ReadOnlyHandling::Unlock),
+                ("解除して変更し元に戻す", ReadOnlyHandling::UnlockAndRestore),
+            ])
+            .lens(AppState::read_only_handling),
+        );
 
     let checkbox_row = Flex::column()
         .with_child(checkbox_row_top)
         .with_spacer(6.0)
+        .with_child(flatten_row)
+        .with_child(sort_row)
+        .with_spacer(6.0)
+        .with_child(replace_count_row)
+        .with_spacer(6.0)
         .with_child(checkbox_row_bottom)
+        .with_spacer(6.0)
+        .with_child(auto_number_row)
+        .with_spacer(6.0)
+        .with_child(renumber_row)
+        .with_child(existing_number_row)
+        .with_spacer(6.0)
+        .with_child(transform_row)
+        .with_spacer(6.0)
+        .with_child(separator_row)
+        .with_spacer(6.0)
+        .with_child(extension_row)
+        .with_spacer(6.0)
+        .with_child(restrict_row)
+        .with_spacer(6.0)
+        .with_child(normalization_row)
+        .with_spacer(6.0)
+        .with_child(max_length_row)
+        .with_spacer(6.0)
+        .with_child(range_row)
+        .with_spacer(6.0)
+        .with_child(threads_row)
+        .with_child(read_only_row)
         .cross_axis_alignment(CrossAxisAlignment::Start);
 
     let button_row = Flex::row()
         .with_child(
-            Button::new("プレビュー")
-                .on_click(|_ctx, data: &mut AppState, _env| update_preview(data))
-                .fix_size(120.0, 40.0),
+            Button::new("プレビュー (Ctrl+Enter)")
+                .on_click(|ctx, _data: &mut AppState, _env| ctx.submit_command(PREVIEW_REQUEST.with(())))
+                .fix_size(180.0, 40.0),
         )
         .with_spacer(10.0)
         .with_child(
-            Button::new("変更を適用")
+            Button::new("変更を適用 (Ctrl+Shift+Enter)")
                 .on_click(|ctx, data: &mut AppState, _env| apply_changes(ctx, data))
-                .fix_size(120.0, 40.0),
+                .disabled_if(|data: &AppState, _| data.search_only)
+                .fix_size(220.0, 40.0),
         );
 
+    // 直近の適用で失敗したファイル（あるときだけ表示）
+    let failures_list = List::new(|| {
+        Label::new(|item: &RenameFailure, _env: &Env| {
+            format!("{} → {}: {}", item.original_path, item.new_name, item.reason)
+        })
+        .with_text_size(11.0)
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .expand_width()
+    })
+    .lens(AppState::rename_failures);
+    let failures_panel = Either::new(
+        |data: &AppState, _env| !data.rename_failures.is_empty(),
+        Flex::column()
+            .with_child(
+                Label::new(|data: &AppState, _env: &Env| format!("失敗したファイル ({})", data.rename_failures.len()))
+                    .with_text_color(Color::rgb8(200, 0, 0)),
+            )
+            .with_spacer(5.0)
+            .with_child(Scroll::new(failures_list).vertical().fix_height(100.0))
+            .cross_axis_alignment(CrossAxisAlignment::Start),
+        SizedBox::empty(),
+    );
+
+    // 直近の適用の結果（ファイルごとの元の名前 → 新しい名前。次の適用まで残す）
+    let results_list = List::new(|| {
+        Label::new(|item: &RenameResult, _env: &Env| {
+            if item.error.is_empty() {
+                format!("✓ {} → {}", item.original_path, item.new_path)
+            } else {
+                format!("✗ {} → {}: {}", item.original_path, item.new_path, item.error)
+            }
+        })
+        .with_text_size(11.0)
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .env_scope(|env, item: &RenameResult| {
+            if !item.error.is_empty() {
+                env.set(druid::theme::TEXT_COLOR, Color::rgb8(200, 0, 0));
+            }
+        })
+        .expand_width()
+    })
+    .lens(AppState::rename_results);
+    let results_panel = Either::new(
+        |data: &AppState, _env| !data.rename_results.is_empty(),
+        Flex::column()
+            .with_child(
+                Flex::row()
+                    .with_child(Label::new(|data: &AppState, _env: &Env| {
+                        let failed = data.rename_results.iter().filter(|r| !r.error.is_empty()).count();
+                        format!("適用結果 (成功 {} / 失敗 {})", data.rename_results.len() - failed, failed)
+                    }))
+                    .with_spacer(10.0)
+                    .with_child(Button::new("閉じる").on_click(|_ctx, data: &mut AppState, _env| {
+                        data.rename_results.clear();
+                        data.rename_folder_breakdown.clear();
+                    })),
+            )
+            .with_child(Either::new(
+                |data: &AppState, _env| data.rename_folder_breakdown.is_empty(),
+                SizedBox::empty(),
+                Label::new(|data: &AppState, _env: &Env| {
+                    // フォルダが多い場合は一覧を押し出さないよう先頭だけ（エラーのあったフォルダが先に並ぶ）
+                    let lines: Vec<&str> = data.rename_folder_breakdown.lines().collect();
+                    let mut text = format!("フォルダごと:\n{}", lines.iter().take(MAX_FOLDERS_IN_PANEL).copied().collect::<Vec<_>>().join("\n"));
+                    if lines.len() > MAX_FOLDERS_IN_PANEL {
+                        text.push_str(&format!("\n…ほか {} フォルダ", lines.len() - MAX_FOLDERS_IN_PANEL));
+                    }
+                    text
+                })
+                    .with_text_size(11.0)
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .expand_width(),
+            ))
+            .with_spacer(5.0)
+            .with_child(Scroll::new(results_list).vertical().fix_height(150.0))
+            .cross_axis_alignment(CrossAxisAlignment::Start),
+        SizedBox::empty(),
+    );
+
+    // 適用履歴（ログ）と、バッチ単位の取り消し
+    let history_buttons = Flex::row()
+        .with_child(Button::new(|data: &AppState, _env: &Env| {
+            if data.show_history { "履歴を閉じる".to_string() } else { "履歴を表示".to_string() }
+        }).on_click(|_ctx, data: &mut AppState, _env| {
+            data.show_history = !data.show_history;
+            if data.show_history {
+                data.history = list_batches().into_iter().collect();
+            }
+        }))
+        .with_spacer(10.0)
+        .with_child(Button::new("ログの場所を開く").on_click(|_ctx, data: &mut AppState, _env| {
+            match log_path().and_then(|p| p.parent().map(|d| d.to_path_buf())) {
+                Some(dir) => {
+                    if let Err(e) = open_in_file_manager(&dir) {
+                        data.set_status(format!("フォルダを開けませんでした: {}", e));
+                    }
+                }
+                None => data.set_status("ログの保存先が見つかりません"),
+            }
+        }));
+    let history_list = List::new(|| {
+        Flex::row()
+            .with_flex_child(
+                Label::new(|item: &LogBatch, _env: &Env| {
+                    let op = match item.op.as_str() {
+                        "copy" => "コピー",
+                        "hardlink" => "ハードリンク",
+                        "symlink" => "シンボリックリンク",
+                        _ => "リネーム",
+                    };
+                    format!("{} {} {} 件 (エラー {}) {}", item.timestamp, op, item.count, item.errors, item.folder)
+                })
+                .with_text_size(11.0)
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .expand_width(),
+                1.0,
+            )
+            .with_spacer(5.0)
+            .with_child(Button::new("元に戻す").on_click(|ctx, item: &mut LogBatch, _env| {
+                ctx.submit_command(UNDO_BATCH.with(item.batch_id.clone()));
+            }))
+    })
+    .lens(AppState::history);
+    let history_panel = Either::new(
+        |data: &AppState, _env| data.show_history,
+        Scroll::new(history_list).vertical().fix_height(120.0),
+        SizedBox::empty(),
+    );
+
+    // ステータスの履歴（時刻付き、新しい順）。最新のものは上の大きい表示にも出る
+    let status_log = Scroll::new(
+        List::new(|| {
+            Label::new(|line: &String, _env: &Env| line.clone())
+                .with_text_size(11.0)
+                .with_text_color(Color::grey(0.6))
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .expand_width()
+        })
+        .lens(AppState::status_log),
+    )
+    .vertical()
+    .fix_height(70.0);
+
     let right_col = Flex::column()
         .with_child(checkbox_row)
         .with_spacer(20.0)
         .with_child(button_row)
         .with_spacer(10.0)
         .with_child(Label::new(|data: &String, _env: &Env| data.clone()).lens(AppState::status_message))
+        .with_spacer(5.0)
+        .with_child(status_log)
+        .with_spacer(10.0)
+        .with_child(ProgressBar::default())
         .with_spacer(10.0)
-        .with_child(ProgressBar);
+        .with_child(failures_panel)
+        .with_spacer(10.0)
+        .with_child(results_panel)
+        .with_spacer(10.0)
+        .with_child(history_buttons)
+        .with_spacer(5.0)
+        .with_child(history_panel);
 
-    let top_panel = Flex::column()
+    // ハイライトなどの色は設定ファイル（settings.ini）で上書きできる
+    let color_overrides = ColorOverrides::load(&Settings::load());
+    let title_row = Flex::row()
         .with_child(Label::new("ファイル名一括変更").with_text_size(24.0))
+        .with_flex_spacer(1.0)
+        .with_child(Checkbox::new("ダークテーマ").lens(AppState::dark_mode));
+
+    let top_panel = Flex::column()
+        .with_child(title_row)
         .with_spacer(10.0)
         .with_child(Flex::row().with_flex_child(left_col, 1.0).with_spacer(20.0).with_flex_child(right_col, 1.0));
 
@@ -101,30 +1000,170 @@ pub fn build_ui() -> impl Widget<AppState> {
                     .with_line_break_mode(LineBreaking::WordWrap)
                     .expand_width(),
             )
+            .with_child(duplicate_label())
             .cross_axis_alignment(CrossAxisAlignment::Start)
+            .controller(RowMenuController)
     })
     .lens(AppState::files);
 
     let preview_list = List::new(|| {
-        Flex::column()
+        // 行ごとの ↑/↓ で並べ替え、連番 {n} の割り当て順を調整する
+        let row_buttons = Flex::row()
+            .with_child(Button::new("↑").on_click(|ctx, item: &mut FileEntry, _env| {
+                ctx.submit_command(MOVE_PREVIEW_ROW.with((item.original_path.clone(), -1)));
+            }))
+            .with_child(Button::new("↓").on_click(|ctx, item: &mut FileEntry, _env| {
+                ctx.submit_command(MOVE_PREVIEW_ROW.with((item.original_path.clone(), 1)));
+            }))
+            // この行だけをすぐに適用する（衝突の判定は一括の適用と同じ）
+            .with_child(Button::new("適用").on_click(|ctx, item: &mut FileEntry, _env| {
+                ctx.submit_command(APPLY_ROW.with(item.original_path.clone()));
+            }));
+        let names = Flex::column()
             .with_child(HighlightedLabel::new(true).expand_width())
             .with_child(
+                Flex::row()
+                    .with_flex_child(
+                        Label::new(|item: &FileEntry, _env: &Env| {
+                            if item.search_only {
+                                return item.original_path.clone();
+                            }
+                            let path = Path::new(&item.original_path);
+                            let original_name = path
+                                .file_name()
+                                .map(|s| s.to_string_lossy().to_string())
+                                .unwrap_or_default();
+                            format!("変更前: {}", original_name)
+                        })
+                        .with_text_color(Color::grey(0.6))
+                        .with_text_size(10.0)
+                        .with_line_break_mode(LineBreaking::WordWrap)
+                        .expand_width(),
+                        1.0,
+                    )
+                    // 変更の理由と適用後のフルパス（サブフォルダ・フラット化・コピー先を含めた移動先）を開いて確かめる
+                    .with_child(
+                        Label::dynamic(|item: &FileEntry, _env| {
+                            if item.show_target { "▾ 詳細".to_string() } else { "▸ 詳細".to_string() }
+                        })
+                        .with_text_color(Color::rgb8(80, 120, 200))
+                        .with_text_size(10.0)
+                        .on_click(|ctx, item: &mut FileEntry, _env| {
+                            ctx.submit_command(TOGGLE_TARGET_DETAIL.with(item.original_path.clone()));
+                        }),
+                    ),
+            )
+            .with_child(Either::new(
+                |item: &FileEntry, _env| item.show_target,
                 Label::new(|item: &FileEntry, _env: &Env| {
-                    let path = Path::new(&item.original_path);
-                    let original_name = path
-                        .file_name()
-                        .map(|s| s.to_string_lossy().to_string())
-                        .unwrap_or_default();
-                    format!("変更前: {}", original_name)
+                    let mut reasons = item.change_reason.clone();
+                    if item.auto_numbered {
+                        if !reasons.is_empty() {
+                            reasons.push_str(" → ");
+                        }
+                        reasons.push_str("重複時の連番");
+                    }
+                    if reasons.is_empty() {
+                        // フラット化で名前を変えずに移動する行など
+                        reasons = "（名前は変わりません）".to_string();
+                    }
+                    let target = if item.target_path.is_empty() {
+                        "（決められません。コピー先フォルダを確認してください）"
+                    } else {
+                        &item.target_path
+                    };
+                    format!("変更の理由: {}\n移動先: {}", reasons, target)
                 })
                 .with_text_color(Color::grey(0.6))
                 .with_text_size(10.0)
                 .with_line_break_mode(LineBreaking::WordWrap)
                 .expand_width(),
+                SizedBox::empty(),
+            ))
+            .with_child(duplicate_label())
+            .with_child(Either::new(
+                |item: &FileEntry, _env| !item.in_range,
+                Label::new("（適用範囲外: 変更しません）")
+                    .with_text_color(Color::grey(0.6))
+                    .with_text_size(10.0),
+                SizedBox::empty(),
+            ))
+            .with_child(Either::new(
+                |item: &FileEntry, _env| !item.path_error.is_empty(),
+                Label::new(|item: &FileEntry, _env: &Env| format!("※{}", item.path_error))
+                    .with_text_color(Color::rgb8(200, 0, 0))
+                    .with_text_size(10.0)
+                    .with_line_break_mode(LineBreaking::WordWrap),
+                SizedBox::empty(),
+            ))
+            .with_child(Either::new(
+                |item: &FileEntry, _env| !item.name_note.is_empty(),
+                Label::new(|item: &FileEntry, _env: &Env| {
+                    format!("※末尾の . ・空白を除きました（Windows では付けられません）: 「{}」", item.name_note)
+                })
+                .with_text_color(Color::rgb8(200, 120, 0))
+                .with_text_size(10.0)
+                .with_line_break_mode(LineBreaking::WordWrap),
+                SizedBox::empty(),
+            ))
+            .with_child(Either::new(
+                |item: &FileEntry, _env| !item.hash_error.is_empty(),
+                Label::new(|item: &FileEntry, _env: &Env| format!("※内容を読めないため {{hash}} を空にしました: {}", item.hash_error))
+                    .with_text_color(Color::rgb8(200, 120, 0))
+                    .with_text_size(10.0)
+                    .with_line_break_mode(LineBreaking::WordWrap),
+                SizedBox::empty(),
+            ))
+            .with_child(Either::new(
+                |item: &FileEntry, _env| item.read_only,
+                Label::new("※読み取り専用のファイルです（扱いは「読み取り専用のファイル」の設定に従います）")
+                    .with_text_color(Color::grey(0.6))
+                    .with_text_size(10.0),
+                SizedBox::empty(),
+            ))
+            .with_child(Either::new(
+                |item: &FileEntry, _env| item.conflicts_existing,
+                Label::new("※同名のファイルが既にあります（このままでは適用できません）")
+                    .with_text_color(Color::rgb8(200, 0, 0))
+                    .with_text_size(10.0),
+                SizedBox::empty(),
+            ))
+            .with_child(Either::new(
+                |item: &FileEntry, _env| item.name_collision,
+                Label::new("※他のファイルと新しい名前が重なります（このままでは適用できません）")
+                    .with_text_color(Color::rgb8(200, 0, 0))
+                    .with_text_size(10.0),
+                SizedBox::empty(),
+            ))
+            .cross_axis_alignment(CrossAxisAlignment::Start);
+        // フォルダの見出し（サブフォルダを含める場合、フォルダの先頭行だけ）
+        let header = Either::new(
+            |item: &FileEntry, _env| !item.group_header.is_empty(),
+            Label::new(|item: &FileEntry, _env: &Env| format!("フォルダ: {}", item.group_header))
+                .with_text_color(Color::rgb8(80, 120, 200))
+                .padding((0.0, 6.0, 0.0, 2.0)),
+            SizedBox::empty(),
+        );
+        Flex::column()
+            .with_child(header)
+            .with_child(
+                Flex::row()
+                    .with_child(Checkbox::new("").lens(FileEntry::selected).controller(RowSelectController))
+                    .with_child(Either::new(
+                        |item: &FileEntry, _env| item.search_only,
+                        // 検索のみモードでは並べ替え・適用の代わりに、見つけたファイルのフォルダを開く
+                        Button::new("フォルダを開く").on_click(|ctx, item: &mut FileEntry, _env| {
+                            ctx.submit_command(REVEAL_FILE.with(item.original_path.clone()));
+                        }),
+                        row_buttons,
+                    ))
+                    .with_spacer(5.0)
+                    .with_flex_child(names, 1.0),
             )
             .cross_axis_alignment(CrossAxisAlignment::Start)
+            .controller(RowMenuController)
     })
-    .lens(AppState::preview_files);
+    .lens(AppState::visible_preview);
 
     let original_scroll = Scroll::new(original_list).vertical();
     let preview_scroll = Scroll::new(preview_list).vertical();
@@ -135,14 +1174,99 @@ pub fn build_ui() -> impl Widget<AppState> {
         .with_flex_child(original_scroll, 1.0);
 
     let preview_panel = Flex::column()
-        .with_child(Label::new(|data: &AppState, _env: &Env| format!("プレビュー ({})", data.preview_files.len())).with_text_alignment(TextAlignment::Start))
+        .with_child(
+            Flex::row()
+                .with_child(Label::new(|data: &AppState, _env: &Env| {
+                    let (heading, note) =
+                        if data.search_only { ("検索結果", "") } else { ("プレビュー", " 変更があるファイルのみ") };
+                    if data.visible_preview.len() == data.preview_files.len() {
+                        format!("{} ({}){}", heading, data.preview_files.len(), note)
+                    } else {
+                        format!("{} ({} / {}){}", heading, data.visible_preview.len(), data.preview_files.len(), note)
+                    }
+                }).with_text_alignment(TextAlignment::Start))
+                .with_spacer(10.0)
+                .with_flex_child(
+                    TextBox::new()
+                        .with_placeholder("絞り込み（元/新の名前）")
+                        .lens(AppState::preview_filter)
+                        .expand_width(),
+                    1.0,
+                )
+                .with_spacer(5.0)
+                .with_child(Checkbox::new("問題のある行を先頭に").lens(AppState::problems_first)),
+        )
+        .with_spacer(5.0)
+        .with_child(
+            // チェック: クリックで 1 行、Shift+クリックで前回クリックした行からの範囲を切り替える
+            Flex::row()
+                .with_child(Button::new("すべて選択").on_click(|_ctx, data: &mut AppState, _env| select_all_visible(data, true)))
+                .with_spacer(5.0)
+                .with_child(Button::new("選択解除").on_click(|_ctx, data: &mut AppState, _env| select_all_visible(data, false)))
+                .with_spacer(10.0)
+                .with_child(Button::new("クリップボードへコピー").on_click(|_ctx, data: &mut AppState, _env| {
+                    copy_preview_to_clipboard(data)
+                }))
+                .with_spacer(5.0)
+                .with_child(
+                    RadioGroup::row(vec![("→ 区切り", CopyFormat::Arrow), ("タブ区切り", CopyFormat::Tab)])
+                        .lens(AppState::copy_format),
+                )
+                .with_spacer(10.0)
+                .with_child(
+                    Label::new("Shift+クリックで範囲選択")
+                        .with_text_color(Color::grey(0.6))
+                        .with_text_size(11.0),
+                ),
+        )
+        .with_spacer(5.0)
+        .with_child(
+            // 外部のエディタで名前をまとめて書き換える: 元の名前をコピーし、編集した同じ行数の名前を貼り付ける
+            Flex::row()
+                .with_child(Button::new("元の名前をコピー").on_click(|_ctx, data: &mut AppState, _env| {
+                    copy_original_names(data)
+                }))
+                .with_spacer(5.0)
+                .with_child(Button::new("新しい名前を貼り付け").on_click(|ctx, data: &mut AppState, _env| {
+                    if paste_new_names(data) {
+                        ctx.submit_command(PREVIEW_REQUEST.with(()));
+                    }
+                }))
+                .with_spacer(5.0)
+                .with_child(Either::new(
+                    |data: &AppState, _env| !data.pasted_names.is_empty(),
+                    Button::new(|data: &AppState, _env: &Env| format!("貼り付けを解除 ({} 件)", data.pasted_names.len()))
+                        .on_click(|ctx, data: &mut AppState, _env| {
+                            data.pasted_names.clear();
+                            data.set_status("貼り付けた名前の使用をやめました");
+                            ctx.submit_command(PREVIEW_REQUEST.with(()));
+                        }),
+                    SizedBox::empty(),
+                )),
+        )
+        .with_spacer(5.0)
+        .with_child(
+            // 内容が同一のファイルの検出（重いため押したときだけ、中止可能）
+            Flex::row()
+                .with_child(Either::new(
+                    |data: &AppState, _env| data.duplicate_check_in_progress,
+                    Button::new("検出を中止").on_click(|_ctx, data: &mut AppState, _env| cancel_duplicate_check(data)),
+                    Button::new("重複ファイル検出").on_click(|ctx, data: &mut AppState, _env| {
+                        start_duplicate_check(data, ctx.get_external_handle())
+                    }),
+                ))
+                .with_spacer(5.0)
+                .with_child(
+                    Button::new("重複の 2 件目以降を外す")
+                        .on_click(|_ctx, data: &mut AppState, _env| deselect_duplicates(data))
+                        .disabled_if(|data: &AppState, _| !data.files.iter().any(|f| f.duplicate_group > 0)),
+                ),
+        )
         .with_spacer(5.0)
         .with_flex_child(preview_scroll, 1.0);
 
-    let main_panel = Flex::row()
-        .with_flex_child(original_panel, 1.0)
-        .with_spacer(10.0)
-        .with_flex_child(preview_panel, 1.0);
+    // 間の仕切りをドラッグして左右の幅を調整できる
+    let main_panel = SplitPanel::new(original_panel, preview_panel);
 
     druid::widget::Either::new(|_data: &AppState, _env| true, Flex::column()
         .with_child(top_panel)
@@ -150,7 +1274,18 @@ pub fn build_ui() -> impl Widget<AppState> {
         .with_flex_child(main_panel, 1.0)
         .padding(10.0)
         .expand()
+        .background(theme::WINDOW_BACKGROUND_COLOR)
+        .env_scope(move |env, data: &AppState| apply_theme(env, data.dark_mode, &color_overrides))
         .controller(AppController), Flex::column())
 }
 
-
+/// 重複ファイル検出で内容が同一と分かった行の印（同じ番号のファイル同士が同一内容）
+fn duplicate_label() -> impl Widget<FileEntry> {
+    Either::new(
+        |item: &FileEntry, _env| item.duplicate_group > 0,
+        Label::new(|item: &FileEntry, _env: &Env| format!("※内容が同一のファイルがあります（重複 #{}）", item.duplicate_group))
+            .with_text_color(Color::rgb8(220, 140, 0))
+            .with_text_size(10.0),
+        SizedBox::empty(),
+    )
+}
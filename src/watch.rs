@@ -0,0 +1,66 @@
+// 対象フォルダの変更の監視（自動再読み込み）
+//
+// notify で対象フォルダを監視し、他のアプリでファイルが追加・削除・変更されたら FILES_CHANGED を送る。
+// まとめてコピーした場合などはイベントが続けて届くため、最後のイベントから少し待って 1 回だけ通知する。
+
+use crate::events::FILES_CHANGED;
+use druid::{ExtEventSink, Target};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::debug;
+
+/// 最後のイベントからこの時間だけ変更がなければ通知する
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// 監視中のフォルダ（フォルダ, サブフォルダも含むか）と監視。監視を破棄すると通知スレッドも終わる。
+static WATCHING: Mutex<Option<(Vec<String>, bool, RecommendedWatcher)>> = Mutex::new(None);
+
+/// `dirs`（対象フォルダと追加フォルダ）の監視を始める（同じ条件で監視中なら何もしない）。以前の監視は止める。
+pub fn watch_dir(dirs: &[String], recursive: bool, sink: ExtEventSink) -> Result<(), String> {
+    let mut watching = WATCHING.lock().unwrap_or_else(|e| e.into_inner());
+    if matches!(watching.as_ref(), Some((d, r, _)) if d == dirs && *r == recursive) {
+        return Ok(());
+    }
+    *watching = None;
+    let (tx, rx) = mpsc::channel::<()>();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        // 読み取りだけのアクセスは一覧に影響しない
+        if let Ok(event) = res {
+            if !matches!(event.kind, EventKind::Access(_)) {
+                let _ = tx.send(());
+            }
+        }
+    })
+    .map_err(|e| e.to_string())?;
+    let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    for dir in dirs {
+        watcher.watch(Path::new(dir), mode).map_err(|e| format!("{}: {}", dir, e))?;
+    }
+    std::thread::spawn(move || {
+        // 監視を破棄すると送信側がなくなり、recv が失敗して終わる
+        while rx.recv().is_ok() {
+            loop {
+                match rx.recv_timeout(SETTLE_DELAY) {
+                    Ok(()) => continue,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            let _ = sink.submit_command(FILES_CHANGED, (), Target::Global);
+        }
+    });
+    debug!(?dirs, recursive, "watch_started");
+    *watching = Some((dirs.to_vec(), recursive, watcher));
+    Ok(())
+}
+
+/// 監視を止める。
+pub fn stop_watching() {
+    let mut watching = WATCHING.lock().unwrap_or_else(|e| e.into_inner());
+    if watching.take().is_some() {
+        debug!("watch_stopped");
+    }
+}
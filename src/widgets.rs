@@ -1,13 +1,22 @@
-use crate::state::FileEntry;
+use crate::config::save_setting;
+use crate::events::{EXCLUDE_FILE, OPEN_FILE, REVEAL_FILE, SELECT_PREVIEW_ROW};
+use crate::theme::{HIGHLIGHT_BACKGROUND_COLOR, HIGHLIGHT_TEXT_COLOR, PROGRESS_FILL_COLOR, PROGRESS_TEXT_COLOR};
+use crate::state::{AppState, FileEntry};
+use crate::preview::build_rule_regex;
+use crate::fuzzy::fuzzy_find;
 use druid::kurbo::{Point, Rect, Size};
 use druid::piet::{TextLayoutBuilder, TextLayout};
 use druid::piet::Text as PietText;
 use druid::piet::Color;
-use druid::{Env, Event, EventCtx, LifeCycle, LifeCycleCtx, LayoutCtx, PaintCtx, UpdateCtx, Widget};
+use druid::{
+    Cursor, Env, Event, EventCtx, LifeCycle, LifeCycleCtx, LayoutCtx, Menu, MenuItem, PaintCtx, TimerToken, UpdateCtx, Widget,
+    WidgetPod,
+};
 use druid::RenderContext;
+use druid::widget::Controller;
 use druid::Data;
-use regex::RegexBuilder;
 use std::path::Path;
+use std::time::Duration;
 
 /// ハイライト表示対応のカスタムラベルウィジェット
 pub struct HighlightedLabel {
@@ -53,71 +62,26 @@ impl Widget<FileEntry> for HighlightedLabel {
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &FileEntry, env: &Env) {
-        let current_text = if self.is_replacement {
-            data.new_name.clone()
-        } else {
-            let path = Path::new(&data.original_path);
-            path.file_name()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_default()
-        };
-
-        if data.search_pattern.is_empty() {
-            let text_layout = ctx
-                .text()
-                .new_text_layout(current_text)
-                .font(druid::piet::FontFamily::SYSTEM_UI, env.get(druid::theme::TEXT_SIZE_NORMAL))
-                .text_color(env.get(druid::theme::TEXT_COLOR))
-                .build()
-                .unwrap();
+        let original_name = Path::new(&data.original_path)
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
 
-            ctx.draw_text(&text_layout, Point::ORIGIN);
-            return;
-        }
-
-        let (highlight_text, is_highlight) = if self.is_replacement {
-            (data.replace_pattern.clone(), !data.replace_pattern.is_empty())
+        // 置換後は元の名前との差分（挿入・変更された部分）をハイライトする（検索のみモードでは名前が変わらないため一致を示す）
+        let spans = if self.is_replacement && !data.search_only {
+            diff_spans(&original_name, &data.new_name)
         } else {
-            (data.search_pattern.clone(), true)
+            search_spans(&original_name, data)
         };
-
-        if !is_highlight || highlight_text.is_empty() {
-            let text_layout = ctx
-                .text()
-                .new_text_layout(current_text)
-                .font(druid::piet::FontFamily::SYSTEM_UI, env.get(druid::theme::TEXT_SIZE_NORMAL))
-                .text_color(env.get(druid::theme::TEXT_COLOR))
-                .build()
-                .unwrap();
-
-            ctx.draw_text(&text_layout, Point::ORIGIN);
-            return;
-        }
-
-        let escaped = regex::escape(&highlight_text);
-        let mut rb = RegexBuilder::new(&escaped);
-        rb.case_insensitive(!data.case_sensitive);
-        let re = match rb.build() {
-            Ok(r) => r,
-            Err(_) => {
-                let text_layout = ctx
-                    .text()
-                    .new_text_layout(current_text)
-                    .font(druid::piet::FontFamily::SYSTEM_UI, env.get(druid::theme::TEXT_SIZE_NORMAL))
-                    .text_color(env.get(druid::theme::TEXT_COLOR))
-                    .build()
-                    .unwrap();
-                ctx.draw_text(&text_layout, Point::ORIGIN);
-                return;
-            }
+        let current_text = if self.is_replacement {
+            data.new_name.clone()
+        } else {
+            original_name
         };
 
         let mut current_x = 0.0;
         let mut last = 0usize;
-        for m in re.find_iter(&current_text) {
-            let start = m.start();
-            let end = m.end();
-
+        for (start, end) in spans {
             if start > last {
                 let normal = &current_text[last..start];
                 let normal_layout = ctx
@@ -136,12 +100,12 @@ impl Widget<FileEntry> for HighlightedLabel {
                 .text()
                 .new_text_layout(seg.to_string())
                 .font(druid::piet::FontFamily::SYSTEM_UI, env.get(druid::theme::TEXT_SIZE_NORMAL))
-                .text_color(Color::rgb8(0, 0, 0))
+                .text_color(env.get(HIGHLIGHT_TEXT_COLOR))
                 .build()
                 .unwrap();
             let hl_size = hl_layout.size();
             let rect = Rect::new(current_x, 0.0, current_x + hl_size.width, hl_size.height);
-            ctx.fill(rect, &Color::rgb8(255, 255, 0));
+            ctx.fill(rect, &env.get(HIGHLIGHT_BACKGROUND_COLOR));
             ctx.draw_text(&hl_layout, Point::new(current_x, 0.0));
             current_x += hl_size.width;
 
@@ -162,16 +126,210 @@ impl Widget<FileEntry> for HighlightedLabel {
     }
 }
 
-/// シンプルな進捗バーウィジェット
-pub struct ProgressBar;
+/// 元のファイル名中で検索パターンに一致する範囲（バイト位置）を返す。
+fn search_spans(text: &str, data: &FileEntry) -> Vec<(usize, usize)> {
+    if data.search_pattern.is_empty() {
+        return Vec::new();
+    }
+    if let Some(max_distance) = data.fuzzy_max_distance {
+        return fuzzy_find(text, &data.search_pattern, max_distance, data.case_sensitive)
+            .map(|m| (m.start, m.end))
+            .filter(|(start, end)| start < end)
+            .into_iter()
+            .collect();
+    }
+    let re = match build_rule_regex(
+        &data.search_pattern,
+        data.use_regex,
+        data.case_sensitive,
+        data.regex_unicode,
+        data.regex_dot_all,
+        data.whole_match,
+    ) {
+        Ok(re) => re,
+        Err(_) => return Vec::new(),
+    };
+    // 最初/最後/N番目のみ置換する設定では、実際に置換される一致だけをハイライトする
+    let total = re.find_iter(text).count();
+    re.find_iter(text)
+        .enumerate()
+        .filter(|(i, _)| data.search_only || data.replace_count.selects(data.replace_nth, *i, total))
+        .map(|(_, m)| (m.start(), m.end()))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+/// 元の名前と新しい名前を文字単位で比較（最長共通部分列）し、
+/// 新しい名前の中で挿入・変更された範囲（バイト位置）を返す。
+fn diff_spans(original: &str, new: &str) -> Vec<(usize, usize)> {
+    if original == new {
+        return Vec::new();
+    }
+    let a: Vec<char> = original.chars().collect();
+    let b: Vec<(usize, char)> = new.char_indices().collect();
+    let (n, m) = (a.len(), b.len());
+    // lcs[i][j]: a[i..] と b[j..] の最長共通部分列の長さ
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j].1 {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    let (mut i, mut j) = (0usize, 0usize);
+    while j < m {
+        if i < n && a[i] == b[j].1 {
+            i += 1;
+            j += 1;
+        } else if i < n && lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            let (start, c) = b[j];
+            let end = start + c.len_utf8();
+            match spans.last_mut() {
+                Some(last) if last.1 == start => last.1 = end,
+                _ => spans.push((start, end)),
+            }
+            j += 1;
+        }
+    }
+    spans
+}
+
+/// プレビュー行のチェックボックスに付け、クリックを SELECT_PREVIEW_ROW コマンドにする。
+/// チェックボックス自体では切り替えず（表示用の一覧は絞り込み後のコピーのため）、
+/// Shift+クリックなら前回クリックした行からの範囲選択として AppController に処理させる。
+pub struct RowSelectController;
+
+impl<W: Widget<FileEntry>> Controller<FileEntry, W> for RowSelectController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut FileEntry, env: &Env) {
+        match event {
+            Event::MouseDown(mouse) if mouse.button.is_left() => {
+                ctx.submit_command(SELECT_PREVIEW_ROW.with((data.original_path.clone(), mouse.mods.shift())));
+                ctx.set_handled();
+            }
+            Event::MouseUp(_) => ctx.set_handled(),
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
+
+/// ファイル一覧・プレビューの行に付け、右クリックでメニュー（エクスプローラーで表示・開く・除外）を出す。
+/// 各項目は元パスを持つコマンドとして AppController が処理する。
+pub struct RowMenuController;
+
+impl<W: Widget<FileEntry>> Controller<FileEntry, W> for RowMenuController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut FileEntry, env: &Env) {
+        if let Event::MouseDown(mouse) = event {
+            if mouse.button.is_right() {
+                let path = data.original_path.clone();
+                let menu = Menu::<AppState>::empty()
+                    .entry(MenuItem::new("エクスプローラーで表示").command(REVEAL_FILE.with(path.clone())))
+                    .entry(MenuItem::new("既定のアプリで開く").command(OPEN_FILE.with(path.clone())))
+                    .separator()
+                    .entry(MenuItem::new("この行を除外").command(EXCLUDE_FILE.with(path)));
+                ctx.show_context_menu(menu, mouse.window_pos);
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+/// 経過時間の表示を更新する間隔（進捗の通知が途切れても時計を進める）
+const PROGRESS_TICK: Duration = Duration::from_secs(1);
+
+/// 表示中の割合を実際の割合へ近づける速さ（1 秒あたり、残りの差に対する割合）
+const PROGRESS_EASING: f64 = 12.0;
+
+/// シンプルな進捗バーウィジェット（割合・件数と、経過時間・残り時間の目安を表示）。
+/// 進捗の通知は間引いて届くため、バーの長さは表示中の割合から実際の割合へなめらかに伸ばす。
+pub struct ProgressBar {
+    timer: TimerToken,
+    shown: f64, // 表示中のバーの割合（0.0〜1.0）
+}
+
+impl Default for ProgressBar {
+    fn default() -> Self {
+        Self { timer: TimerToken::INVALID, shown: 0.0 }
+    }
+}
+
+/// 実際の進捗の割合（0.0〜1.0）
+fn progress_ratio(data: &AppState) -> f64 {
+    if data.conversion_total == 0 {
+        return 0.0;
+    }
+    (data.conversion_done as f64 / data.conversion_total as f64).min(1.0)
+}
+
+/// 進捗バーに表示する文字列。残り時間はここまでの処理速度から推定する（開始直後は推定しない）。
+fn progress_text(done: usize, total: usize, elapsed: Option<Duration>) -> String {
+    let percent = done as f64 / total as f64 * 100.0;
+    let mut text = format!("{:.0}% ({}/{})", percent, done, total);
+    if let Some(elapsed) = elapsed {
+        text.push_str(&format!("  経過 {}", format_duration(elapsed)));
+        let secs = elapsed.as_secs_f64();
+        if done > 0 && done < total && secs > 0.0 {
+            let remaining = (total - done) as f64 * secs / done as f64;
+            text.push_str(&format!("  残り 約 {}", format_duration(Duration::from_secs_f64(remaining))));
+        } else if done < total {
+            text.push_str("  残り 計算中");
+        }
+    }
+    text
+}
+
+/// 時間を `m:ss`（1 時間以上は `h:mm:ss`）で表す。
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
 
 impl Widget<crate::state::AppState> for ProgressBar {
-    fn event(&mut self, _ctx: &mut EventCtx, _event: &Event, _data: &mut crate::state::AppState, _env: &Env) {}
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut crate::state::AppState, _env: &Env) {
+        match event {
+            Event::Timer(token) if *token == self.timer && data.conversion_in_progress => {
+                ctx.request_paint();
+                self.timer = ctx.request_timer(PROGRESS_TICK);
+            }
+            Event::AnimFrame(interval) if data.conversion_in_progress => {
+                let target = progress_ratio(data);
+                let step = (*interval as f64 / 1e9 * PROGRESS_EASING).min(1.0);
+                self.shown += (target - self.shown) * step;
+                // 差が 1 ピクセルに満たない程度まで近づいたら止める
+                if (target - self.shown).abs() < 0.001 {
+                    self.shown = target;
+                } else {
+                    ctx.request_anim_frame();
+                }
+                ctx.request_paint();
+            }
+            _ => {}
+        }
+    }
     fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle, _data: &crate::state::AppState, _env: &Env) {}
     fn update(&mut self, ctx: &mut UpdateCtx, old_data: &crate::state::AppState, data: &crate::state::AppState, _env: &Env) {
         if !old_data.same(data) {
             ctx.request_paint();
         }
+        if data.conversion_in_progress && !old_data.conversion_in_progress {
+            self.timer = ctx.request_timer(PROGRESS_TICK);
+            self.shown = 0.0;
+        }
+        if data.conversion_in_progress && old_data.conversion_done != data.conversion_done {
+            ctx.request_anim_frame();
+        }
     }
     fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &druid::BoxConstraints, _data: &crate::state::AppState, _env: &Env) -> druid::kurbo::Size {
         let height = 20.0;
@@ -180,16 +338,16 @@ impl Widget<crate::state::AppState> for ProgressBar {
     }
     fn paint(&mut self, ctx: &mut PaintCtx, data: &crate::state::AppState, env: &Env) {
         if data.conversion_in_progress && data.conversion_total > 0 {
-            let progress = data.conversion_done as f64 / data.conversion_total as f64;
             let rect = ctx.size().to_rect();
-            let filled_rect = Rect::new(rect.x0, rect.y0, rect.x0 + rect.width() * progress, rect.y1);
+            let filled_rect = Rect::new(rect.x0, rect.y0, rect.x0 + rect.width() * self.shown, rect.y1);
             ctx.fill(rect, &env.get(druid::theme::BACKGROUND_LIGHT));
-            ctx.fill(filled_rect, &Color::rgb8(0, 128, 0));
-            let text = format!("{:.0}% ({}/{})", progress * 100.0, data.conversion_done, data.conversion_total);
+            ctx.fill(filled_rect, &env.get(PROGRESS_FILL_COLOR));
+            let elapsed = data.conversion_started.map(|started| started.elapsed());
+            let text = progress_text(data.conversion_done, data.conversion_total, elapsed);
             let text_layout = ctx
                 .text()
                 .new_text_layout(text)
-                .text_color(Color::WHITE)
+                .text_color(env.get(PROGRESS_TEXT_COLOR))
                 .build()
                 .unwrap();
             let text_size = text_layout.size();
@@ -199,4 +357,109 @@ impl Widget<crate::state::AppState> for ProgressBar {
     }
 }
 
+/// 仕切りの幅（ドラッグできる範囲）
+const DIVIDER_WIDTH: f64 = 10.0;
+const MIN_SPLIT_RATIO: f64 = 0.15;
+const MAX_SPLIT_RATIO: f64 = 0.85;
+
+/// 左パネルの幅の比率を、どちらのパネルも潰れない範囲に収める。
+pub fn clamp_split_ratio(ratio: f64) -> f64 {
+    if ratio.is_finite() {
+        ratio.clamp(MIN_SPLIT_RATIO, MAX_SPLIT_RATIO)
+    } else {
+        0.5
+    }
+}
+
+/// 左右 2 つのパネルを `AppState::split_ratio` の比率で並べ、間の仕切りをドラッグして比率を変えるウィジェット。
+/// ドラッグを終えると比率を設定ファイルに保存する。
+pub struct SplitPanel {
+    left: WidgetPod<AppState, Box<dyn Widget<AppState>>>,
+    right: WidgetPod<AppState, Box<dyn Widget<AppState>>>,
+}
+
+impl SplitPanel {
+    pub fn new(left: impl Widget<AppState> + 'static, right: impl Widget<AppState> + 'static) -> Self {
+        Self {
+            left: WidgetPod::new(Box::new(left)),
+            right: WidgetPod::new(Box::new(right)),
+        }
+    }
+
+    fn divider_rect(size: Size, ratio: f64) -> Rect {
+        let x = (size.width - DIVIDER_WIDTH).max(0.0) * ratio;
+        Rect::new(x, 0.0, x + DIVIDER_WIDTH, size.height)
+    }
+}
+
+impl Widget<AppState> for SplitPanel {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        let divider = Self::divider_rect(ctx.size(), data.split_ratio);
+        match event {
+            Event::MouseDown(mouse) if divider.contains(mouse.pos) => {
+                ctx.set_active(true);
+                ctx.set_handled();
+                return;
+            }
+            Event::MouseMove(mouse) if ctx.is_active() => {
+                let available = ctx.size().width - DIVIDER_WIDTH;
+                if available > 0.0 {
+                    data.split_ratio = clamp_split_ratio((mouse.pos.x - DIVIDER_WIDTH / 2.0) / available);
+                }
+                ctx.set_cursor(&Cursor::ResizeLeftRight);
+                ctx.set_handled();
+                return;
+            }
+            Event::MouseMove(mouse) => {
+                if divider.contains(mouse.pos) {
+                    ctx.set_cursor(&Cursor::ResizeLeftRight);
+                } else {
+                    ctx.clear_cursor();
+                }
+            }
+            Event::MouseUp(_) if ctx.is_active() => {
+                ctx.set_active(false);
+                save_setting("split_ratio", format!("{:.3}", data.split_ratio));
+                ctx.set_handled();
+                return;
+            }
+            _ => {}
+        }
+        self.left.event(ctx, event, data, env);
+        self.right.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, env: &Env) {
+        self.left.lifecycle(ctx, event, data, env);
+        self.right.lifecycle(ctx, event, data, env);
+    }
 
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
+        if old_data.split_ratio != data.split_ratio {
+            ctx.request_layout();
+        }
+        self.left.update(ctx, data, env);
+        self.right.update(ctx, data, env);
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &druid::BoxConstraints, data: &AppState, env: &Env) -> Size {
+        let size = bc.max();
+        let divider = Self::divider_rect(size, data.split_ratio);
+        let left_size = Size::new(divider.x0, size.height);
+        let right_size = Size::new((size.width - divider.x1).max(0.0), size.height);
+        self.left.layout(ctx, &druid::BoxConstraints::tight(left_size), data, env);
+        self.left.set_origin(ctx, Point::ORIGIN);
+        self.right.layout(ctx, &druid::BoxConstraints::tight(right_size), data, env);
+        self.right.set_origin(ctx, Point::new(divider.x1, 0.0));
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        self.left.paint(ctx, data, env);
+        self.right.paint(ctx, data, env);
+        let divider = Self::divider_rect(ctx.size(), data.split_ratio);
+        let center = divider.center().x;
+        let color = if ctx.is_active() || ctx.is_hot() { Color::grey(0.5) } else { Color::grey(0.8) };
+        ctx.fill(Rect::new(center - 1.0, 0.0, center + 1.0, divider.y1), &color);
+    }
+}